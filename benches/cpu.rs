@@ -0,0 +1,89 @@
+// Measures CPU throughput in instructions per second, with each way of
+// dispatching instructions: the table of specialized handlers that step()
+// uses, and the run-time decode it replaced. The CPU runs on flat RAM, so
+// that PPU and APU ticks don't swamp the difference.
+//
+// Run with `cargo bench`.
+
+extern crate nes;
+
+use nes::cpu::{self, Cpu, Dispatch, RamBus};
+use std::time::Instant;
+
+const INSTRUCTIONS: u64 = 50_000_000;
+const RUNS: usize = 5;
+
+// A loop mixing the common addressing modes and instruction types.
+const PROGRAM: &str = "
+define counter $10
+define ptr $20
+define ptr_hi $21
+define table $0300
+lda #$00
+sta ptr
+lda #$03
+sta ptr_hi
+start: ldx #$00
+loop: lda table,x
+clc
+adc #$03
+sta table,x
+inc counter
+lsr counter
+ldy counter
+lda (ptr),y
+and #$0F
+ora $11
+eor #$AA
+cmp #$10
+bne skip
+pha
+pla
+skip: inx
+bne loop
+jmp start
+";
+
+fn main() {
+    let program = cpu::assemble::assemble(PROGRAM, 0x8000).unwrap();
+    let decode = best_of_runs(&program, Dispatch::Decode);
+    let table = best_of_runs(&program, Dispatch::Table);
+
+    println!("best of {} runs, in millions of instructions/s:", RUNS);
+    println!("  decode: {:.2}", decode / 1e6);
+    println!(
+        "  table:  {:.2} ({:+.0}%)",
+        table / 1e6,
+        (table / decode - 1.0) * 100.0
+    );
+}
+
+// Returns the best instructions per second over RUNS runs.
+fn best_of_runs(program: &[u8], dispatch: Dispatch) -> f64 {
+    let mut best = 0.0;
+    for _ in 0..RUNS {
+        let mut cpu = Cpu::with_bus(RamBus::new());
+        cpu.dispatch = dispatch;
+        cpu.mem_write_buf(0x8000, program.to_vec());
+        cpu.regs.pc = 0x8000;
+
+        let start = Instant::now();
+        for _ in 0..INSTRUCTIONS {
+            cpu.step().unwrap();
+        }
+        let secs = start.elapsed().as_secs_f64();
+        let ips = INSTRUCTIONS as f64 / secs;
+        println!(
+            "{:?}: {} instructions ({} cycles) in {:.3}s: {:.2}M instructions/s",
+            dispatch,
+            INSTRUCTIONS,
+            cpu.cycles,
+            secs,
+            ips / 1e6
+        );
+        if ips > best {
+            best = ips;
+        }
+    }
+    best
+}
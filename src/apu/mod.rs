@@ -0,0 +1,887 @@
+// Reference: https://wiki.nesdev.com/w/index.php/APU
+//
+// This models the CPU-visible state of the APU: the channel registers, the
+// length counters, envelopes, sweeps and linear counter, and the frame counter
+// that clocks them. It does not generate samples; it exists so that programs
+// can drive the sound registers, and so that channel activity can be
+// inspected.
+
+use crate::savestate::{self, State};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// DMC output rates, in CPU cycles per bit (NTSC).
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+// Frame counter step boundaries, in CPU cycles since the last reset of the
+// sequencer.
+// https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+const FRAME_STEPS_4: [u32; 4] = [7457, 14913, 22371, 29829];
+const FRAME_STEPS_5: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Channel {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pulse1 => "pulse 1",
+            Self::Pulse2 => "pulse 2",
+            Self::Triangle => "triangle",
+            Self::Noise => "noise",
+            Self::Dmc => "dmc",
+        }
+    }
+}
+
+pub const CHANNELS: [Channel; 5] = [
+    Channel::Pulse1,
+    Channel::Pulse2,
+    Channel::Triangle,
+    Channel::Noise,
+    Channel::Dmc,
+];
+
+// A summary of what a channel is currently doing, suitable for display.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChannelStatus {
+    pub channel: Channel,
+
+    // Whether the channel is currently producing sound.
+    pub active: bool,
+
+    // Output volume, 0-15. The triangle channel has no volume control, so it
+    // reports full volume while active. The DMC reports the top four bits of
+    // its 7-bit output level.
+    pub volume: u8,
+
+    // Raw timer period. For the pulse channels, the output frequency is
+    // CPU_HZ / (16 * (period + 1)), and for the triangle channel it is
+    // CPU_HZ / (32 * (period + 1)). For the noise channel, this is the index
+    // into the noise period table, and for the DMC it is the rate index.
+    pub period: u16,
+}
+
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool, // shares a bit with the length counter halt flag
+    constant: bool,
+    volume: u8, // also used as the divider period
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write_control(&mut self, v: u8) {
+        self.looping = v & 0b0010_0000 != 0;
+        self.constant = v & 0b0001_0000 != 0;
+        self.volume = v & 0b1111;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.looping {
+            self.decay = 15;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(index >> 3) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+}
+
+struct Pulse {
+    // Pulse 1 negates with ones' complement, pulse 2 with two's complement.
+    ones_complement: bool,
+    duty: u8,
+    envelope: Envelope,
+    length: LengthCounter,
+    period: u16,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    fn new(ones_complement: bool) -> Pulse {
+        Pulse {
+            ones_complement,
+            duty: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            period: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+        }
+    }
+
+    fn write(&mut self, reg: u16, v: u8) {
+        match reg {
+            0 => {
+                self.duty = v >> 6;
+                self.length.halt = v & 0b0010_0000 != 0;
+                self.envelope.write_control(v);
+            }
+            1 => {
+                self.sweep_enabled = v & 0b1000_0000 != 0;
+                self.sweep_period = (v >> 4) & 0b111;
+                self.sweep_negate = v & 0b1000 != 0;
+                self.sweep_shift = v & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0xFF00) | v as u16,
+            3 => {
+                self.period = (self.period & 0xFF) | ((v as u16 & 0b111) << 8);
+                self.length.load(v);
+                self.envelope.start = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let delta = self.period >> self.sweep_shift;
+        if !self.sweep_negate {
+            return self.period + delta;
+        }
+
+        let delta = if self.ones_complement {
+            delta + 1
+        } else {
+            delta
+        };
+        self.period.saturating_sub(delta)
+    }
+
+    // The sweep unit mutes the channel if the current period is too small, or
+    // if the target period overflows, regardless of whether it is enabled.
+    fn sweep_muting(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0
+            && self.sweep_enabled
+            && self.sweep_shift > 0
+            && !self.sweep_muting()
+        {
+            self.period = self.sweep_target();
+        }
+
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn status(&self, channel: Channel) -> ChannelStatus {
+        let volume = self.envelope.output();
+        ChannelStatus {
+            channel,
+            active: self.length.value > 0 && !self.sweep_muting() && volume > 0,
+            volume,
+            period: self.period,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Triangle {
+    length: LengthCounter, // halt flag doubles as linear counter control
+    linear_reload_value: u8,
+    linear_reload: bool,
+    linear: u8,
+    period: u16,
+}
+
+impl Triangle {
+    fn write(&mut self, reg: u16, v: u8) {
+        match reg {
+            0 => {
+                self.length.halt = v & 0b1000_0000 != 0;
+                self.linear_reload_value = v & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.period = (self.period & 0xFF00) | v as u16,
+            3 => {
+                self.period = (self.period & 0xFF) | ((v as u16 & 0b111) << 8);
+                self.length.load(v);
+                self.linear_reload = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_reload_value;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+
+        if !self.length.halt {
+            self.linear_reload = false;
+        }
+    }
+
+    fn status(&self) -> ChannelStatus {
+        // Periods below 2 produce ultrasonic output, which is effectively
+        // silence.
+        let active = self.length.value > 0 && self.linear > 0 && self.period >= 2;
+        ChannelStatus {
+            channel: Channel::Triangle,
+            active,
+            volume: if active { 15 } else { 0 },
+            period: self.period,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Noise {
+    envelope: Envelope,
+    length: LengthCounter,
+    short_mode: bool,
+    period: u8,
+}
+
+impl Noise {
+    fn write(&mut self, reg: u16, v: u8) {
+        match reg {
+            0 => {
+                self.length.halt = v & 0b0010_0000 != 0;
+                self.envelope.write_control(v);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = v & 0b1000_0000 != 0;
+                self.period = v & 0b1111;
+            }
+            3 => {
+                self.length.load(v);
+                self.envelope.start = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn status(&self) -> ChannelStatus {
+        let volume = self.envelope.output();
+        ChannelStatus {
+            channel: Channel::Noise,
+            active: self.length.value > 0 && volume > 0,
+            volume,
+            period: self.period as u16,
+        }
+    }
+}
+
+// The DMC fetches sample bytes from CPU memory. Since the APU has no access to
+// the CPU bus, sample bytes are consumed at the correct rate, but their
+// contents are not decoded; the output level only reflects $4011 writes.
+#[derive(Default)]
+struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    rate: u8,
+    level: u8,
+    sample_addr: u16,
+    sample_len: u16,
+    bytes_remaining: u16,
+    byte_timer: u32,
+    irq: bool,
+}
+
+impl Dmc {
+    fn write(&mut self, reg: u16, v: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = v & 0b1000_0000 != 0;
+                self.looping = v & 0b0100_0000 != 0;
+                self.rate = v & 0b1111;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = v & 0b0111_1111,
+            2 => self.sample_addr = 0xC000 + (v as u16 * 64),
+            3 => self.sample_len = (v as u16 * 16) + 1,
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.bytes_remaining = self.sample_len;
+        self.byte_timer = 8 * DMC_RATE_TABLE[self.rate as usize] as u32;
+    }
+
+    fn tick(&mut self) {
+        if self.bytes_remaining == 0 {
+            return;
+        }
+
+        self.byte_timer -= 1;
+        if self.byte_timer > 0 {
+            return;
+        }
+
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining > 0 {
+            self.byte_timer = 8 * DMC_RATE_TABLE[self.rate as usize] as u32;
+        } else if self.looping {
+            self.restart();
+        } else if self.irq_enabled {
+            self.irq = true;
+        }
+    }
+
+    fn status(&self) -> ChannelStatus {
+        ChannelStatus {
+            channel: Channel::Dmc,
+            active: self.bytes_remaining > 0 || self.level > 0,
+            volume: self.level >> 3,
+            period: self.rate as u16,
+        }
+    }
+}
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+}
+
+impl Default for Apu {
+    fn default() -> Apu {
+        Apu::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+        }
+    }
+
+    // Handles writes to $4000-$4013, $4015 and $4017.
+    pub fn write(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, v),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, v),
+            0x4008..=0x400B => self.triangle.write(addr - 0x4008, v),
+            0x400C..=0x400F => self.noise.write(addr - 0x400C, v),
+            0x4010..=0x4013 => self.dmc.write(addr - 0x4010, v),
+            0x4015 => {
+                self.pulse1.length.set_enabled(v & 0b0000_0001 != 0);
+                self.pulse2.length.set_enabled(v & 0b0000_0010 != 0);
+                self.triangle.length.set_enabled(v & 0b0000_0100 != 0);
+                self.noise.length.set_enabled(v & 0b0000_1000 != 0);
+                self.dmc.set_enabled(v & 0b0001_0000 != 0);
+                self.dmc.irq = false;
+            }
+            0x4017 => {
+                self.five_step = v & 0b1000_0000 != 0;
+                self.irq_inhibit = v & 0b0100_0000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => panic!("invalid APU register: {:04X}", addr),
+        }
+    }
+
+    // Handles reads from $4015. Reading clears the frame interrupt flag.
+    pub fn read_status(&mut self) -> u8 {
+        let v = self.peek_status();
+        self.frame_irq = false;
+        v
+    }
+
+    // Returns what a read from $4015 would, without clearing anything.
+    pub fn peek_status(&self) -> u8 {
+        let mut v = 0;
+        if self.pulse1.length.value > 0 {
+            v |= 0b0000_0001;
+        }
+        if self.pulse2.length.value > 0 {
+            v |= 0b0000_0010;
+        }
+        if self.triangle.length.value > 0 {
+            v |= 0b0000_0100;
+        }
+        if self.noise.length.value > 0 {
+            v |= 0b0000_1000;
+        }
+        if self.dmc.bytes_remaining > 0 {
+            v |= 0b0001_0000;
+        }
+        if self.frame_irq {
+            v |= 0b0100_0000;
+        }
+        if self.dmc.irq {
+            v |= 0b1000_0000;
+        }
+        v
+    }
+
+    // Whether the APU is asserting the CPU's IRQ line.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    // Advances the APU by one CPU cycle.
+    pub fn tick(&mut self) {
+        self.dmc.tick();
+
+        self.frame_cycle += 1;
+        let steps: &[u32] = if self.five_step {
+            &FRAME_STEPS_5
+        } else {
+            &FRAME_STEPS_4
+        };
+
+        let step = match steps.iter().position(|&c| c == self.frame_cycle) {
+            Some(step) => step,
+            None => return,
+        };
+
+        // The 5-step sequence has an empty fourth step.
+        if !(self.five_step && step == 3) {
+            self.clock_quarter_frame();
+        }
+
+        if step == 1 || step == steps.len() - 1 {
+            self.clock_half_frame();
+        }
+
+        if step == steps.len() - 1 {
+            self.frame_cycle = 0;
+            if !self.five_step && !self.irq_inhibit {
+                self.frame_irq = true;
+            }
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    pub fn channel_status(&self, channel: Channel) -> ChannelStatus {
+        match channel {
+            Channel::Pulse1 => self.pulse1.status(Channel::Pulse1),
+            Channel::Pulse2 => self.pulse2.status(Channel::Pulse2),
+            Channel::Triangle => self.triangle.status(),
+            Channel::Noise => self.noise.status(),
+            Channel::Dmc => self.dmc.status(),
+        }
+    }
+}
+
+impl State for Envelope {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bool(self.start);
+        w.bool(self.looping);
+        w.bool(self.constant);
+        w.u8(self.volume);
+        w.u8(self.divider);
+        w.u8(self.decay);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.start = r.bool()?;
+        self.looping = r.bool()?;
+        self.constant = r.bool()?;
+        self.volume = r.u8()?;
+        self.divider = r.u8()?;
+        self.decay = r.u8()?;
+        Ok(())
+    }
+}
+
+impl State for LengthCounter {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bool(self.enabled);
+        w.bool(self.halt);
+        w.u8(self.value);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.enabled = r.bool()?;
+        self.halt = r.bool()?;
+        self.value = r.u8()?;
+        Ok(())
+    }
+}
+
+impl State for Pulse {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.u8(self.duty);
+        self.envelope.save_state(w);
+        self.length.save_state(w);
+        w.u16(self.period);
+        w.bool(self.sweep_enabled);
+        w.u8(self.sweep_period);
+        w.bool(self.sweep_negate);
+        w.u8(self.sweep_shift);
+        w.u8(self.sweep_divider);
+        w.bool(self.sweep_reload);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.duty = r.u8()?;
+        self.envelope.load_state(r)?;
+        self.length.load_state(r)?;
+        self.period = r.u16()?;
+        self.sweep_enabled = r.bool()?;
+        self.sweep_period = r.u8()?;
+        self.sweep_negate = r.bool()?;
+        self.sweep_shift = r.u8()?;
+        self.sweep_divider = r.u8()?;
+        self.sweep_reload = r.bool()?;
+        Ok(())
+    }
+}
+
+impl State for Triangle {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        self.length.save_state(w);
+        w.u8(self.linear_reload_value);
+        w.bool(self.linear_reload);
+        w.u8(self.linear);
+        w.u16(self.period);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.length.load_state(r)?;
+        self.linear_reload_value = r.u8()?;
+        self.linear_reload = r.bool()?;
+        self.linear = r.u8()?;
+        self.period = r.u16()?;
+        Ok(())
+    }
+}
+
+impl State for Noise {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        self.envelope.save_state(w);
+        self.length.save_state(w);
+        w.bool(self.short_mode);
+        w.u8(self.period);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.envelope.load_state(r)?;
+        self.length.load_state(r)?;
+        self.short_mode = r.bool()?;
+        self.period = r.u8()?;
+        Ok(())
+    }
+}
+
+impl State for Dmc {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bool(self.irq_enabled);
+        w.bool(self.looping);
+        w.u8(self.rate);
+        w.u8(self.level);
+        w.u16(self.sample_addr);
+        w.u16(self.sample_len);
+        w.u16(self.bytes_remaining);
+        w.u32(self.byte_timer);
+        w.bool(self.irq);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.irq_enabled = r.bool()?;
+        self.looping = r.bool()?;
+        self.rate = r.u8()?;
+        self.level = r.u8()?;
+        self.sample_addr = r.u16()?;
+        self.sample_len = r.u16()?;
+        self.bytes_remaining = r.u16()?;
+        self.byte_timer = r.u32()?;
+        self.irq = r.bool()?;
+        if self.rate as usize >= DMC_RATE_TABLE.len()
+            || (self.bytes_remaining > 0 && self.byte_timer == 0)
+        {
+            return Err(savestate::Error::Invalid("DMC timer"));
+        }
+        Ok(())
+    }
+}
+
+impl State for Apu {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        w.bool(self.five_step);
+        w.bool(self.irq_inhibit);
+        w.bool(self.frame_irq);
+        w.u32(self.frame_cycle);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.five_step = r.bool()?;
+        self.irq_inhibit = r.bool()?;
+        self.frame_irq = r.bool()?;
+        self.frame_cycle = r.u32()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_length_counter() {
+    let mut apu = Apu::new();
+    apu.write(0x4017, 0b0100_0000); // inhibit frame IRQ
+
+    // loading while disabled has no effect
+    apu.write(0x4003, 0b0000_1000);
+    assert_eq!(apu.read_status(), 0);
+
+    // index 1 loads 254
+    apu.write(0x4015, 0b0000_0001);
+    apu.write(0x4003, 0b0000_1000);
+    assert_eq!(apu.pulse1.length.value, 254);
+    assert_eq!(apu.read_status(), 0b0000_0001);
+
+    // clocked twice per 4-step sequence
+    for _ in 0..FRAME_STEPS_4[3] {
+        apu.tick();
+    }
+    assert_eq!(apu.pulse1.length.value, 252);
+
+    // halted counters are not clocked
+    apu.write(0x4000, 0b0010_0000);
+    for _ in 0..FRAME_STEPS_4[3] {
+        apu.tick();
+    }
+    assert_eq!(apu.pulse1.length.value, 252);
+
+    // disabling clears the counter
+    apu.write(0x4015, 0);
+    assert_eq!(apu.read_status(), 0);
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut apu = Apu::new();
+    for _ in 0..FRAME_STEPS_4[3] {
+        apu.tick();
+    }
+    assert!(apu.irq());
+
+    // reading status acknowledges the interrupt, but peeking it doesn't
+    assert_eq!(apu.peek_status(), 0b0100_0000);
+    assert!(apu.irq());
+    assert_eq!(apu.read_status(), 0b0100_0000);
+    assert!(!apu.irq());
+
+    // inhibited
+    apu.write(0x4017, 0b0100_0000);
+    for _ in 0..FRAME_STEPS_4[3] {
+        apu.tick();
+    }
+    assert!(!apu.irq());
+
+    // no interrupt in 5-step mode
+    apu.write(0x4017, 0b1000_0000);
+    for _ in 0..FRAME_STEPS_5[4] {
+        apu.tick();
+    }
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_envelope() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0b0000_1000);
+
+    // constant volume
+    apu.write(0x400C, 0b0001_0111);
+    apu.write(0x400F, 0b0000_1000);
+    assert_eq!(apu.channel_status(Channel::Noise).volume, 7);
+
+    // decay starts at 15 on the first quarter frame, and decreases once per
+    // (period + 1) quarter frames thereafter
+    apu.write(0x400C, 0b0000_0000);
+    apu.write(0x400F, 0b0000_1000);
+    assert_eq!(apu.channel_status(Channel::Noise).volume, 0);
+    apu.clock_quarter_frame();
+    assert_eq!(apu.channel_status(Channel::Noise).volume, 15);
+    apu.clock_quarter_frame();
+    assert_eq!(apu.channel_status(Channel::Noise).volume, 14);
+}
+
+#[test]
+fn test_channel_status() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0b0000_0101);
+
+    // pulse 1: constant volume 15, period 0x1FD
+    apu.write(0x4000, 0b1011_1111);
+    apu.write(0x4002, 0xFD);
+    apu.write(0x4003, 0b0000_1001);
+    assert_eq!(
+        apu.channel_status(Channel::Pulse1),
+        ChannelStatus {
+            channel: Channel::Pulse1,
+            active: true,
+            volume: 15,
+            period: 0x1FD,
+        }
+    );
+
+    // pulse periods below 8 are muted
+    apu.write(0x4002, 0x07);
+    apu.write(0x4003, 0b0000_1000);
+    assert!(!apu.channel_status(Channel::Pulse1).active);
+
+    // pulse 2 is not enabled
+    apu.write(0x4004, 0b1011_1111);
+    apu.write(0x4006, 0xFD);
+    apu.write(0x4007, 0b0000_1001);
+    assert!(!apu.channel_status(Channel::Pulse2).active);
+
+    // the triangle requires the linear counter to be reloaded on a quarter
+    // frame
+    apu.write(0x4008, 0b0111_1111);
+    apu.write(0x400A, 0x40);
+    apu.write(0x400B, 0b0000_1000);
+    assert!(!apu.channel_status(Channel::Triangle).active);
+    apu.clock_quarter_frame();
+    assert!(apu.channel_status(Channel::Triangle).active);
+}
+
+#[test]
+fn test_dmc() {
+    let mut apu = Apu::new();
+    apu.write(0x4010, 0b1000_1111); // IRQ, fastest rate
+    apu.write(0x4013, 0); // 1-byte sample
+    apu.write(0x4015, 0b0001_0000);
+    assert_eq!(apu.read_status(), 0b0001_0000);
+
+    for _ in 0..(8 * DMC_RATE_TABLE[15]) {
+        apu.tick();
+    }
+    assert_eq!(apu.read_status(), 0b1000_0000);
+    assert!(apu.irq());
+
+    // writing $4015 acknowledges the interrupt
+    apu.write(0x4015, 0);
+    assert!(!apu.irq());
+}
@@ -14,6 +14,10 @@ pub enum AddressMode {
     Indirect,
     IndirectX, // aka "indexed indirect"
     IndirectY, // aka "indirect indexed"
+
+    // 65C02 only
+    ZeroPageIndirect,
+    AbsoluteIndirectX, // JMP ($xxxx,X)
 }
 
 impl AddressMode {
@@ -32,6 +36,8 @@ impl AddressMode {
             Self::Indirect => 2,
             Self::IndirectX => 1,
             Self::IndirectY => 1,
+            Self::ZeroPageIndirect => 1,
+            Self::AbsoluteIndirectX => 2,
         }
     }
 }
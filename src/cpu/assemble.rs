@@ -1,5 +1,6 @@
 use super::address_mode::AddressMode;
 use super::opcode;
+use super::state::Variant;
 use crate::math;
 use regex::Regex;
 use std::collections::HashMap;
@@ -23,8 +24,8 @@ enum Operand<'a> {
     Immediate(Opval<'a>), // address modes: immediate
     IndexX(Opval<'a>),    // address modes: zero page x, absolute x
     IndexY(Opval<'a>),    // address modes: zero page y, absolute y
-    Indirect(Opval<'a>),  // address modes: indirect
-    IndirectX(Opval<'a>), // address modes: indirect x
+    Indirect(Opval<'a>),  // address modes: indirect, zero page indirect
+    IndirectX(Opval<'a>), // address modes: indirect x, absolute indirect x
     IndirectY(Opval<'a>), // address modes: indirect y
     Direct(Opval<'a>),    // address modes: absolute, relative, zero page
 }
@@ -57,7 +58,7 @@ impl Numeric {
     fn to_bytes(self) -> Vec<u8> {
         match self {
             Self::Byte(n) => vec![n],
-            Self::Word(n) => math::u16_to_bytes_le(n).iter().cloned().collect(),
+            Self::Word(n) => math::u16_to_bytes_le(n).to_vec(),
         }
     }
 
@@ -67,11 +68,13 @@ impl Numeric {
             Self::Word(n) => Some(n),
         }
     }
+}
 
-    fn to_string(self) -> String {
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Byte(n) => format!("${:02x}", n),
-            Self::Word(n) => format!("${:04x}", n),
+            Self::Byte(n) => write!(f, "${:02x}", n),
+            Self::Word(n) => write!(f, "${:04x}", n),
         }
     }
 }
@@ -84,7 +87,7 @@ struct MapSymbolTable<'a>(HashMap<&'a str, Numeric>);
 
 impl<'a> SymbolTable for MapSymbolTable<'a> {
     fn get(&self, symbol: &str) -> Option<Numeric> {
-        self.0.get(symbol).map(|n| *n)
+        self.0.get(symbol).copied()
     }
 }
 
@@ -126,7 +129,7 @@ impl fmt::Display for Error {
                 "no valid address mode for opcode type {:?} and operand {}",
                 opcode_type, operand_str
             ),
-            Self::BranchLabelTooFar(symbol) => write!(f, "label too far from branch: {}", symbol),
+            Self::BranchLabelTooFar(target) => write!(f, "target too far from branch: {}", target),
             Self::LiteralInBranch(literal) => {
                 write!(f, "literal value used in branch: {}", literal)
             }
@@ -289,78 +292,88 @@ fn parse_opval<'a>(src: &'a str) -> Result<Opval<'a>, Error> {
 }
 
 fn infer_address_mode(
+    variant: Variant,
     opcode_type: opcode::Type,
     operand: &Operand,
     symbols: &dyn SymbolTable,
 ) -> Result<AddressMode, Error> {
     match operand {
         Operand::None => {
-            if opcode_type.compatible_with(AddressMode::Implicit) {
+            if opcode_type.compatible_with(variant, AddressMode::Implicit) {
                 return Ok(AddressMode::Implicit);
             }
-            if opcode_type.compatible_with(AddressMode::Accumulator) {
+            if opcode_type.compatible_with(variant, AddressMode::Accumulator) {
                 return Ok(AddressMode::Accumulator);
             }
         }
         Operand::Immediate(_) => {
-            if opcode_type.compatible_with(AddressMode::Immediate) {
+            if opcode_type.compatible_with(variant, AddressMode::Immediate) {
                 return Ok(AddressMode::Immediate);
             }
         }
         Operand::IndexX(opval) => match opval.to_numeric(symbols)? {
             Numeric::Byte(_) => {
-                if opcode_type.compatible_with(AddressMode::ZeroPageX) {
+                if opcode_type.compatible_with(variant, AddressMode::ZeroPageX) {
                     return Ok(AddressMode::ZeroPageX);
                 }
             }
             Numeric::Word(_) => {
-                if opcode_type.compatible_with(AddressMode::AbsoluteX) {
+                if opcode_type.compatible_with(variant, AddressMode::AbsoluteX) {
                     return Ok(AddressMode::AbsoluteX);
                 }
             }
         },
         Operand::IndexY(opval) => match opval.to_numeric(symbols)? {
             Numeric::Byte(_) => {
-                if opcode_type.compatible_with(AddressMode::ZeroPageY) {
+                if opcode_type.compatible_with(variant, AddressMode::ZeroPageY) {
                     return Ok(AddressMode::ZeroPageY);
                 }
             }
             Numeric::Word(_) => {
-                if opcode_type.compatible_with(AddressMode::AbsoluteY) {
+                if opcode_type.compatible_with(variant, AddressMode::AbsoluteY) {
                     return Ok(AddressMode::AbsoluteY);
                 }
             }
         },
         Operand::Indirect(_) => {
-            if opcode_type.compatible_with(AddressMode::Indirect) {
+            if opcode_type.compatible_with(variant, AddressMode::Indirect) {
                 return Ok(AddressMode::Indirect);
             }
+            if opcode_type.compatible_with(variant, AddressMode::ZeroPageIndirect) {
+                return Ok(AddressMode::ZeroPageIndirect);
+            }
         }
         Operand::IndirectX(_) => {
-            if opcode_type.compatible_with(AddressMode::IndirectX) {
+            if opcode_type.compatible_with(variant, AddressMode::IndirectX) {
                 return Ok(AddressMode::IndirectX);
             }
+            if opcode_type.compatible_with(variant, AddressMode::AbsoluteIndirectX) {
+                return Ok(AddressMode::AbsoluteIndirectX);
+            }
         }
         Operand::IndirectY(_) => {
-            if opcode_type.compatible_with(AddressMode::IndirectY) {
+            if opcode_type.compatible_with(variant, AddressMode::IndirectY) {
                 return Ok(AddressMode::IndirectY);
             }
         }
         Operand::Direct(opval) => {
             // branches
-            if opcode_type.compatible_with(AddressMode::Relative) {
+            if opcode_type.compatible_with(variant, AddressMode::Relative) {
                 return Ok(AddressMode::Relative);
             }
 
             // jumps
-            if opcode_type.is_jump() {
+            if opcode_type.is_jump(variant) {
                 return Ok(AddressMode::Absolute);
             }
 
             // literals and references
-            match opval.to_numeric(symbols)? {
-                Numeric::Byte(_) => return Ok(AddressMode::ZeroPage),
-                Numeric::Word(_) => return Ok(AddressMode::Absolute),
+            let addr_mode = match opval.to_numeric(symbols)? {
+                Numeric::Byte(_) => AddressMode::ZeroPage,
+                Numeric::Word(_) => AddressMode::Absolute,
+            };
+            if opcode_type.compatible_with(variant, addr_mode) {
+                return Ok(addr_mode);
             }
         }
     }
@@ -373,6 +386,12 @@ fn infer_address_mode(
 
 // TODO: return an actual error message.
 pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
+    assemble_for(src, base_reloc_addr, Variant::Ricoh2A03)
+}
+
+// Assembles for a particular CPU variant, which determines the available
+// instructions and address modes.
+pub fn assemble_for(src: &str, base_reloc_addr: u16, variant: Variant) -> Result<Vec<u8>, Error> {
     // collect statements
     let mut statements = Vec::new();
     for line in src.lines() {
@@ -384,7 +403,7 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
             None => line,
         };
 
-        if line.len() == 0 {
+        if line.is_empty() {
             continue;
         }
 
@@ -428,7 +447,12 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
     // and label addresses.
     let mut address_modes = Vec::new();
     for (&opcode_type, operand) in instructions.iter() {
-        address_modes.push(infer_address_mode(opcode_type, &operand, &def_symbols)?);
+        address_modes.push(infer_address_mode(
+            variant,
+            opcode_type,
+            operand,
+            &def_symbols,
+        )?);
     }
 
     // generate instruction addresses
@@ -438,7 +462,7 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
             (Vec::new(), base_reloc_addr),
             |(mut accum, next), addr_mode| {
                 accum.push(next);
-                return (accum, next + 1 + addr_mode.operand_size() as u16);
+                (accum, next + 1 + addr_mode.operand_size() as u16)
             },
         )
         .0;
@@ -464,37 +488,34 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
 
         let num_opval = match addr_mode {
             // Relative address modes treat reference operands as labels, and
-            // the encoded version should be a signed delta value.
+            // word literals as absolute targets. The encoded version should
+            // be a signed delta value.
             AddressMode::Relative => {
-                match operand {
-                    Operand::Direct(opval) => {
-                        match opval {
-                            Opval::Reference(s) => {
-                                match label_symbols.get(s) {
-                                    Some(dest) => {
-                                        // The displacement operand is calculated
-                                        // relative to the next instruction.
-                                        let src = instruction_addrs[i] + 2;
-                                        let delta = (dest.to_u16().unwrap() as i64) - (src as i64);
-                                        if delta < -128 || 127 < delta {
-                                            return Err(Error::BranchLabelTooFar(s.to_string()));
-                                        }
-
-                                        Some(Numeric::Byte((delta as i8).to_le_bytes()[0]))
-                                    }
-                                    None => return Err(Error::SymbolNotFound(s.to_string())),
-                                }
-                            }
-                            Opval::Literal(n) => {
-                                return Err(Error::LiteralInBranch(n.to_string()));
-                            }
-                        }
-                    }
+                let (dest, name) = match operand {
+                    Operand::Direct(Opval::Reference(s)) => match label_symbols.get(s) {
+                        Some(dest) => (dest.to_u16().unwrap(), s.to_string()),
+                        None => return Err(Error::SymbolNotFound(s.to_string())),
+                    },
+                    Operand::Direct(Opval::Literal(n)) => match n.to_u16() {
+                        Some(dest) => (dest, n.to_string()),
+                        // a byte could be mistaken for the delta itself
+                        None => return Err(Error::LiteralInBranch(n.to_string())),
+                    },
                     _ => panic!(
                         "invalid operand {:?} for opcode type {:?}",
                         operand, opcode_type
                     ),
+                };
+
+                // The displacement operand is calculated relative to the next
+                // instruction.
+                let src = instruction_addrs[i] + 2;
+                let delta = (dest as i64) - (src as i64);
+                if !(-128..=127).contains(&delta) {
+                    return Err(Error::BranchLabelTooFar(name));
                 }
+
+                Some(Numeric::Byte((delta as i8).to_le_bytes()[0]))
             }
             _ => match operand {
                 Operand::None => None,
@@ -506,7 +527,7 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
                 | Operand::IndirectY(opval) => Some(opval.to_numeric(&def_symbols)?),
                 Operand::Direct(opval) => {
                     // Jumps can use labels.
-                    let symbols: &dyn SymbolTable = if opcode_type.is_jump() {
+                    let symbols: &dyn SymbolTable = if opcode_type.is_jump(variant) {
                         &all_symbols
                     } else {
                         &def_symbols
@@ -530,7 +551,7 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
         }
 
         // Write opcode
-        let oc = opcode::encode(opcode_type, addr_mode).unwrap();
+        let oc = opcode::encode(variant, opcode_type, addr_mode).unwrap();
         code.push(oc);
 
         // Write operand
@@ -539,7 +560,7 @@ pub fn assemble(src: &str, base_reloc_addr: u16) -> Result<Vec<u8>, Error> {
         }
     }
 
-    return Ok(code);
+    Ok(code)
 }
 
 #[test]
@@ -607,6 +628,12 @@ label_c: ; terminal label
         ]
     );
 
+    // branches to absolute addresses
+    assert_eq!(
+        assemble("nop\nbne $0600\nbeq $0684", 0x600).unwrap(),
+        vec![0xEA, 0xD0, 0xFD, 0xF0, 0x7F]
+    );
+
     // errors
     assert_eq!(
         assemble("def x y ; no such statement structure", 0),
@@ -640,6 +667,10 @@ label_c: ; terminal label
         assemble(&("a:\n".to_string() + &"nop\n".repeat(127) + "beq a"), 0),
         Err(Error::BranchLabelTooFar(String::from("a")))
     );
+    assert_eq!(
+        assemble("beq $1000", 0),
+        Err(Error::BranchLabelTooFar("$1000".to_string()))
+    );
     assert_eq!(
         assemble("beq $01", 0),
         Err(Error::LiteralInBranch("$01".to_string()))
@@ -653,3 +684,46 @@ label_c: ; terminal label
         ))
     );
 }
+
+#[test]
+fn test_cmos() {
+    let asm = "
+loop: bra loop
+phx
+ply
+stz $10
+stz $1000,x
+tsb $10
+trb $1000
+lda ($10)
+sta ($10)
+bit #$01
+inc
+dec
+jmp ($1000,x)
+jmp ($10FF)
+";
+    assert_eq!(
+        assemble_for(asm, 0x600, Variant::Cmos65C02).unwrap(),
+        vec![
+            0x80, 0xFE, 0xDA, 0x7A, 0x64, 0x10, 0x9E, 0x00, 0x10, 0x04, 0x10, 0x1C, 0x00, 0x10,
+            0xB2, 0x10, 0x92, 0x10, 0x89, 0x01, 0x1A, 0x3A, 0x7C, 0x00, 0x10, 0x6C, 0xFF, 0x10
+        ]
+    );
+
+    // not available on the NES
+    assert_eq!(
+        assemble("stz $10", 0),
+        Err(Error::NoValidAddressMode(
+            opcode::Type::Stz,
+            String::from("Direct(Literal(Byte(16)))")
+        ))
+    );
+    assert_eq!(
+        assemble("lda ($10)", 0),
+        Err(Error::NoValidAddressMode(
+            opcode::Type::Lda,
+            String::from("Indirect(Literal(Byte(16)))")
+        ))
+    );
+}
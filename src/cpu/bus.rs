@@ -0,0 +1,335 @@
+use super::super::apu;
+use super::super::input;
+use super::super::mapper;
+use super::super::ppu;
+use super::error::{Access, CpuError};
+use crate::savestate::{self, State};
+
+const RAM_SIZE: usize = 1 << 11;
+
+// A read or write that a program made, for debuggers to inspect.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BusAccess {
+    pub addr: u16,
+    pub access: Access,
+    pub value: u8,
+}
+
+// Everything on the other side of the CPU's address and data pins. The CPU
+// calls tick() once per cycle, before that cycle's read or write, so that
+// devices with side effects see accesses at the right time.
+pub trait Bus {
+    // Buses may reject reads and writes that nothing responds to with
+    // CpuError::Unmapped. The NES doesn't: reads there see open bus, and
+    // writes are ignored.
+    fn read(&mut self, addr: u16) -> Result<u8, CpuError>;
+
+    fn write(&mut self, addr: u16, v: u8) -> Result<(), CpuError>;
+
+    fn tick(&mut self) {}
+
+    // Cycles for which the bus has held the CPU off since the last call, e.g.
+    // for DMA. The bus ticks itself through them; the CPU only counts them.
+    fn take_stall_cycles(&mut self) -> u64 {
+        0
+    }
+
+    // Interrupt lines. NMI is edge-triggered, so taking it acknowledges it.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Reads without side effects, for traces and other debugging output.
+    // Returns None where that isn't possible.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    // The PPU's scanline and dot, for systems that have one.
+    fn ppu_position(&self) -> Option<(usize, usize)> {
+        None
+    }
+}
+
+// The NES CPU memory map.
+// Reference: https://wiki.nesdev.com/w/index.php/CPU_memory_map
+pub struct NesBus {
+    pub ram: [u8; RAM_SIZE],
+    pub ppu: ppu::Ppu,
+    pub apu: apu::Apu,
+    pub input: input::Input,
+    pub mapper_prg: Box<dyn mapper::Prg>,
+
+    // The last value read from or written to the data bus. Reads that
+    // nothing responds to see this value, since the bus lines hold their
+    // charge for a while.
+    // https://wiki.nesdev.com/w/index.php/Open_bus_behavior
+    pub data_bus: u8,
+
+    odd_cycle: bool,
+    stall_cycles: u64,
+}
+
+impl NesBus {
+    pub fn new(mapper_prg: Box<dyn mapper::Prg>, mapper_chr: Box<dyn mapper::Ppu>) -> NesBus {
+        NesBus {
+            ram: [0; RAM_SIZE],
+            ppu: ppu::Ppu::new(mapper_chr),
+            apu: apu::Apu::new(),
+            input: input::Input::new(),
+            mapper_prg,
+            data_bus: 0,
+            odd_cycle: false,
+            stall_cycles: 0,
+        }
+    }
+
+    fn read_byte(&mut self, addr: u16) -> u8 {
+        let v = match addr {
+            0..=0x07FF => self.ram[addr as usize],
+            0x0800..=0x0FFF => self.ram[addr as usize - 0x0800],
+            0x1000..=0x17FF => self.ram[addr as usize - 0x1000],
+            0x1800..=0x1FFF => self.ram[addr as usize - 0x1800],
+            0x2000..=0x3FFF => self.ppu.read_register(addr % 8),
+            // bit 5 is not driven
+            0x4015 => self.apu.read_status() | (self.data_bus & 0b0010_0000),
+            0x4016 => self.input.read(0, &self.ppu, self.data_bus),
+            0x4017 => self.input.read(1, &self.ppu, self.data_bus),
+            0x4020..=0xFFFF => self.mapper_prg.read(addr).unwrap_or(self.data_bus),
+            // Write-only APU registers, and CPU test mode registers, which are
+            // disabled on the NES. The value is usually the high byte of the
+            // address, which was the last byte of the instruction fetched.
+            0x4000..=0x4014 | 0x4018..=0x401F => self.data_bus,
+        };
+        self.data_bus = v;
+        v
+    }
+
+    // Writing $XX will upload 256 bytes of data from CPU page $XX00-$XXFF to
+    // the internal PPU OAM, starting at OAMADDR. This page is typically
+    // located in internal RAM, commonly $0200-$02FF, but cartridge RAM or ROM
+    // can be used as well. The CPU is suspended for 513 cycles, plus one if
+    // the transfer starts on an odd cycle: one idle cycle, an optional
+    // alignment cycle, then a read and a write for each byte.
+    // https://wiki.nesdev.com/w/index.php/PPU_registers#OAMDMA
+    fn oam_dma(&mut self, page: u8) {
+        let align = 1 + self.odd_cycle as u64;
+        for _ in 0..align {
+            self.tick();
+        }
+        let base = (page as u16) << 8;
+        for i in 0..256 {
+            self.tick();
+            let v = self.read_byte(base + i);
+            self.tick();
+            self.ppu.write_register(4, v);
+        }
+        self.stall_cycles += align + 512;
+    }
+}
+
+impl Bus for NesBus {
+    fn read(&mut self, addr: u16) -> Result<u8, CpuError> {
+        Ok(self.read_byte(addr))
+    }
+
+    fn write(&mut self, addr: u16, v: u8) -> Result<(), CpuError> {
+        self.data_bus = v;
+        match addr {
+            0..=0x07FF => self.ram[addr as usize] = v,
+            0x0800..=0x0FFF => self.ram[addr as usize - 0x0800] = v,
+            0x1000..=0x17FF => self.ram[addr as usize - 0x1000] = v,
+            0x1800..=0x1FFF => self.ram[addr as usize - 0x1800] = v,
+            0x2000..=0x3FFF => self.ppu.write_register(addr % 8, v),
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write(addr, v),
+            0x4014 => self.oam_dma(v),
+            0x4016 => self.input.write(v),
+            // Nothing responds to writes to ROM, which some games make, or to
+            // the disabled test registers.
+            0x4020..=0xFFFF => {
+                self.mapper_prg.write(addr, v);
+            }
+            0x4018..=0x401F => {}
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        self.apu.tick();
+        for _ in 0..3 {
+            self.ppu.tick();
+        }
+    }
+
+    fn take_stall_cycles(&mut self) -> u64 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        self.ppu.take_nmi()
+    }
+
+    fn irq(&self) -> bool {
+        self.apu.irq()
+    }
+
+    // Mirrors read(), but leaves registers, latches and the data bus as they
+    // are.
+    fn peek(&self, addr: u16) -> Option<u8> {
+        let v = match addr {
+            0..=0x1FFF => self.ram[addr as usize % RAM_SIZE],
+            0x2000..=0x3FFF => self.ppu.peek_register(addr % 8),
+            0x4015 => self.apu.peek_status() | (self.data_bus & 0b0010_0000),
+            0x4016 => self.input.peek(0, &self.ppu, self.data_bus),
+            0x4017 => self.input.peek(1, &self.ppu, self.data_bus),
+            0x4020..=0xFFFF => self.mapper_prg.peek(addr).unwrap_or(self.data_bus),
+            0x4000..=0x4014 | 0x4018..=0x401F => self.data_bus,
+        };
+        Some(v)
+    }
+
+    fn ppu_position(&self) -> Option<(usize, usize)> {
+        Some((self.ppu.scanline(), self.ppu.dot()))
+    }
+}
+
+impl State for NesBus {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(&self.ram);
+        w.u8(self.data_bus);
+        w.bool(self.odd_cycle);
+        w.u64(self.stall_cycles);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.input.save_state(w);
+        self.mapper_prg.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        r.bytes_into(&mut self.ram, "RAM")?;
+        self.data_bus = r.u8()?;
+        self.odd_cycle = r.bool()?;
+        self.stall_cycles = r.u64()?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.input.load_state(r)?;
+        self.mapper_prg.load_state(r)
+    }
+}
+
+// 64 KiB of RAM and nothing else, for running 6502 programs that don't
+// target the NES, like CPU test suites.
+pub struct RamBus {
+    pub mem: Vec<u8>,
+}
+
+impl RamBus {
+    pub fn new() -> RamBus {
+        RamBus {
+            mem: vec![0; 1 << 16],
+        }
+    }
+}
+
+impl Default for RamBus {
+    fn default() -> RamBus {
+        RamBus::new()
+    }
+}
+
+impl Bus for RamBus {
+    fn read(&mut self, addr: u16) -> Result<u8, CpuError> {
+        Ok(self.mem[addr as usize])
+    }
+
+    fn write(&mut self, addr: u16, v: u8) -> Result<(), CpuError> {
+        self.mem[addr as usize] = v;
+        Ok(())
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.mem[addr as usize])
+    }
+}
+
+#[test]
+fn test_nes_bus_mirroring() {
+    let (prg, chr) = mapper::test::new();
+    let mut bus = NesBus::new(Box::new(prg), Box::new(chr));
+    bus.write(0x0801, 0xAB).unwrap();
+    assert_eq!(bus.read(0x0001).unwrap(), 0xAB);
+    assert_eq!(bus.read(0x1801).unwrap(), 0xAB);
+    assert_eq!(bus.peek(0x1001), Some(0xAB));
+}
+
+#[test]
+fn test_nes_bus_peek() {
+    let (prg, chr) = mapper::test::new();
+    let mut bus = NesBus::new(Box::new(prg), Box::new(chr));
+    // into vblank
+    while bus.ppu.scanline() != 241 || bus.ppu.dot() < 2 {
+        bus.tick();
+    }
+    bus.input.controllers[0].set(input::Button::A, true);
+    bus.write(0x4016, 1).unwrap();
+    bus.write(0x4016, 0).unwrap();
+
+    // peeking registers doesn't change what the next read sees
+    for _ in 0..2 {
+        assert_eq!(bus.peek(0x2002), Some(0x80));
+        assert_eq!(bus.peek(0x4016), Some(0x01));
+    }
+    assert_eq!(bus.read(0x2002).unwrap(), 0x80);
+    assert_eq!(bus.read(0x4016).unwrap(), 0x81);
+    assert_eq!(bus.peek(0x2002), Some(0x00));
+    assert_eq!(bus.peek(0x4016), Some(0x80));
+
+    // nor does peeking change the data bus
+    bus.write(0x0001, 0x34).unwrap();
+    bus.write(0x0000, 0x12).unwrap();
+    assert_eq!(bus.peek(0x0001), Some(0x34));
+    assert_eq!(bus.peek(0x4000), Some(0x12));
+}
+
+#[test]
+fn test_controller_open_bus() {
+    let (prg, chr) = mapper::test::new();
+    let mut bus = NesBus::new(Box::new(prg), Box::new(chr));
+    bus.input.controllers[0].set(input::Button::A, true);
+    bus.write(0x4016, 1).unwrap();
+    bus.write(0x4016, 0).unwrap();
+
+    // the upper three bits are left over from the last value on the bus
+    bus.write(0x0000, 0x40).unwrap();
+    assert_eq!(bus.read(0x4016).unwrap(), 0x41);
+    bus.write(0x0000, 0xFF).unwrap();
+    assert_eq!(bus.peek(0x4016), Some(0xE0));
+    assert_eq!(bus.read(0x4016).unwrap(), 0xE0);
+}
+
+#[test]
+fn test_oam_dma() {
+    let (prg, chr) = mapper::test::new();
+    let mut bus = NesBus::new(Box::new(prg), Box::new(chr));
+    bus.write(0x4014, 0x02).unwrap();
+    assert_eq!(bus.take_stall_cycles(), 513);
+    assert_eq!(bus.take_stall_cycles(), 0);
+
+    // the first transfer ended on an odd cycle, so the next one needs an
+    // alignment cycle
+    bus.write(0x4014, 0x02).unwrap();
+    assert_eq!(bus.take_stall_cycles(), 514);
+}
+
+#[test]
+fn test_ram_bus() {
+    let mut bus = RamBus::new();
+    bus.write(0xFFFF, 0xAB).unwrap();
+    assert_eq!(bus.read(0xFFFF).unwrap(), 0xAB);
+    assert_eq!(bus.read(0x4000).unwrap(), 0);
+}
@@ -0,0 +1,314 @@
+// Turns machine code back into text, in the syntax that the assemble module
+// accepts, so that a listing's source can be assembled again:
+//
+// C000  A9 01     LDA #$01
+// C002  D0 FC     loop: BNE loop
+//
+// Branch and jump targets that have a symbol are written as that symbol,
+// and the instruction at a symbol's address gets it as a label. Targets
+// without a symbol are written as absolute addresses, which the assembler
+// turns back into branch displacements.
+
+use super::address_mode::AddressMode;
+use super::bus::Bus;
+use super::opcode;
+use super::state::{Cpu, Variant};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+pub type Symbols = HashMap<u16, String>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+
+    // The instruction, or ??? for bytes that aren't one.
+    pub text: String,
+}
+
+impl Line {
+    // The line as assembler source, without the address and bytes.
+    pub fn source(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{}: {}", label, self.text),
+            None => self.text.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = self
+            .bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(f, "{:04X}  {:<9} {}", self.addr, hex, self.source())
+    }
+}
+
+pub fn disassemble(code: &[u8], base_addr: u16) -> Vec<Line> {
+    disassemble_for(code, base_addr, Variant::Ricoh2A03, &Symbols::new())
+}
+
+pub fn disassemble_for(
+    code: &[u8],
+    base_addr: u16,
+    variant: Variant,
+    symbols: &Symbols,
+) -> Vec<Line> {
+    let fetch = |addr: u16| code.get(addr.wrapping_sub(base_addr) as usize).copied();
+    let end = base_addr as usize + code.len();
+    disassemble_with(fetch, base_addr, end, variant, symbols)
+}
+
+// Disassembles the instructions that start in a range of the CPU's address
+// space. Memory is read with Bus::peek(), and bytes that the bus can't peek
+// show up as ???.
+pub fn disassemble_cpu<B: Bus>(
+    cpu: &Cpu<B>,
+    range: RangeInclusive<u16>,
+    symbols: &Symbols,
+) -> Vec<Line> {
+    let fetch = |addr: u16| cpu.bus.peek(addr);
+    let end = *range.end() as usize + 1;
+    disassemble_with(fetch, *range.start(), end, cpu.variant, symbols)
+}
+
+// The mnemonic of an encoding, or None if it isn't an instruction.
+pub fn mnemonic(variant: Variant, encoding: u8) -> Option<&'static str> {
+    opcode::decode(variant, encoding).map(|(opcode_type, _, _)| opcode_type.mnemonic())
+}
+
+fn disassemble_with(
+    fetch: impl Fn(u16) -> Option<u8>,
+    start: u16,
+    end: usize,
+    variant: Variant,
+    symbols: &Symbols,
+) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = start as usize;
+    while addr < end {
+        let line = disassemble_line(&fetch, addr as u16, variant, symbols);
+        addr += line.bytes.len().max(1);
+        lines.push(line);
+    }
+    lines
+}
+
+fn disassemble_line(
+    fetch: &impl Fn(u16) -> Option<u8>,
+    addr: u16,
+    variant: Variant,
+    symbols: &Symbols,
+) -> Line {
+    let label = symbols.get(&addr).cloned();
+    let unknown = |bytes| Line {
+        addr,
+        bytes,
+        label: label.clone(),
+        text: "???".to_string(),
+    };
+
+    let encoding = match fetch(addr) {
+        Some(encoding) => encoding,
+        None => return unknown(Vec::new()),
+    };
+    let (opcode_type, addr_mode) = match opcode::decode(variant, encoding) {
+        Some((opcode_type, addr_mode, _)) => (opcode_type, addr_mode),
+        None => return unknown(vec![encoding]),
+    };
+
+    let mut bytes = vec![encoding];
+    for i in 1..=addr_mode.operand_size() as u16 {
+        match fetch(addr.wrapping_add(i)) {
+            Some(b) => bytes.push(b),
+            // a truncated instruction
+            None => return unknown(vec![encoding]),
+        }
+    }
+
+    let operand = operand_text(opcode_type, addr_mode, addr, &bytes[1..], variant, symbols);
+    let text = if operand.is_empty() {
+        opcode_type.mnemonic().to_string()
+    } else {
+        format!("{} {}", opcode_type.mnemonic(), operand)
+    };
+
+    Line {
+        addr,
+        bytes,
+        label,
+        text,
+    }
+}
+
+fn operand_text(
+    opcode_type: opcode::Type,
+    addr_mode: AddressMode,
+    addr: u16,
+    operand: &[u8],
+    variant: Variant,
+    symbols: &Symbols,
+) -> String {
+    let byte = operand.first().copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, operand.get(1).copied().unwrap_or(0)]);
+    let target = |target: u16| match symbols.get(&target) {
+        Some(symbol) => symbol.clone(),
+        None => format!("${:04X}", target),
+    };
+
+    match addr_mode {
+        // The assembler infers the accumulator mode from a missing operand.
+        AddressMode::Implicit | AddressMode::Accumulator => String::new(),
+        AddressMode::Immediate => format!("#${:02X}", byte),
+        AddressMode::ZeroPage => format!("${:02X}", byte),
+        AddressMode::ZeroPageX => format!("${:02X},X", byte),
+        AddressMode::ZeroPageY => format!("${:02X},Y", byte),
+        AddressMode::Relative => target(addr.wrapping_add(2).wrapping_add(byte as i8 as u16)),
+        AddressMode::Absolute => {
+            if opcode_type.is_jump(variant) {
+                target(word)
+            } else {
+                format!("${:04X}", word)
+            }
+        }
+        AddressMode::AbsoluteX => format!("${:04X},X", word),
+        AddressMode::AbsoluteY => format!("${:04X},Y", word),
+        AddressMode::Indirect => format!("(${:04X})", word),
+        AddressMode::IndirectX => format!("(${:02X},X)", byte),
+        AddressMode::IndirectY => format!("(${:02X}),Y", byte),
+        AddressMode::ZeroPageIndirect => format!("(${:02X})", byte),
+        AddressMode::AbsoluteIndirectX => format!("(${:04X},X)", word),
+    }
+}
+
+#[cfg(test)]
+use super::assemble;
+
+#[cfg(test)]
+fn source(lines: &[Line]) -> String {
+    lines
+        .iter()
+        .map(Line::source)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn test_disassemble() {
+    let lines = disassemble(&[0xA9, 0x01, 0x0A, 0x9D, 0x00, 0x02, 0xD0, 0xF8], 0xC000);
+    let listing: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    assert_eq!(
+        listing,
+        vec![
+            "C000  A9 01     LDA #$01",
+            "C002  0A        ASL",
+            "C003  9D 00 02  STA $0200,X",
+            "C006  D0 F8     BNE $C000",
+        ]
+    );
+
+    // symbols for branch and jump targets
+    let mut symbols = Symbols::new();
+    symbols.insert(0xC000, "start".to_string());
+    let lines = disassemble_for(
+        &[0xE8, 0xD0, 0xFD, 0x4C, 0x00, 0xC0],
+        0xC000,
+        Variant::Ricoh2A03,
+        &symbols,
+    );
+    assert_eq!(source(&lines), "start: INX\nBNE start\nJMP start");
+
+    // undefined and truncated instructions
+    let lines = disassemble_for(&[0x02, 0xAD, 0x00], 0, Variant::Cmos65C02, &symbols);
+    let listing: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    assert_eq!(
+        listing,
+        vec![
+            "0000  02        ???",
+            "0001  AD        ???",
+            "0002  00        BRK"
+        ]
+    );
+}
+
+#[test]
+fn test_disassemble_cpu() {
+    let mut cpu = Cpu::new_test();
+    cpu.mem_write_buf(0x0600, vec![0xAD, 0x02, 0x20, 0x60]);
+    let lines = disassemble_cpu(&cpu, 0x0600..=0x0603, &Symbols::new());
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].text, "LDA $2002");
+    assert_eq!(lines[1].text, "RTS");
+
+    // memory that can't be peeked
+    let cpu = Cpu::with_bus(NoPeekBus);
+    let lines = disassemble_cpu(&cpu, 0x2000..=0x2000, &Symbols::new());
+    assert_eq!(lines[0].to_string(), "2000            ???");
+}
+
+#[cfg(test)]
+struct NoPeekBus;
+
+#[cfg(test)]
+impl Bus for NoPeekBus {
+    fn read(&mut self, _addr: u16) -> Result<u8, super::CpuError> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _addr: u16, _v: u8) -> Result<(), super::CpuError> {
+        Ok(())
+    }
+}
+
+// Random programs survive a trip through the disassembler and assembler.
+// Unofficial encodings that duplicate others are left out, since they can't
+// be told apart once they're text.
+#[test]
+fn test_round_trip() {
+    let mut seed = 0x2545_F491_u32;
+    let mut rand = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    for &variant in &[Variant::Ricoh2A03, Variant::Cmos65C02] {
+        let encodings: Vec<u8> = (0..=255)
+            .filter(|&e| match opcode::decode(variant, e) {
+                Some((opcode_type, addr_mode, _)) => {
+                    opcode::encode(variant, opcode_type, addr_mode) == Some(e)
+                }
+                None => false,
+            })
+            .collect();
+
+        for _ in 0..50 {
+            let base = 0x8000;
+            let mut code = Vec::new();
+            for _ in 0..64 {
+                code.push(encodings[rand() as usize % encodings.len()]);
+                let (_, addr_mode, _) = opcode::decode(variant, *code.last().unwrap()).unwrap();
+                for _ in 0..addr_mode.operand_size() {
+                    code.push(rand() as u8);
+                }
+            }
+
+            let lines = disassemble_for(&code, base, variant, &Symbols::new());
+            let src = source(&lines);
+            assert_eq!(
+                assemble::assemble_for(&src, base, variant),
+                Ok(code),
+                "{}",
+                src
+            );
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use super::bus::Bus;
+use super::error::CpuError;
+use super::execute;
+use super::opcode;
+use super::operand;
+use super::state::{Cpu, Variant};
+use std::marker::PhantomData;
+
+pub type Handler<B> = fn(&mut Cpu<B>) -> Result<(), CpuError>;
+
+// How step() gets from an encoding to the code that executes it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dispatch {
+    // One indirect call through a table of handlers specialized for each
+    // encoding.
+    Table,
+
+    // Looks up the opcode type and address mode at run time, then matches on
+    // each. This is what the table replaced, kept so that benches/cpu.rs can
+    // compare the two.
+    Decode,
+}
+
+// Decodes and executes the instruction for an encoding, without
+// specializing on it.
+pub fn decode_and_execute<B: Bus>(cpu: &mut Cpu<B>, encoding: u8) -> Result<(), CpuError> {
+    let (opcode_type, addr_mode) = match opcode::decode(cpu.variant, encoding) {
+        Some((opcode_type, addr_mode, _)) => (opcode_type, addr_mode),
+        None => {
+            return Err(CpuError::UnknownOpcode {
+                pc: cpu.regs.pc.wrapping_sub(1),
+                opcode: encoding,
+            })
+        }
+    };
+    let operand = operand::decode(cpu, opcode_type, addr_mode)?;
+    execute::execute(opcode_type, cpu, operand)
+}
+
+// Decodes and executes the instruction for one encoding. The opcode type and
+// address mode are constant for each instantiation, so the compiler reduces
+// this to the addressing and execution code for that opcode alone.
+fn handler<B: Bus, const CMOS: bool, const ENCODING: u8>(cpu: &mut Cpu<B>) -> Result<(), CpuError> {
+    let table = if CMOS {
+        &opcode::CMOS_DECODE_TABLE
+    } else {
+        &opcode::DECODE_TABLE
+    };
+    let (opcode_type, addr_mode) = match table[ENCODING as usize] {
+        Some((opcode_type, addr_mode, _)) => (opcode_type, addr_mode),
+        None => {
+            return Err(CpuError::UnknownOpcode {
+                pc: cpu.regs.pc.wrapping_sub(1),
+                opcode: ENCODING,
+            })
+        }
+    };
+    let operand = operand::decode(cpu, opcode_type, addr_mode)?;
+    execute::execute(opcode_type, cpu, operand)
+}
+
+macro_rules! handlers {
+    ($cmos:literal) => {
+        handlers!($cmos;
+            0x00 0x01 0x02 0x03 0x04 0x05 0x06 0x07 0x08 0x09 0x0A 0x0B 0x0C 0x0D 0x0E 0x0F
+            0x10 0x11 0x12 0x13 0x14 0x15 0x16 0x17 0x18 0x19 0x1A 0x1B 0x1C 0x1D 0x1E 0x1F
+            0x20 0x21 0x22 0x23 0x24 0x25 0x26 0x27 0x28 0x29 0x2A 0x2B 0x2C 0x2D 0x2E 0x2F
+            0x30 0x31 0x32 0x33 0x34 0x35 0x36 0x37 0x38 0x39 0x3A 0x3B 0x3C 0x3D 0x3E 0x3F
+            0x40 0x41 0x42 0x43 0x44 0x45 0x46 0x47 0x48 0x49 0x4A 0x4B 0x4C 0x4D 0x4E 0x4F
+            0x50 0x51 0x52 0x53 0x54 0x55 0x56 0x57 0x58 0x59 0x5A 0x5B 0x5C 0x5D 0x5E 0x5F
+            0x60 0x61 0x62 0x63 0x64 0x65 0x66 0x67 0x68 0x69 0x6A 0x6B 0x6C 0x6D 0x6E 0x6F
+            0x70 0x71 0x72 0x73 0x74 0x75 0x76 0x77 0x78 0x79 0x7A 0x7B 0x7C 0x7D 0x7E 0x7F
+            0x80 0x81 0x82 0x83 0x84 0x85 0x86 0x87 0x88 0x89 0x8A 0x8B 0x8C 0x8D 0x8E 0x8F
+            0x90 0x91 0x92 0x93 0x94 0x95 0x96 0x97 0x98 0x99 0x9A 0x9B 0x9C 0x9D 0x9E 0x9F
+            0xA0 0xA1 0xA2 0xA3 0xA4 0xA5 0xA6 0xA7 0xA8 0xA9 0xAA 0xAB 0xAC 0xAD 0xAE 0xAF
+            0xB0 0xB1 0xB2 0xB3 0xB4 0xB5 0xB6 0xB7 0xB8 0xB9 0xBA 0xBB 0xBC 0xBD 0xBE 0xBF
+            0xC0 0xC1 0xC2 0xC3 0xC4 0xC5 0xC6 0xC7 0xC8 0xC9 0xCA 0xCB 0xCC 0xCD 0xCE 0xCF
+            0xD0 0xD1 0xD2 0xD3 0xD4 0xD5 0xD6 0xD7 0xD8 0xD9 0xDA 0xDB 0xDC 0xDD 0xDE 0xDF
+            0xE0 0xE1 0xE2 0xE3 0xE4 0xE5 0xE6 0xE7 0xE8 0xE9 0xEA 0xEB 0xEC 0xED 0xEE 0xEF
+            0xF0 0xF1 0xF2 0xF3 0xF4 0xF5 0xF6 0xF7 0xF8 0xF9 0xFA 0xFB 0xFC 0xFD 0xFE 0xFF
+        )
+    };
+    ($cmos:literal; $($encoding:literal)*) => {
+        [$(handler::<B, $cmos, $encoding>),*]
+    };
+}
+
+// Handlers indexed by encoding, so that dispatching an instruction is a
+// single indirect call. Statics can't be generic, so the tables are
+// associated constants of a type that is.
+struct Handlers<B>(PhantomData<B>);
+
+impl<B: Bus> Handlers<B> {
+    const NMOS: [Handler<B>; 256] = handlers!(false);
+    const CMOS: [Handler<B>; 256] = handlers!(true);
+}
+
+pub fn handler_for<B: Bus>(variant: Variant, encoding: u8) -> Handler<B> {
+    match variant {
+        Variant::Ricoh2A03 | Variant::Nmos6502 => Handlers::<B>::NMOS[encoding as usize],
+        Variant::Cmos65C02 => Handlers::<B>::CMOS[encoding as usize],
+    }
+}
+
+// Each handler should behave exactly like the generic decode and execute path.
+#[test]
+fn test_handlers() {
+    let setup = |variant: Variant, encoding: u8| {
+        let mut cpu = Cpu::new_test();
+        cpu.variant = variant;
+        cpu.regs.pc = 0x201;
+        cpu.regs.s = 0xF0;
+        cpu.regs.a = 0x5A;
+        cpu.regs.x = 0x03;
+        cpu.regs.y = 0xFE;
+        cpu.mem_write_buf(0x200, vec![encoding, 0x10, 0x03]);
+        cpu.mem_write_buf(0x10, vec![0x80, 0x03, 0x7F, 0x04]);
+        cpu
+    };
+
+    for &variant in &[Variant::Ricoh2A03, Variant::Cmos65C02] {
+        for encoding in 0..=255 {
+            let mut cpu = setup(variant, encoding);
+            let res = handler_for(variant, encoding)(&mut cpu);
+
+            let (opcode_type, addr_mode, _) = match opcode::decode(variant, encoding) {
+                Some(decoded) => decoded,
+                None => {
+                    let pc = 0x200;
+                    assert_eq!(
+                        res,
+                        Err(CpuError::UnknownOpcode {
+                            pc,
+                            opcode: encoding
+                        })
+                    );
+                    continue;
+                }
+            };
+            let mut expected = setup(variant, encoding);
+            let operand = operand::decode(&mut expected, opcode_type, addr_mode).unwrap();
+            let expected_res = execute::execute(opcode_type, &mut expected, operand);
+
+            assert_eq!(res, expected_res, "{:#04X}", encoding);
+            assert_eq!(cpu.regs, expected.regs, "{:#04X}", encoding);
+            assert_eq!(cpu.cycles, expected.cycles, "{:#04X}", encoding);
+            assert_eq!(cpu.bus.ram[..], expected.bus.ram[..], "{:#04X}", encoding);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use super::operand::Operand;
+use std::error;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// Reasons the CPU can fail to execute an instruction. Each one carries enough
+// context for a debugger or test harness to report where execution stopped.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CpuError {
+    // The byte at pc does not encode any instruction.
+    UnknownOpcode { pc: u16, opcode: u8 },
+
+    // Nothing on the bus responds at the address. The NES ignores such
+    // writes, and such reads see open bus, so only stricter buses fail.
+    Unmapped { addr: u16, access: Access },
+
+    // A KIL opcode at pc has jammed the CPU. It stays halted until reset.
+    Halted { pc: u16 },
+
+    // The instruction has no value or address to use for this operand.
+    InvalidOperand(Operand),
+}
+
+impl error::Error for CpuError {}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            Self::Unmapped { addr, access } => {
+                let access = match access {
+                    Access::Read => "read from",
+                    Access::Write => "write to",
+                };
+                write!(f, "{} unmapped address ${:04X}", access, addr)
+            }
+            Self::Halted { pc } => write!(f, "CPU halted at ${:04X}", pc),
+            Self::InvalidOperand(operand) => write!(f, "invalid operand: {:?}", operand),
+        }
+    }
+}
@@ -1,18 +1,15 @@
+use crate::cpu::bus::Bus;
+use crate::cpu::error::CpuError;
 use crate::cpu::operand::Operand;
-use crate::cpu::state::Cpu;
+use crate::cpu::state::{Cpu, Variant};
 use crate::cpu::status::Status;
 use crate::math;
 
-pub fn adc(cpu: &mut Cpu, operand: Operand) {
+pub fn adc<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
     let prev = cpu.regs.a;
-    let opval = operand.read(cpu);
-    let res16 = prev as u16
-        + opval as u16
-        + if cpu.regs.status_check(Status::Carry) {
-            1
-        } else {
-            0
-        };
+    let opval = operand.read(cpu)?;
+    let carry = cpu.regs.status_check(Status::Carry) as u8;
+    let res16 = prev as u16 + opval as u16 + carry as u16;
     let res = res16 as u8;
 
     cpu.regs.a = res;
@@ -26,39 +23,65 @@ pub fn adc(cpu: &mut Cpu, operand: Operand) {
         Status::Overflow,
         math::same_sign(prev, opval) && !math::same_sign(prev, res),
     );
+
+    if cpu.decimal_mode() {
+        adc_decimal(cpu, prev, opval, carry);
+        cmos_decimal_flags(cpu);
+    }
+    Ok(())
+}
+
+// NMOS 6502 decimal addition. Z keeps its value from the binary sum, and N
+// and V are taken from the sum before the high digit is adjusted, so only C
+// is valid in decimal terms.
+// Reference: http://www.6502.org/tutorials/decimal_mode.html#A
+fn adc_decimal<B: Bus>(cpu: &mut Cpu<B>, a: u8, b: u8, carry: u8) {
+    let mut lo = (a & 0x0F) + (b & 0x0F) + carry;
+    if lo >= 0x0A {
+        lo = ((lo + 0x06) & 0x0F) + 0x10;
+    }
+    let sum = (a & 0xF0) as u16 + (b & 0xF0) as u16 + lo as u16;
+    let signed = (a & 0xF0) as i8 as i16 + (b & 0xF0) as i8 as i16 + lo as i16;
+    cpu.regs.status_set(Status::Negative, sum & 0x80 != 0);
+    cpu.regs
+        .status_set(Status::Overflow, !(-128..=127).contains(&signed));
+
+    let sum = if sum >= 0xA0 { sum + 0x60 } else { sum };
+    cpu.regs.a = sum as u8;
+    cpu.regs.status_set(Status::Carry, sum >= 0x100);
 }
 
 #[test]
 fn test_adc() {
     // no-mask operation
     let mut cpu = Cpu::new_test();
-    adc(&mut cpu, Operand::Immediate(1));
+    adc(&mut cpu, Operand::Immediate(1)).unwrap();
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, 0);
 
     // incorporates carry
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::Carry.mask();
-    adc(&mut cpu, Operand::Immediate(1));
+    adc(&mut cpu, Operand::Immediate(1)).unwrap();
     assert_eq!(cpu.regs.a, 2);
     assert_eq!(cpu.regs.p, 0);
 
     // sets zero mask
     let mut cpu = Cpu::new_test();
-    adc(&mut cpu, Operand::Immediate(0));
+    adc(&mut cpu, Operand::Immediate(0)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     // sets negative mask
     let mut cpu = Cpu::new_test();
-    adc(&mut cpu, Operand::Immediate(0xFF));
+    adc(&mut cpu, Operand::Immediate(0xFF)).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     // sets carry
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
-    adc(&mut cpu, Operand::Immediate(1));
+    adc(&mut cpu, Operand::Immediate(1)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
@@ -66,14 +89,14 @@ fn test_adc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::Carry.mask();
     cpu.regs.a = 0;
-    adc(&mut cpu, Operand::Immediate(0xFF));
+    adc(&mut cpu, Operand::Immediate(0xFF)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
     // positive overflow
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x7F;
-    adc(&mut cpu, Operand::Immediate(0x7F));
+    adc(&mut cpu, Operand::Immediate(0x7F)).unwrap();
     assert_eq!(cpu.regs.a, 0xFE);
     assert_eq!(
         cpu.regs.p,
@@ -83,7 +106,7 @@ fn test_adc() {
     // negative overflow
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x80;
-    adc(&mut cpu, Operand::Immediate(0x80));
+    adc(&mut cpu, Operand::Immediate(0x80)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(
         cpu.regs.p,
@@ -94,21 +117,16 @@ fn test_adc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::Carry.mask();
     cpu.regs.a = 0x7F;
-    adc(&mut cpu, Operand::Immediate(0x80));
+    adc(&mut cpu, Operand::Immediate(0x80)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 }
 
-pub fn sbc(cpu: &mut Cpu, operand: Operand) {
+pub fn sbc<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
     let prev = cpu.regs.a;
-    let opval = operand.read(cpu);
-    let complement = !opval as u16
-        + if cpu.regs.status_check(Status::Carry) {
-            1
-        } else {
-            0
-        };
-    let res16 = prev as u16 + complement;
+    let opval = operand.read(cpu)?;
+    let carry = cpu.regs.status_check(Status::Carry) as u8;
+    let res16 = prev as u16 + !opval as u16 + carry as u16;
     let res = res16 as u8;
 
     cpu.regs.a = res;
@@ -122,6 +140,36 @@ pub fn sbc(cpu: &mut Cpu, operand: Operand) {
         Status::Overflow,
         !math::same_sign(prev, opval) && math::same_sign(opval, res),
     );
+
+    if cpu.decimal_mode() {
+        cpu.regs.a = sbc_decimal(prev, opval, carry);
+        cmos_decimal_flags(cpu);
+    }
+    Ok(())
+}
+
+// The 65C02 takes an extra cycle in decimal mode to set N and Z from the
+// adjusted result.
+fn cmos_decimal_flags<B: Bus>(cpu: &mut Cpu<B>) {
+    if cpu.variant == Variant::Cmos65C02 {
+        cpu.bus_dummy_read(cpu.regs.pc);
+        cpu.regs.status_set_zn(cpu.regs.a);
+    }
+}
+
+// NMOS 6502 decimal subtraction. Only the accumulator differs from binary
+// mode; all flags keep their values from the binary difference.
+// Reference: http://www.6502.org/tutorials/decimal_mode.html#A
+fn sbc_decimal(a: u8, b: u8, carry: u8) -> u8 {
+    let mut lo = (a & 0x0F) as i16 - (b & 0x0F) as i16 + carry as i16 - 1;
+    if lo < 0 {
+        lo = ((lo - 0x06) & 0x0F) - 0x10;
+    }
+    let mut diff = (a & 0xF0) as i16 - (b & 0xF0) as i16 + lo;
+    if diff < 0 {
+        diff -= 0x60;
+    }
+    diff as u8
 }
 
 #[test]
@@ -130,7 +178,7 @@ fn test_sbc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 5;
     cpu.regs.p = Status::Carry.mask();
-    sbc(&mut cpu, Operand::Immediate(3));
+    sbc(&mut cpu, Operand::Immediate(3)).unwrap();
     assert_eq!(cpu.regs.a, 2);
     assert_eq!(cpu.regs.p, Status::Carry.mask());
 
@@ -138,7 +186,7 @@ fn test_sbc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 5;
     cpu.regs.p = Status::Carry.mask();
-    sbc(&mut cpu, Operand::Immediate(5));
+    sbc(&mut cpu, Operand::Immediate(5)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
@@ -146,7 +194,7 @@ fn test_sbc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 5;
     cpu.regs.p = Status::Carry.mask();
-    sbc(&mut cpu, Operand::Immediate(6));
+    sbc(&mut cpu, Operand::Immediate(6)).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
@@ -154,7 +202,7 @@ fn test_sbc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x7F;
     cpu.regs.p = Status::Carry.mask();
-    sbc(&mut cpu, Operand::Immediate(0xFF));
+    sbc(&mut cpu, Operand::Immediate(0xFF)).unwrap();
     assert_eq!(cpu.regs.a, 0x80);
     assert_eq!(
         cpu.regs.p,
@@ -165,36 +213,159 @@ fn test_sbc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x80;
     cpu.regs.p = Status::Carry.mask();
-    sbc(&mut cpu, Operand::Immediate(1));
+    sbc(&mut cpu, Operand::Immediate(1)).unwrap();
     assert_eq!(cpu.regs.a, 0x7F);
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Overflow.mask());
 
     // borrow subtraction, positive result
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 5;
-    sbc(&mut cpu, Operand::Immediate(3));
+    sbc(&mut cpu, Operand::Immediate(3)).unwrap();
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, Status::Carry.mask());
 
     // borrow subtraction, zero result
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 5;
-    sbc(&mut cpu, Operand::Immediate(4));
+    sbc(&mut cpu, Operand::Immediate(4)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
     // borrow subtraction, negative result
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 5;
-    sbc(&mut cpu, Operand::Immediate(5));
+    sbc(&mut cpu, Operand::Immediate(5)).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
 
-pub fn cmp(cpu: &mut Cpu, operand: Operand) {
-    let opval = operand.read(cpu);
+#[cfg(test)]
+fn bcd(n: u8) -> u8 {
+    (n / 10) << 4 | (n % 10)
+}
+
+// Every pair of valid BCD operands, with and without carry, should produce
+// the decimal sum or difference. The 2A03 ignores the decimal flag.
+#[test]
+fn test_decimal_table() {
+    for &variant in &[Variant::Nmos6502, Variant::Cmos65C02] {
+        for x in 0..100 {
+            for y in 0..100 {
+                for carry in 0..2 {
+                    let mut cpu = Cpu::new_test();
+                    cpu.variant = variant;
+                    cpu.regs.p = Status::DecimalMode.mask() | carry;
+                    cpu.regs.a = bcd(x);
+                    adc(&mut cpu, Operand::Immediate(bcd(y))).unwrap();
+                    let sum = x + y + carry;
+                    assert_eq!(cpu.regs.a, bcd(sum % 100), "{} + {} + {}", x, y, carry);
+                    assert_eq!(cpu.regs.status_check(Status::Carry), sum >= 100);
+
+                    // carry clear means borrow
+                    cpu.regs.p = Status::DecimalMode.mask() | carry;
+                    cpu.regs.a = bcd(x);
+                    sbc(&mut cpu, Operand::Immediate(bcd(y))).unwrap();
+                    let diff = 100 + x - y - (1 - carry);
+                    assert_eq!(cpu.regs.a, bcd(diff % 100), "{} - {} - {}", x, y, 1 - carry);
+                    assert_eq!(cpu.regs.status_check(Status::Carry), diff >= 100);
+                }
+            }
+        }
+    }
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.p = Status::DecimalMode.mask();
+    cpu.regs.a = 0x09;
+    adc(&mut cpu, Operand::Immediate(0x01)).unwrap();
+    assert_eq!(cpu.regs.a, 0x0A);
+}
+
+// Flag results in decimal mode, from the NMOS 6502 examples at
+// http://www.6502.org/tutorials/decimal_mode.html#A
+#[test]
+fn test_decimal_flags() {
+    let run = |f: fn(&mut Cpu, Operand) -> Result<(), CpuError>, a: u8, b: u8, carry: bool| {
+        let mut cpu = Cpu::new_test();
+        cpu.variant = Variant::Nmos6502;
+        cpu.regs.status_set(Status::DecimalMode, true);
+        cpu.regs.status_set(Status::Carry, carry);
+        cpu.regs.a = a;
+        f(&mut cpu, Operand::Immediate(b)).unwrap();
+        (cpu.regs.a, cpu.regs.p & !Status::DecimalMode.mask())
+    };
+
+    // 99 + 01: N is set from the unadjusted sum, and Z from the binary sum
+    assert_eq!(
+        run(adc, 0x99, 0x01, false),
+        (0x00, Status::Carry.mask() | Status::Negative.mask())
+    );
+
+    // 79 + 00 + 1: the unadjusted sum $80 overflows
+    assert_eq!(
+        run(adc, 0x79, 0x00, true),
+        (0x80, Status::Negative.mask() | Status::Overflow.mask())
+    );
+
+    // 24 + 56: the unadjusted sum $80 overflows, though the binary sum $7A
+    // does not
+    assert_eq!(
+        run(adc, 0x24, 0x56, false),
+        (0x80, Status::Negative.mask() | Status::Overflow.mask())
+    );
+
+    // 93 + 82: carry out, and overflow from two negative values
+    assert_eq!(
+        run(adc, 0x93, 0x82, false),
+        (0x75, Status::Carry.mask() | Status::Overflow.mask())
+    );
+
+    // 00 - 01: flags are those of the binary difference
+    assert_eq!(run(sbc, 0x00, 0x01, true), (0x99, Status::Negative.mask()));
+
+    // 32 - 02 - 1
+    assert_eq!(run(sbc, 0x32, 0x02, false), (0x29, Status::Carry.mask()));
+
+    // 80 - 01: overflow
+    assert_eq!(
+        run(sbc, 0x80, 0x01, true),
+        (0x79, Status::Carry.mask() | Status::Overflow.mask())
+    );
+}
+
+// The 65C02 sets N and Z from the decimal result, and takes a cycle to do so.
+#[test]
+fn test_decimal_flags_cmos() {
+    let run = |f: fn(&mut Cpu, Operand) -> Result<(), CpuError>, a: u8, b: u8, carry: bool| {
+        let mut cpu = Cpu::new_test();
+        cpu.variant = Variant::Cmos65C02;
+        cpu.regs.status_set(Status::DecimalMode, true);
+        cpu.regs.status_set(Status::Carry, carry);
+        cpu.regs.a = a;
+        f(&mut cpu, Operand::Immediate(b)).unwrap();
+        assert_eq!(cpu.cycles, 1);
+        (cpu.regs.a, cpu.regs.p & !Status::DecimalMode.mask())
+    };
+
+    assert_eq!(
+        run(adc, 0x99, 0x01, false),
+        (0x00, Status::Carry.mask() | Status::Zero.mask())
+    );
+    assert_eq!(
+        run(adc, 0x79, 0x00, true),
+        (0x80, Status::Negative.mask() | Status::Overflow.mask())
+    );
+    assert_eq!(run(sbc, 0x00, 0x01, true), (0x99, Status::Negative.mask()));
+    assert_eq!(
+        run(sbc, 0x32, 0x32, true),
+        (0x00, Status::Carry.mask() | Status::Zero.mask())
+    );
+}
+
+pub fn cmp<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let opval = operand.read(cpu)?;
     cpu.regs.status_set(Status::Carry, cpu.regs.a >= opval);
     cpu.regs.status_set_zn(cpu.regs.a.wrapping_sub(opval));
+    Ok(())
 }
 
 #[test]
@@ -210,42 +381,43 @@ fn test_cmp() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 3;
     cpu.mem_write(0x10, 4);
-    cmp(&mut cpu, Operand::Memory(0x10));
+    cmp(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     // A < M, N = 0
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 2;
     cpu.mem_write(0x10, 0xFF);
-    cmp(&mut cpu, Operand::Memory(0x10));
+    cmp(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, 0);
 
     // A = M
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 3;
     cpu.mem_write(0x10, 3);
-    cmp(&mut cpu, Operand::Memory(0x10));
+    cmp(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
     // A > M, N = 0
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 2;
     cpu.mem_write(0x10, 1);
-    cmp(&mut cpu, Operand::Memory(0x10));
+    cmp(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask());
 
     // A > M, N = 1
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
     cpu.mem_write(0xFE, 1);
-    cmp(&mut cpu, Operand::Memory(0x10));
+    cmp(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Negative.mask());
 }
 
-pub fn cpx(cpu: &mut Cpu, operand: Operand) {
-    let opval = operand.read(cpu);
+pub fn cpx<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let opval = operand.read(cpu)?;
     cpu.regs.status_set(Status::Carry, cpu.regs.x >= opval);
     cpu.regs.status_set_zn(cpu.regs.x.wrapping_sub(opval));
+    Ok(())
 }
 
 #[test]
@@ -256,42 +428,43 @@ fn test_cpx() {
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 3;
     cpu.mem_write(0x10, 4);
-    cpx(&mut cpu, Operand::Memory(0x10));
+    cpx(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     // X < M, N = 0
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 2;
     cpu.mem_write(0x10, 0xFF);
-    cpx(&mut cpu, Operand::Memory(0x10));
+    cpx(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, 0);
 
     // X = M
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 3;
     cpu.mem_write(0x10, 3);
-    cpx(&mut cpu, Operand::Memory(0x10));
+    cpx(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
     // X > M, N = 0
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 2;
     cpu.mem_write(0x10, 1);
-    cpx(&mut cpu, Operand::Memory(0x10));
+    cpx(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask());
 
     // X > M, N = 1
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0xFF;
     cpu.mem_write(0xFE, 1);
-    cpx(&mut cpu, Operand::Memory(0x10));
+    cpx(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Negative.mask());
 }
 
-pub fn cpy(cpu: &mut Cpu, operand: Operand) {
-    let opval = operand.read(cpu);
+pub fn cpy<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let opval = operand.read(cpu)?;
     cpu.regs.status_set(Status::Carry, cpu.regs.y >= opval);
     cpu.regs.status_set_zn(cpu.regs.y.wrapping_sub(opval));
+    Ok(())
 }
 
 #[test]
@@ -302,34 +475,34 @@ fn test_cpy() {
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 3;
     cpu.mem_write(0x10, 4);
-    cpy(&mut cpu, Operand::Memory(0x10));
+    cpy(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     // Y < M, N = 0
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 2;
     cpu.mem_write(0x10, 0xFF);
-    cpy(&mut cpu, Operand::Memory(0x10));
+    cpy(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, 0);
 
     // Y = M
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 3;
     cpu.mem_write(0x10, 3);
-    cpy(&mut cpu, Operand::Memory(0x10));
+    cpy(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
 
     // Y > M, N = 0
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 2;
     cpu.mem_write(0x10, 1);
-    cpy(&mut cpu, Operand::Memory(0x10));
+    cpy(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask());
 
     // Y > M, N = 1
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 0xFF;
     cpu.mem_write(0xFE, 1);
-    cpy(&mut cpu, Operand::Memory(0x10));
+    cpy(&mut cpu, Operand::Memory(0x10)).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Negative.mask());
 }
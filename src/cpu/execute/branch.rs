@@ -1,71 +1,76 @@
+use super::super::bus::Bus;
+use super::super::error::CpuError;
 use super::super::operand::Operand;
 use super::super::state::Cpu;
 use super::super::status::Status;
 use crate::math;
 
-fn branch(cpu: &mut Cpu, operand: Operand, cond: bool) {
+fn branch<B: Bus>(cpu: &mut Cpu<B>, operand: Operand, cond: bool) -> Result<(), CpuError> {
     if !cond {
-        return;
+        return Ok(());
     }
 
-    cpu.cycle_add(1);
-    let addr = math::byte_addr_offset(cpu.regs.pc, operand.read(cpu));
+    // A taken branch reads the next opcode while it adds the offset, and
+    // again from the wrong page if the add carries into the high byte.
+    cpu.bus_dummy_read(cpu.regs.pc);
+    let addr = math::byte_addr_offset(cpu.regs.pc, operand.read(cpu)?);
     if math::page_crossing(cpu.regs.pc, addr) {
-        cpu.cycle_add(1);
+        cpu.bus_dummy_read((cpu.regs.pc & 0xFF00) | (addr & 0x00FF));
     }
 
     cpu.regs.pc = addr;
+    Ok(())
 }
 
 #[test]
 fn test_branch_cycle() {
     // no branch
     let mut cpu = Cpu::new_test();
-    branch(&mut cpu, Operand::Immediate(1), false);
+    branch(&mut cpu, Operand::Immediate(1), false).unwrap();
     assert_eq!(cpu.cycles, 0);
 
     // branch, no page crossing
     let mut cpu = Cpu::new_test();
-    branch(&mut cpu, Operand::Immediate(1), true);
+    branch(&mut cpu, Operand::Immediate(1), true).unwrap();
     assert_eq!(cpu.cycles, 1);
 
     // branch, positive page crossing
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0xFF;
-    branch(&mut cpu, Operand::Immediate(1), true);
+    branch(&mut cpu, Operand::Immediate(1), true).unwrap();
     assert_eq!(cpu.cycles, 2);
 
     // branch, negative page crossing
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x100;
-    branch(&mut cpu, Operand::Immediate(0x80), true);
+    branch(&mut cpu, Operand::Immediate(0x80), true).unwrap();
     assert_eq!(cpu.cycles, 2);
 }
 
-pub fn bcc(cpu: &mut Cpu, operand: Operand) {
-    branch(cpu, operand, !cpu.regs.status_check(Status::Carry));
+pub fn bcc<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    branch(cpu, operand, !cpu.regs.status_check(Status::Carry))
 }
 
 #[test]
 fn test_bcc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bcc(&mut cpu, Operand::Immediate(2));
+    bcc(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Carry.mask();
-    bcc(&mut cpu, Operand::Immediate(2));
+    bcc(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bcc(&mut cpu, Operand::Immediate(0xFE));
+    bcc(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn bcs(cpu: &mut Cpu, operand: Operand) {
+pub fn bcs<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
     branch(cpu, operand, cpu.regs.status_check(Status::Carry))
 }
 
@@ -74,22 +79,22 @@ fn test_bcs() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Carry.mask();
-    bcs(&mut cpu, Operand::Immediate(2));
+    bcs(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bcs(&mut cpu, Operand::Immediate(2));
+    bcs(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Carry.mask();
-    bcs(&mut cpu, Operand::Immediate(0xFE));
+    bcs(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn beq(cpu: &mut Cpu, operand: Operand) {
+pub fn beq<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
     branch(cpu, operand, cpu.regs.status_check(Status::Zero))
 }
 
@@ -98,23 +103,23 @@ fn test_beq() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Zero.mask();
-    beq(&mut cpu, Operand::Immediate(2));
+    beq(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    beq(&mut cpu, Operand::Immediate(2));
+    beq(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Zero.mask();
-    beq(&mut cpu, Operand::Immediate(0xFE));
+    beq(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn bmi(cpu: &mut Cpu, operand: Operand) {
-    branch(cpu, operand, cpu.regs.status_check(Status::Negative));
+pub fn bmi<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    branch(cpu, operand, cpu.regs.status_check(Status::Negative))
 }
 
 #[test]
@@ -122,45 +127,45 @@ fn test_bmi() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Negative.mask();
-    bmi(&mut cpu, Operand::Immediate(2));
+    bmi(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bmi(&mut cpu, Operand::Immediate(2));
+    bmi(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Negative.mask();
-    bmi(&mut cpu, Operand::Immediate(0xFE));
+    bmi(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn bne(cpu: &mut Cpu, operand: Operand) {
-    branch(cpu, operand, !cpu.regs.status_check(Status::Zero));
+pub fn bne<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    branch(cpu, operand, !cpu.regs.status_check(Status::Zero))
 }
 
 #[test]
 fn test_bne() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bne(&mut cpu, Operand::Immediate(2));
+    bne(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Zero.mask();
-    bne(&mut cpu, Operand::Immediate(2));
+    bne(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bne(&mut cpu, Operand::Immediate(0xFE));
+    bne(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn bpl(cpu: &mut Cpu, operand: Operand) {
+pub fn bpl<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
     branch(cpu, operand, !cpu.regs.status_check(Status::Negative))
 }
 
@@ -168,46 +173,46 @@ pub fn bpl(cpu: &mut Cpu, operand: Operand) {
 fn test_bpl() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bpl(&mut cpu, Operand::Immediate(2));
+    bpl(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Negative.mask();
-    bpl(&mut cpu, Operand::Immediate(2));
+    bpl(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bpl(&mut cpu, Operand::Immediate(0xFE));
+    bpl(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn bvc(cpu: &mut Cpu, operand: Operand) {
-    branch(cpu, operand, !cpu.regs.status_check(Status::Overflow));
+pub fn bvc<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    branch(cpu, operand, !cpu.regs.status_check(Status::Overflow))
 }
 
 #[test]
 fn test_bvc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bvc(&mut cpu, Operand::Immediate(2));
+    bvc(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Overflow.mask();
-    bvc(&mut cpu, Operand::Immediate(2));
+    bvc(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bvc(&mut cpu, Operand::Immediate(0xFE));
+    bvc(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
 
-pub fn bvs(cpu: &mut Cpu, operand: Operand) {
-    branch(cpu, operand, cpu.regs.status_check(Status::Overflow));
+pub fn bvs<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    branch(cpu, operand, cpu.regs.status_check(Status::Overflow))
 }
 
 #[test]
@@ -215,17 +220,34 @@ fn test_bvs() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Overflow.mask();
-    bvs(&mut cpu, Operand::Immediate(2));
+    bvs(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x12);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    bvs(&mut cpu, Operand::Immediate(2));
+    bvs(&mut cpu, Operand::Immediate(2)).unwrap();
     assert_eq!(cpu.regs.pc, 0x10);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
     cpu.regs.p = Status::Overflow.mask();
-    bvs(&mut cpu, Operand::Immediate(0xFE));
+    bvs(&mut cpu, Operand::Immediate(0xFE)).unwrap();
+    assert_eq!(cpu.regs.pc, 0xE);
+}
+
+pub fn bra<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    branch(cpu, operand, true)
+}
+
+#[test]
+fn test_bra() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.pc = 0x10;
+    bra(&mut cpu, Operand::Immediate(2)).unwrap();
+    assert_eq!(cpu.regs.pc, 0x12);
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.pc = 0x10;
+    bra(&mut cpu, Operand::Immediate(0xFE)).unwrap();
     assert_eq!(cpu.regs.pc, 0xE);
 }
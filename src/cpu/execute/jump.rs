@@ -1,43 +1,55 @@
+use super::super::bus::Bus;
+use super::super::error::CpuError;
 use super::super::operand::Operand;
 use super::super::state::Cpu;
 
-pub fn jmp(cpu: &mut Cpu, operand: Operand) {
-    cpu.regs.pc = operand.address();
+pub fn jmp<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    cpu.regs.pc = operand.address()?;
+    Ok(())
 }
 
 #[test]
 fn test_jmp() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x10;
-    jmp(&mut cpu, Operand::Memory(0x20));
+    jmp(&mut cpu, Operand::Memory(0x20)).unwrap();
     assert_eq!(cpu.regs.pc, 0x20);
 }
 
-pub fn jsr(cpu: &mut Cpu, operand: Operand) {
-    cpu.stack_push16(cpu.regs.pc - 1);
-    cpu.regs.pc = operand.address();
+// The CPU fetches the target's high byte after pushing the return address,
+// but the operand has already been fetched here. This leaves an idle read
+// from the stack, then the pushes.
+pub fn jsr<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    cpu.stack_push16(cpu.regs.pc.wrapping_sub(1))?;
+    cpu.regs.pc = operand.address()?;
+    Ok(())
 }
 
 #[test]
 fn test_jsr() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x202;
-    jsr(&mut cpu, Operand::Memory(0x300));
+    jsr(&mut cpu, Operand::Memory(0x300)).unwrap();
     assert_eq!(cpu.stack_peek16(0), 0x201);
     assert_eq!(cpu.regs.pc, 0x300);
 }
 
-pub fn rts(cpu: &mut Cpu, _operand: Operand) {
-    cpu.regs.pc = cpu.stack_pop16() + 1;
+pub fn rts<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    let addr = cpu.stack_pop16()?;
+    cpu.bus_dummy_read(addr); // read while incrementing
+    cpu.regs.pc = addr.wrapping_add(1);
+    Ok(())
 }
 
 #[test]
 fn test_rts() {
     let mut cpu = Cpu::new_test();
     cpu.regs.pc = 0x300;
-    cpu.stack_push(0x69); // this is just a sentinel for the test
-    cpu.stack_push16(0x201);
-    rts(&mut cpu, Operand::None);
+    cpu.stack_push(0x69).unwrap(); // this is just a sentinel for the test
+    cpu.stack_push16(0x201).unwrap();
+    rts(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.stack_peek(0), 0x69);
     assert_eq!(cpu.regs.pc, 0x202);
 }
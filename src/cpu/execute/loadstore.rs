@@ -1,11 +1,15 @@
+use crate::cpu::bus::Bus;
+use crate::cpu::error::CpuError;
 use crate::cpu::operand::Operand;
 use crate::cpu::state::Cpu;
+#[cfg(test)]
 use crate::cpu::status::Status;
 
-pub fn lda(cpu: &mut Cpu, operand: Operand) {
-    let res = operand.read(cpu);
+pub fn lda<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.read(cpu)?;
     cpu.regs.a = res;
     cpu.regs.status_set_zn(res);
+    Ok(())
 }
 
 #[test]
@@ -13,29 +17,30 @@ fn test_lda() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x69;
     cpu.mem_write(0x200, 1);
-    lda(&mut cpu, Operand::Memory(0x200));
+    lda(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x69;
     cpu.mem_write(0x200, 0xFF);
-    lda(&mut cpu, Operand::Memory(0x200));
+    lda(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x69;
     cpu.mem_write(0x200, 0);
-    lda(&mut cpu, Operand::Memory(0x200));
+    lda(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 }
 
-pub fn ldx(cpu: &mut Cpu, operand: Operand) {
-    let res = operand.read(cpu);
+pub fn ldx<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.read(cpu)?;
     cpu.regs.x = res;
     cpu.regs.status_set_zn(res);
+    Ok(())
 }
 
 #[test]
@@ -43,29 +48,30 @@ fn test_ldx() {
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0x69;
     cpu.mem_write(0x200, 1);
-    ldx(&mut cpu, Operand::Memory(0x200));
+    ldx(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.x, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0x69;
     cpu.mem_write(0x200, 0xFF);
-    ldx(&mut cpu, Operand::Memory(0x200));
+    ldx(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.x, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0x69;
     cpu.mem_write(0x200, 0);
-    ldx(&mut cpu, Operand::Memory(0x200));
+    ldx(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.x, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 }
 
-pub fn ldy(cpu: &mut Cpu, operand: Operand) {
-    let res = operand.read(cpu);
+pub fn ldy<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.read(cpu)?;
     cpu.regs.y = res;
     cpu.regs.status_set_zn(res);
+    Ok(())
 }
 
 #[test]
@@ -73,27 +79,27 @@ fn test_ldy() {
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 0x69;
     cpu.mem_write(0x200, 1);
-    ldy(&mut cpu, Operand::Memory(0x200));
+    ldy(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.y, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 0x69;
     cpu.mem_write(0x200, 0xFF);
-    ldy(&mut cpu, Operand::Memory(0x200));
+    ldy(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.y, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 0x69;
     cpu.mem_write(0x200, 0);
-    ldy(&mut cpu, Operand::Memory(0x200));
+    ldy(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.regs.y, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 }
 
-pub fn sta(cpu: &mut Cpu, operand: Operand) {
-    operand.write(cpu, cpu.regs.a);
+pub fn sta<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.write(cpu, cpu.regs.a)
 }
 
 #[test]
@@ -101,12 +107,12 @@ fn test_sta() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 1;
     cpu.mem_write(0x200, 0x69);
-    sta(&mut cpu, Operand::Memory(0x200));
+    sta(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.mem_read(0x200), 1);
 }
 
-pub fn stx(cpu: &mut Cpu, operand: Operand) {
-    operand.write(cpu, cpu.regs.x);
+pub fn stx<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.write(cpu, cpu.regs.x)
 }
 
 #[test]
@@ -114,12 +120,12 @@ fn test_stx() {
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 1;
     cpu.mem_write(0x200, 0x69);
-    stx(&mut cpu, Operand::Memory(0x200));
+    stx(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.mem_read(0x200), 1);
 }
 
-pub fn sty(cpu: &mut Cpu, operand: Operand) {
-    operand.write(cpu, cpu.regs.y);
+pub fn sty<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.write(cpu, cpu.regs.y)
 }
 
 #[test]
@@ -127,6 +133,19 @@ fn test() {
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 1;
     cpu.mem_write(0x200, 0x69);
-    sty(&mut cpu, Operand::Memory(0x200));
+    sty(&mut cpu, Operand::Memory(0x200)).unwrap();
     assert_eq!(cpu.mem_read(0x200), 1);
 }
+
+pub fn stz<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.write(cpu, 0)
+}
+
+#[test]
+fn test_stz() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 1;
+    cpu.mem_write(0x200, 0x69);
+    stz(&mut cpu, Operand::Memory(0x200)).unwrap();
+    assert_eq!(cpu.mem_read(0x200), 0);
+}
@@ -1,145 +1,215 @@
+use crate::cpu::bus::Bus;
+use crate::cpu::error::CpuError;
 use crate::cpu::operand::Operand;
 use crate::cpu::state::Cpu;
 use crate::cpu::status::Status;
 
-pub fn and(cpu: &mut Cpu, operand: Operand) {
-    let res = cpu.regs.a & operand.read(cpu);
+pub fn and<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = cpu.regs.a & operand.read(cpu)?;
     cpu.regs.a = res;
     cpu.regs.status_set_zn(res);
+    Ok(())
 }
 
 #[test]
 fn test_and() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 1;
-    and(&mut cpu, Operand::Immediate(0));
+    and(&mut cpu, Operand::Immediate(0)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0;
-    and(&mut cpu, Operand::Immediate(1));
+    and(&mut cpu, Operand::Immediate(1)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x11;
-    and(&mut cpu, Operand::Immediate(0x10));
+    and(&mut cpu, Operand::Immediate(0x10)).unwrap();
     assert_eq!(cpu.regs.a, 0x10);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0x81;
-    and(&mut cpu, Operand::Immediate(0x80));
+    and(&mut cpu, Operand::Immediate(0x80)).unwrap();
     assert_eq!(cpu.regs.a, 0x80);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
 
-pub fn eor(cpu: &mut Cpu, operand: Operand) {
-    let res = cpu.regs.a ^ operand.read(cpu);
+pub fn eor<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = cpu.regs.a ^ operand.read(cpu)?;
     cpu.regs.a = res;
     cpu.regs.status_set_zn(res);
+    Ok(())
 }
 
 #[test]
 fn test_eor() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0;
-    eor(&mut cpu, Operand::Immediate(0xFF));
+    eor(&mut cpu, Operand::Immediate(0xFF)).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0b1111;
-    eor(&mut cpu, Operand::Immediate(0b1111_1111));
+    eor(&mut cpu, Operand::Immediate(0b1111_1111)).unwrap();
     assert_eq!(cpu.regs.a, 0b1111_0000);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0b1111_0000;
-    eor(&mut cpu, Operand::Immediate(0b1111_1111));
+    eor(&mut cpu, Operand::Immediate(0b1111_1111)).unwrap();
     assert_eq!(cpu.regs.a, 0b0000_1111);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
-    eor(&mut cpu, Operand::Immediate(0xFF));
+    eor(&mut cpu, Operand::Immediate(0xFF)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 }
 
-pub fn ora(cpu: &mut Cpu, operand: Operand) {
-    let res = cpu.regs.a | operand.read(cpu);
+pub fn ora<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = cpu.regs.a | operand.read(cpu)?;
     cpu.regs.a = res;
     cpu.regs.status_set_zn(res);
+    Ok(())
 }
 
 #[test]
 fn test_ora() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0;
-    ora(&mut cpu, Operand::Immediate(1));
+    ora(&mut cpu, Operand::Immediate(1)).unwrap();
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0;
-    ora(&mut cpu, Operand::Immediate(0xFF));
+    ora(&mut cpu, Operand::Immediate(0xFF)).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0b0000_1111;
-    ora(&mut cpu, Operand::Immediate(0b1111_0000));
+    ora(&mut cpu, Operand::Immediate(0b1111_0000)).unwrap();
     assert_eq!(cpu.regs.a, 0b1111_1111);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0;
-    ora(&mut cpu, Operand::Immediate(0));
+    ora(&mut cpu, Operand::Immediate(0)).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 }
 
-pub fn bit(cpu: &mut Cpu, operand: Operand) {
-    let v = operand.read(cpu);
+// The 65C02's BIT #imm only sets Z, since N and V would just reflect the
+// operand.
+pub fn bit<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let v = operand.read(cpu)?;
     cpu.regs.status_set(Status::Zero, cpu.regs.a & v == 0);
+    if let Operand::Immediate(_) = operand {
+        return Ok(());
+    }
     cpu.regs.status_set(Status::Overflow, v & 0b0100_0000 != 0);
     cpu.regs.status_set(Status::Negative, v & 0b1000_0000 != 0);
+    Ok(())
 }
 
 #[test]
 fn test_bit() {
     let mut cpu = Cpu::new_test();
     cpu.mem_write(0, 0);
-    bit(&mut cpu, Operand::Memory(0));
+    bit(&mut cpu, Operand::Memory(0)).unwrap();
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
     cpu.mem_write(0, 1);
-    bit(&mut cpu, Operand::Memory(0));
+    bit(&mut cpu, Operand::Memory(0)).unwrap();
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.mem_write(0, 0b0100_0000);
-    bit(&mut cpu, Operand::Memory(0));
+    bit(&mut cpu, Operand::Memory(0)).unwrap();
     assert_eq!(cpu.regs.p, Status::Zero.mask() | Status::Overflow.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
     cpu.mem_write(0, 0b0100_0000);
-    bit(&mut cpu, Operand::Memory(0));
+    bit(&mut cpu, Operand::Memory(0)).unwrap();
     assert_eq!(cpu.regs.p, Status::Overflow.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.mem_write(0, 0b1000_0000);
-    bit(&mut cpu, Operand::Memory(0));
+    bit(&mut cpu, Operand::Memory(0)).unwrap();
     assert_eq!(cpu.regs.p, Status::Zero.mask() | Status::Negative.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
     cpu.mem_write(0, 0b1000_0000);
-    bit(&mut cpu, Operand::Memory(0));
+    bit(&mut cpu, Operand::Memory(0)).unwrap();
     assert_eq!(cpu.regs.p, Status::Negative.mask());
+
+    // immediate mode leaves N and V alone
+    let mut cpu = Cpu::new_test();
+    cpu.regs.p = Status::Overflow.mask();
+    cpu.regs.a = 0x0F;
+    bit(&mut cpu, Operand::Immediate(0b1000_0000)).unwrap();
+    assert_eq!(cpu.regs.p, Status::Zero.mask() | Status::Overflow.mask());
+}
+
+// Sets Z from A & M, like BIT, then clears the bits of A in memory.
+pub fn trb<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.modify(cpu, |cpu, prev| {
+        cpu.regs.status_set(Status::Zero, cpu.regs.a & prev == 0);
+        prev & !cpu.regs.a
+    })?;
+    Ok(())
+}
+
+#[test]
+fn test_trb() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b0110;
+    cpu.mem_write(0, 0b1100);
+    trb(&mut cpu, Operand::Memory(0)).unwrap();
+    assert_eq!(cpu.mem_read(0), 0b1000);
+    assert_eq!(cpu.regs.p, 0);
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b0011;
+    cpu.mem_write(0, 0b1100);
+    trb(&mut cpu, Operand::Memory(0)).unwrap();
+    assert_eq!(cpu.mem_read(0), 0b1100);
+    assert_eq!(cpu.regs.p, Status::Zero.mask());
+}
+
+// Sets Z from A & M, like BIT, then sets the bits of A in memory.
+pub fn tsb<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.modify(cpu, |cpu, prev| {
+        cpu.regs.status_set(Status::Zero, cpu.regs.a & prev == 0);
+        prev | cpu.regs.a
+    })?;
+    Ok(())
+}
+
+#[test]
+fn test_tsb() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b0110;
+    cpu.mem_write(0, 0b1100);
+    tsb(&mut cpu, Operand::Memory(0)).unwrap();
+    assert_eq!(cpu.mem_read(0), 0b1110);
+    assert_eq!(cpu.regs.p, 0);
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b0011;
+    cpu.mem_write(0, 0b1100);
+    tsb(&mut cpu, Operand::Memory(0)).unwrap();
+    assert_eq!(cpu.mem_read(0), 0b1111);
+    assert_eq!(cpu.regs.p, Status::Zero.mask());
 }
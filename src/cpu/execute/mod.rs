@@ -1,3 +1,5 @@
+use super::bus::Bus;
+use super::error::CpuError;
 use super::opcode;
 use super::operand::Operand;
 use super::state::Cpu;
@@ -13,8 +15,14 @@ mod stack;
 mod status;
 mod system;
 mod transfer;
+mod unofficial;
 
-pub fn execute(opcode_type: opcode::Type, cpu: &mut Cpu, operand: Operand) {
+#[inline(always)]
+pub fn execute<B: Bus>(
+    opcode_type: opcode::Type,
+    cpu: &mut Cpu<B>,
+    operand: Operand,
+) -> Result<(), CpuError> {
     match opcode_type {
         opcode::Type::Adc => arithmetic::adc(cpu, operand),
         opcode::Type::And => logic::and(cpu, operand),
@@ -72,5 +80,33 @@ pub fn execute(opcode_type: opcode::Type, cpu: &mut Cpu, operand: Operand) {
         opcode::Type::Txa => transfer::txa(cpu, operand),
         opcode::Type::Txs => stack::txs(cpu, operand),
         opcode::Type::Tya => transfer::tya(cpu, operand),
+        opcode::Type::Bra => branch::bra(cpu, operand),
+        opcode::Type::Phx => stack::phx(cpu, operand),
+        opcode::Type::Phy => stack::phy(cpu, operand),
+        opcode::Type::Plx => stack::plx(cpu, operand),
+        opcode::Type::Ply => stack::ply(cpu, operand),
+        opcode::Type::Stz => loadstore::stz(cpu, operand),
+        opcode::Type::Trb => logic::trb(cpu, operand),
+        opcode::Type::Tsb => logic::tsb(cpu, operand),
+        opcode::Type::Alr => unofficial::alr(cpu, operand),
+        opcode::Type::Anc => unofficial::anc(cpu, operand),
+        opcode::Type::Arr => unofficial::arr(cpu, operand),
+        opcode::Type::Axs => unofficial::axs(cpu, operand),
+        opcode::Type::Dcp => unofficial::dcp(cpu, operand),
+        opcode::Type::Isc => unofficial::isc(cpu, operand),
+        opcode::Type::Kil => unofficial::kil(cpu, operand),
+        opcode::Type::Las => unofficial::las(cpu, operand),
+        opcode::Type::Lax => unofficial::lax(cpu, operand),
+        opcode::Type::Lxa => unofficial::lxa(cpu, operand),
+        opcode::Type::Rla => unofficial::rla(cpu, operand),
+        opcode::Type::Rra => unofficial::rra(cpu, operand),
+        opcode::Type::Sax => unofficial::sax(cpu, operand),
+        opcode::Type::Sha => unofficial::sha(cpu, operand),
+        opcode::Type::Shx => unofficial::shx(cpu, operand),
+        opcode::Type::Shy => unofficial::shy(cpu, operand),
+        opcode::Type::Slo => unofficial::slo(cpu, operand),
+        opcode::Type::Sre => unofficial::sre(cpu, operand),
+        opcode::Type::Tas => unofficial::tas(cpu, operand),
+        opcode::Type::Xaa => unofficial::xaa(cpu, operand),
     }
 }
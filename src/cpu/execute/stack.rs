@@ -1,102 +1,180 @@
+use super::super::bus::Bus;
+use super::super::error::CpuError;
 use super::super::operand::Operand;
 use super::super::state::Cpu;
+#[cfg(test)]
 use super::super::status::Status;
 
-pub fn tsx(cpu: &mut Cpu, _operand: Operand) {
+pub fn tsx<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.x = cpu.regs.s;
     cpu.regs.status_set_zn(cpu.regs.x);
+    Ok(())
 }
 
 #[test]
 fn test_tsx() {
     let mut cpu = Cpu::new_test();
     cpu.regs.s = 1;
-    tsx(&mut cpu, Operand::None);
+    tsx(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.x, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.s = 0;
-    tsx(&mut cpu, Operand::None);
+    tsx(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.x, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.s = 0xFF;
-    tsx(&mut cpu, Operand::None);
+    tsx(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.x, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
 
-pub fn txs(cpu: &mut Cpu, _operand: Operand) {
+pub fn txs<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.s = cpu.regs.x;
+    Ok(())
 }
 
 #[test]
 fn test_txs() {
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 1;
-    txs(&mut cpu, Operand::None);
+    txs(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.s, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0;
-    txs(&mut cpu, Operand::None);
+    txs(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.s, 0);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0xFF;
-    txs(&mut cpu, Operand::None);
+    txs(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.s, 0xFF);
     assert_eq!(cpu.regs.p, 0);
 }
 
-pub fn pha(cpu: &mut Cpu, _operand: Operand) {
-    cpu.stack_push(cpu.regs.a);
+pub fn pha<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.stack_push(cpu.regs.a)
 }
 
 #[test]
 fn test_pha() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 1;
-    pha(&mut cpu, Operand::None);
+    pha(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.stack_peek(0), 1);
 }
 
-pub fn php(cpu: &mut Cpu, _operand: Operand) {
-    cpu.stack_push(cpu.regs.p);
+pub fn php<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.stack_push(cpu.regs.p)
 }
 
 #[test]
 fn test_php() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = 1;
-    php(&mut cpu, Operand::None);
+    php(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.stack_peek(0), 1);
 }
 
-pub fn pla(cpu: &mut Cpu, _operand: Operand) {
-    cpu.regs.a = cpu.stack_pop();
+// Pulls read from the stack once before incrementing the stack pointer.
+pub fn pla<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    cpu.regs.a = cpu.stack_pop()?;
+    Ok(())
 }
 
 #[test]
 fn test_pla() {
     let mut cpu = Cpu::new_test();
-    cpu.stack_push(1);
-    pla(&mut cpu, Operand::None);
+    cpu.stack_push(1).unwrap();
+    pla(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 1);
 }
 
-pub fn plp(cpu: &mut Cpu, _operand: Operand) {
-    cpu.regs.p = cpu.stack_pop();
+pub fn plp<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    cpu.regs.p = cpu.stack_pop()?;
+    Ok(())
 }
 
 #[test]
 fn test_plp() {
     let mut cpu = Cpu::new_test();
-    cpu.stack_push(1);
-    plp(&mut cpu, Operand::None);
+    cpu.stack_push(1).unwrap();
+    plp(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, 1);
 }
+
+pub fn phx<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.stack_push(cpu.regs.x)
+}
+
+#[test]
+fn test_phx() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.x = 1;
+    phx(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.stack_peek(0), 1);
+}
+
+pub fn phy<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.stack_push(cpu.regs.y)
+}
+
+#[test]
+fn test_phy() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.y = 1;
+    phy(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.stack_peek(0), 1);
+}
+
+pub fn plx<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    cpu.regs.x = cpu.stack_pop()?;
+    cpu.regs.status_set_zn(cpu.regs.x);
+    Ok(())
+}
+
+#[test]
+fn test_plx() {
+    let mut cpu = Cpu::new_test();
+    cpu.stack_push(1).unwrap();
+    plx(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.regs.x, 1);
+    assert_eq!(cpu.regs.p, 0);
+
+    let mut cpu = Cpu::new_test();
+    cpu.stack_push(0x80).unwrap();
+    plx(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.regs.x, 0x80);
+    assert_eq!(cpu.regs.p, Status::Negative.mask());
+}
+
+pub fn ply<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    cpu.regs.y = cpu.stack_pop()?;
+    cpu.regs.status_set_zn(cpu.regs.y);
+    Ok(())
+}
+
+#[test]
+fn test_ply() {
+    let mut cpu = Cpu::new_test();
+    cpu.stack_push(1).unwrap();
+    ply(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.regs.y, 1);
+    assert_eq!(cpu.regs.p, 0);
+
+    let mut cpu = Cpu::new_test();
+    cpu.stack_push(0).unwrap();
+    ply(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.regs.y, 0);
+    assert_eq!(cpu.regs.p, Status::Zero.mask());
+}
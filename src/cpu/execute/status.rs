@@ -1,84 +1,93 @@
+use super::super::bus::Bus;
+use super::super::error::CpuError;
 use super::super::operand::Operand;
 use super::super::state::Cpu;
 use super::super::status::Status;
 
-pub fn clc(cpu: &mut Cpu, _operand: Operand) {
+pub fn clc<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::Carry, false);
+    Ok(())
 }
 
 #[test]
 fn test_clc() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::Carry.mask();
-    clc(&mut cpu, Operand::None);
+    clc(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, 0);
 }
 
-pub fn cld(cpu: &mut Cpu, _operand: Operand) {
+pub fn cld<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::DecimalMode, false);
+    Ok(())
 }
 
 #[test]
 fn test_cld() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::DecimalMode.mask();
-    cld(&mut cpu, Operand::None);
+    cld(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, 0);
 }
 
-pub fn cli(cpu: &mut Cpu, _operand: Operand) {
+pub fn cli<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::InterruptDisable, false);
+    Ok(())
 }
 
 #[test]
 fn test_cli() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::InterruptDisable.mask();
-    cli(&mut cpu, Operand::None);
+    cli(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, 0);
 }
 
-pub fn clv(cpu: &mut Cpu, _operand: Operand) {
+pub fn clv<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::Overflow, false);
+    Ok(())
 }
 
 #[test]
 fn test_clv() {
     let mut cpu = Cpu::new_test();
     cpu.regs.p = Status::Overflow.mask();
-    clv(&mut cpu, Operand::None);
+    clv(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, 0);
 }
 
-pub fn sec(cpu: &mut Cpu, _operand: Operand) {
+pub fn sec<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::Carry, true);
+    Ok(())
 }
 
 #[test]
 fn test_sec() {
     let mut cpu = Cpu::new_test();
-    sec(&mut cpu, Operand::None);
+    sec(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, Status::Carry.mask());
 }
 
-pub fn sed(cpu: &mut Cpu, _operand: Operand) {
+pub fn sed<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::DecimalMode, true);
+    Ok(())
 }
 
 #[test]
 fn test_sed() {
     let mut cpu = Cpu::new_test();
-    sed(&mut cpu, Operand::None);
+    sed(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, Status::DecimalMode.mask());
 }
 
-pub fn sei(cpu: &mut Cpu, _operand: Operand) {
+pub fn sei<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.status_set(Status::InterruptDisable, true);
+    Ok(())
 }
 
 #[test]
 fn test_sei() {
     let mut cpu = Cpu::new_test();
-    sei(&mut cpu, Operand::None);
+    sei(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.p, Status::InterruptDisable.mask());
 }
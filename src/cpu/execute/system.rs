@@ -1,12 +1,18 @@
+use super::super::bus::Bus;
+use super::super::error::CpuError;
 use super::super::operand::Operand;
-use super::super::state::Cpu;
+use super::super::state::{Cpu, Variant};
 use super::super::status::Status;
 
-pub fn brk(cpu: &mut Cpu, _operand: Operand) {
-    cpu.stack_push16(cpu.regs.pc);
-    cpu.stack_push(cpu.regs.p);
+pub fn brk<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.stack_push16(cpu.regs.pc)?;
+    cpu.stack_push(cpu.regs.p)?;
     cpu.regs.status_set(Status::BreakCommand, true);
-    cpu.regs.pc = cpu.mem_read16(0xFFFE);
+    if cpu.variant == Variant::Cmos65C02 {
+        cpu.regs.status_set(Status::DecimalMode, false);
+    }
+    cpu.regs.pc = cpu.bus_read16(0xFFFE)?;
+    Ok(())
 }
 
 #[test]
@@ -16,28 +22,44 @@ fn test_brk() {
     cpu.regs.p = 0b1000_0001;
     cpu.mem_write(0xFFFE, 0xFF);
     cpu.mem_write(0xFFFF, 0x02);
-    brk(&mut cpu, Operand::None);
+    brk(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.pc, 0x2FF);
     assert_eq!(cpu.regs.p, 0b1001_0001);
     assert_eq!(cpu.stack_peek(0), 0b1000_0001);
     assert_eq!(cpu.stack_peek16(1), 0x201);
+
+    // the 65C02 clears the decimal flag
+    let mut cpu = Cpu::new_test();
+    cpu.variant = Variant::Cmos65C02;
+    cpu.regs.p = Status::DecimalMode.mask();
+    brk(&mut cpu, Operand::None).unwrap();
+    assert_eq!(cpu.regs.p, Status::BreakCommand.mask());
+    assert_eq!(cpu.stack_peek(0), Status::DecimalMode.mask());
 }
 
-pub fn rti(cpu: &mut Cpu, _operand: Operand) {
-    cpu.regs.p = cpu.stack_pop();
-    cpu.regs.pc = cpu.stack_pop16();
+pub fn rti<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.bus_dummy_read(cpu.stack_pointer());
+    cpu.regs.p = cpu.stack_pop()?;
+    cpu.regs.pc = cpu.stack_pop16()?;
+    Ok(())
 }
 
 #[test]
 fn test_rti() {
     let mut cpu = Cpu::new_test();
-    cpu.stack_push(0x69); // sentinel
-    cpu.stack_push16(0x200); // PC
-    cpu.stack_push(1); // status
-    rti(&mut cpu, Operand::None);
+    cpu.stack_push(0x69).unwrap(); // sentinel
+    cpu.stack_push16(0x200).unwrap(); // PC
+    cpu.stack_push(1).unwrap(); // status
+    rti(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.pc, 0x200);
     assert_eq!(cpu.regs.p, 1);
     assert_eq!(cpu.stack_peek(0), 0x69);
 }
 
-pub fn nop(_cpu: &mut Cpu, _operand: Operand) {}
+// Unofficial NOPs with a memory operand read it, like LDA.
+pub fn nop<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    if let Operand::Memory(_) = operand {
+        operand.read(cpu)?;
+    }
+    Ok(())
+}
@@ -1,105 +1,112 @@
+use super::super::bus::Bus;
+use super::super::error::CpuError;
 use super::super::operand::Operand;
 use super::super::state::Cpu;
+#[cfg(test)]
 use super::super::status::Status;
 
-pub fn tax(cpu: &mut Cpu, _operand: Operand) {
+pub fn tax<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.x = cpu.regs.a;
     cpu.regs.status_set_zn(cpu.regs.x);
+    Ok(())
 }
 
 #[test]
 fn test_tax() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 1;
-    tax(&mut cpu, Operand::None);
+    tax(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.x, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
-    tax(&mut cpu, Operand::None);
+    tax(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.x, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
-    tax(&mut cpu, Operand::None);
+    tax(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.x, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
 
-pub fn tay(cpu: &mut Cpu, _operand: Operand) {
+pub fn tay<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.y = cpu.regs.a;
     cpu.regs.status_set_zn(cpu.regs.y);
+    Ok(())
 }
 
 #[test]
 fn test_tay() {
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 1;
-    tay(&mut cpu, Operand::None);
+    tay(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.y, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
-    tay(&mut cpu, Operand::None);
+    tay(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.y, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.a = 0xFF;
-    tay(&mut cpu, Operand::None);
+    tay(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.y, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
 
-pub fn txa(cpu: &mut Cpu, _operand: Operand) {
+pub fn txa<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.a = cpu.regs.x;
     cpu.regs.status_set_zn(cpu.regs.a);
+    Ok(())
 }
 
 #[test]
 fn test_txa() {
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 1;
-    txa(&mut cpu, Operand::None);
+    txa(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0;
-    txa(&mut cpu, Operand::None);
+    txa(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 0xFF;
-    txa(&mut cpu, Operand::None);
+    txa(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
 
-pub fn tya(cpu: &mut Cpu, _operand: Operand) {
+pub fn tya<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
     cpu.regs.a = cpu.regs.y;
     cpu.regs.status_set_zn(cpu.regs.a);
+    Ok(())
 }
 
 #[test]
 fn test_tya() {
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 1;
-    tya(&mut cpu, Operand::None);
+    tya(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, 0);
 
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 0;
-    tya(&mut cpu, Operand::None);
+    tya(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(cpu.regs.p, Status::Zero.mask());
 
     let mut cpu = Cpu::new_test();
     cpu.regs.y = 0xFF;
-    tya(&mut cpu, Operand::None);
+    tya(&mut cpu, Operand::None).unwrap();
     assert_eq!(cpu.regs.a, 0xFF);
     assert_eq!(cpu.regs.p, Status::Negative.mask());
 }
@@ -0,0 +1,459 @@
+// Undocumented opcodes. Most combine two official operations, sharing a
+// single operand fetch.
+// Reference: https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+
+use super::arithmetic;
+use super::logic;
+use crate::cpu::bus::Bus;
+use crate::cpu::error::CpuError;
+use crate::cpu::operand::Operand;
+use crate::cpu::state::Cpu;
+use crate::cpu::status::Status;
+use crate::math;
+
+// The unstable opcodes OR the accumulator with a chip-dependent constant
+// before combining it with other values. This is the value most commonly
+// observed on NES consoles.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+pub fn alr<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let prev = cpu.regs.a & operand.read(cpu)?;
+    let res = prev >> 1;
+    cpu.regs.a = res;
+    cpu.regs.status_set_zn(res);
+    cpu.regs.status_set(Status::Carry, prev & 1 != 0);
+    Ok(())
+}
+
+#[test]
+fn test_alr() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b1000_0011;
+    alr(&mut cpu, Operand::Immediate(0b1000_0001)).unwrap();
+    assert_eq!(cpu.regs.a, 0b0100_0000);
+    assert_eq!(cpu.regs.p, Status::Carry.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b10;
+    alr(&mut cpu, Operand::Immediate(0b01)).unwrap();
+    assert_eq!(cpu.regs.a, 0);
+    assert_eq!(cpu.regs.p, Status::Zero.mask());
+}
+
+pub fn anc<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    logic::and(cpu, operand)?;
+    let negative = cpu.regs.status_check(Status::Negative);
+    cpu.regs.status_set(Status::Carry, negative);
+    Ok(())
+}
+
+#[test]
+fn test_anc() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0xF0;
+    anc(&mut cpu, Operand::Immediate(0x80)).unwrap();
+    assert_eq!(cpu.regs.a, 0x80);
+    assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Negative.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0xF0;
+    cpu.regs.p = Status::Carry.mask();
+    anc(&mut cpu, Operand::Immediate(0x70)).unwrap();
+    assert_eq!(cpu.regs.a, 0x70);
+    assert_eq!(cpu.regs.p, 0);
+}
+
+// Like AND followed by ROR, except that carry comes from bit 6 of the result,
+// and overflow from bit 6 XOR bit 5.
+pub fn arr<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let carry = if cpu.regs.status_check(Status::Carry) {
+        0b1000_0000
+    } else {
+        0
+    };
+    let res = (cpu.regs.a & operand.read(cpu)?) >> 1 | carry;
+    cpu.regs.a = res;
+    cpu.regs.status_set_zn(res);
+    cpu.regs.status_set(Status::Carry, res & 0b0100_0000 != 0);
+    cpu.regs
+        .status_set(Status::Overflow, (res >> 6 ^ res >> 5) & 1 != 0);
+    Ok(())
+}
+
+#[test]
+fn test_arr() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0xFF;
+    cpu.regs.p = Status::Carry.mask();
+    arr(&mut cpu, Operand::Immediate(0xFF)).unwrap();
+    assert_eq!(cpu.regs.a, 0xFF);
+    assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Negative.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0xFF;
+    arr(&mut cpu, Operand::Immediate(0b1000_0000)).unwrap();
+    assert_eq!(cpu.regs.a, 0b0100_0000);
+    assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Overflow.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0xFF;
+    arr(&mut cpu, Operand::Immediate(0b0100_0000)).unwrap();
+    assert_eq!(cpu.regs.a, 0b0010_0000);
+    assert_eq!(cpu.regs.p, Status::Overflow.mask());
+
+    let mut cpu = Cpu::new_test();
+    arr(&mut cpu, Operand::Immediate(0xFF)).unwrap();
+    assert_eq!(cpu.regs.a, 0);
+    assert_eq!(cpu.regs.p, Status::Zero.mask());
+}
+
+// Carry is set as in CMP, and overflow is unaffected.
+pub fn axs<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let ax = cpu.regs.a & cpu.regs.x;
+    let opval = operand.read(cpu)?;
+    let res = ax.wrapping_sub(opval);
+    cpu.regs.x = res;
+    cpu.regs.status_set_zn(res);
+    cpu.regs.status_set(Status::Carry, ax >= opval);
+    Ok(())
+}
+
+#[test]
+fn test_axs() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0x0F;
+    cpu.regs.x = 0xFC;
+    axs(&mut cpu, Operand::Immediate(0x02)).unwrap();
+    assert_eq!(cpu.regs.x, 0x0A);
+    assert_eq!(cpu.regs.p, Status::Carry.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0x0F;
+    cpu.regs.x = 0x01;
+    cpu.regs.p = Status::Overflow.mask();
+    axs(&mut cpu, Operand::Immediate(0x02)).unwrap();
+    assert_eq!(cpu.regs.x, 0xFF);
+    assert_eq!(
+        cpu.regs.p,
+        Status::Overflow.mask() | Status::Negative.mask()
+    );
+}
+
+pub fn dcp<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.modify(cpu, |_, prev| prev.wrapping_sub(1))?;
+    arithmetic::cmp(cpu, Operand::Immediate(res))
+}
+
+#[test]
+fn test_dcp() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 4;
+    cpu.mem_write(0x10, 5);
+    dcp(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 4);
+    assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 4;
+    dcp(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0xFF);
+    assert_eq!(cpu.regs.p, 0);
+}
+
+pub fn isc<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.modify(cpu, |_, prev| prev.wrapping_add(1))?;
+    arithmetic::sbc(cpu, Operand::Immediate(res))
+}
+
+#[test]
+fn test_isc() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 5;
+    cpu.regs.p = Status::Carry.mask();
+    cpu.mem_write(0x10, 2);
+    isc(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 3);
+    assert_eq!(cpu.regs.a, 2);
+    assert_eq!(cpu.regs.p, Status::Carry.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 5;
+    cpu.regs.p = Status::Carry.mask();
+    cpu.mem_write(0x10, 0xFF);
+    isc(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0);
+    assert_eq!(cpu.regs.a, 5);
+    assert_eq!(cpu.regs.p, Status::Carry.mask());
+}
+
+// Jams the CPU. Only a reset will recover it.
+pub fn kil<B: Bus>(cpu: &mut Cpu<B>, _operand: Operand) -> Result<(), CpuError> {
+    cpu.halted = true;
+    Err(CpuError::Halted {
+        pc: cpu.regs.pc.wrapping_sub(1),
+    })
+}
+
+#[test]
+fn test_kil() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.pc = 0x11;
+    assert_eq!(
+        kil(&mut cpu, Operand::None),
+        Err(CpuError::Halted { pc: 0x10 })
+    );
+    assert!(cpu.halted);
+}
+
+pub fn las<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.read(cpu)? & cpu.regs.s;
+    cpu.regs.a = res;
+    cpu.regs.x = res;
+    cpu.regs.s = res;
+    cpu.regs.status_set_zn(res);
+    Ok(())
+}
+
+#[test]
+fn test_las() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.s = 0xF0;
+    cpu.mem_write(0x10, 0x9F);
+    las(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.regs.a, 0x90);
+    assert_eq!(cpu.regs.x, 0x90);
+    assert_eq!(cpu.regs.s, 0x90);
+    assert_eq!(cpu.regs.p, Status::Negative.mask());
+}
+
+pub fn lax<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.read(cpu)?;
+    cpu.regs.a = res;
+    cpu.regs.x = res;
+    cpu.regs.status_set_zn(res);
+    Ok(())
+}
+
+#[test]
+fn test_lax() {
+    let mut cpu = Cpu::new_test();
+    cpu.mem_write(0x10, 0x80);
+    lax(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.regs.a, 0x80);
+    assert_eq!(cpu.regs.x, 0x80);
+    assert_eq!(cpu.regs.p, Status::Negative.mask());
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 1;
+    cpu.regs.x = 1;
+    lax(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.regs.a, 0);
+    assert_eq!(cpu.regs.x, 0);
+    assert_eq!(cpu.regs.p, Status::Zero.mask());
+}
+
+pub fn lxa<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = (cpu.regs.a | UNSTABLE_MAGIC) & operand.read(cpu)?;
+    cpu.regs.a = res;
+    cpu.regs.x = res;
+    cpu.regs.status_set_zn(res);
+    Ok(())
+}
+
+#[test]
+fn test_lxa() {
+    let mut cpu = Cpu::new_test();
+    lxa(&mut cpu, Operand::Immediate(0x0F)).unwrap();
+    assert_eq!(cpu.regs.a, UNSTABLE_MAGIC & 0x0F);
+    assert_eq!(cpu.regs.x, UNSTABLE_MAGIC & 0x0F);
+    assert_eq!(cpu.regs.p, 0);
+}
+
+pub fn rla<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.modify(cpu, |cpu, prev| {
+        let res = (prev << 1)
+            | if cpu.regs.status_check(Status::Carry) {
+                1
+            } else {
+                0
+            };
+        cpu.regs.status_set(Status::Carry, prev & 0b1000_0000 != 0);
+        res
+    })?;
+    logic::and(cpu, Operand::Immediate(res))
+}
+
+#[test]
+fn test_rla() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0xFF;
+    cpu.regs.p = Status::Carry.mask();
+    cpu.mem_write(0x10, 0b1000_0000);
+    rla(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 1);
+    assert_eq!(cpu.regs.a, 1);
+    assert_eq!(cpu.regs.p, Status::Carry.mask());
+}
+
+pub fn rra<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.modify(cpu, |cpu, prev| {
+        let res = prev >> 1
+            | if cpu.regs.status_check(Status::Carry) {
+                0b1000_0000
+            } else {
+                0
+            };
+        cpu.regs.status_set(Status::Carry, prev & 1 != 0);
+        res
+    })?;
+    arithmetic::adc(cpu, Operand::Immediate(res))
+}
+
+#[test]
+fn test_rra() {
+    // carry from the rotate feeds the add
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 1;
+    cpu.mem_write(0x10, 0b101);
+    rra(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0b10);
+    assert_eq!(cpu.regs.a, 4);
+    assert_eq!(cpu.regs.p, 0);
+}
+
+pub fn sax<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    operand.write(cpu, cpu.regs.a & cpu.regs.x)
+}
+
+#[test]
+fn test_sax() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0b1100;
+    cpu.regs.x = 0b1010;
+    sax(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0b1000);
+    assert_eq!(cpu.regs.p, 0);
+}
+
+// The SH* stores AND the value with the high byte of the base address plus
+// one. If indexing crossed a page, the value also replaces the high byte of
+// the target address.
+fn store_high<B: Bus>(
+    cpu: &mut Cpu<B>,
+    operand: Operand,
+    index: u8,
+    val: u8,
+) -> Result<(), CpuError> {
+    let addr = operand.address()?;
+    let base = addr.wrapping_sub(index as u16);
+    let res = val & ((base >> 8) as u8).wrapping_add(1);
+    let addr = if math::page_crossing(base, addr) {
+        (res as u16) << 8 | addr & 0xFF
+    } else {
+        addr
+    };
+    cpu.bus_write(addr, res)
+}
+
+pub fn sha<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    store_high(cpu, operand, cpu.regs.y, cpu.regs.a & cpu.regs.x)
+}
+
+pub fn shx<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    store_high(cpu, operand, cpu.regs.y, cpu.regs.x)
+}
+
+pub fn shy<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    store_high(cpu, operand, cpu.regs.x, cpu.regs.y)
+}
+
+pub fn tas<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    cpu.regs.s = cpu.regs.a & cpu.regs.x;
+    store_high(cpu, operand, cpu.regs.y, cpu.regs.s)
+}
+
+#[test]
+fn test_store_high() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.x = 0xFF;
+    cpu.regs.y = 0x01;
+    shx(&mut cpu, Operand::Memory(0x0201)).unwrap();
+    assert_eq!(cpu.mem_read(0x0201), 0x03);
+
+    // page crossing
+    let mut cpu = Cpu::new_test();
+    cpu.regs.y = 0x03;
+    cpu.regs.x = 0x02;
+    shy(&mut cpu, Operand::Memory(0x0501)).unwrap();
+    assert_eq!(cpu.mem_read(0x0501), 0);
+    assert_eq!(cpu.mem_read(0x0101), 0x01);
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0x0F;
+    cpu.regs.x = 0xFC;
+    cpu.regs.y = 0x01;
+    tas(&mut cpu, Operand::Memory(0x0701)).unwrap();
+    assert_eq!(cpu.regs.s, 0x0C);
+    assert_eq!(cpu.mem_read(0x0701), 0x08);
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 0x0F;
+    cpu.regs.x = 0xFC;
+    cpu.regs.y = 0x01;
+    sha(&mut cpu, Operand::Memory(0x0701)).unwrap();
+    assert_eq!(cpu.mem_read(0x0701), 0x08);
+}
+
+pub fn slo<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.modify(cpu, |cpu, prev| {
+        cpu.regs.status_set(Status::Carry, prev & 0b1000_0000 != 0);
+        prev << 1
+    })?;
+    logic::ora(cpu, Operand::Immediate(res))
+}
+
+#[test]
+fn test_slo() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 1;
+    cpu.mem_write(0x10, 0b1100_0000);
+    slo(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0b1000_0000);
+    assert_eq!(cpu.regs.a, 0b1000_0001);
+    assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Negative.mask());
+}
+
+pub fn sre<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = operand.modify(cpu, |cpu, prev| {
+        cpu.regs.status_set(Status::Carry, prev & 1 != 0);
+        prev >> 1
+    })?;
+    logic::eor(cpu, Operand::Immediate(res))
+}
+
+#[test]
+fn test_sre() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.a = 1;
+    cpu.mem_write(0x10, 0b11);
+    sre(&mut cpu, Operand::Memory(0x10)).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 1);
+    assert_eq!(cpu.regs.a, 0);
+    assert_eq!(cpu.regs.p, Status::Carry.mask() | Status::Zero.mask());
+}
+
+pub fn xaa<B: Bus>(cpu: &mut Cpu<B>, operand: Operand) -> Result<(), CpuError> {
+    let res = (cpu.regs.a | UNSTABLE_MAGIC) & cpu.regs.x & operand.read(cpu)?;
+    cpu.regs.a = res;
+    cpu.regs.status_set_zn(res);
+    Ok(())
+}
+
+#[test]
+fn test_xaa() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.x = 0xF0;
+    xaa(&mut cpu, Operand::Immediate(0xFF)).unwrap();
+    assert_eq!(cpu.regs.a, UNSTABLE_MAGIC & 0xF0);
+    assert_eq!(cpu.regs.p, Status::Negative.mask());
+}
@@ -1,8 +1,22 @@
 mod address_mode;
-mod assemble;
+pub mod assemble;
+mod bus;
+pub mod disassemble;
+mod dispatch;
+mod error;
 mod execute;
 mod opcode;
 mod operand;
 mod state;
 mod status;
 mod step;
+mod trace;
+
+pub use bus::{Bus, BusAccess, NesBus, RamBus};
+pub use dispatch::Dispatch;
+pub use error::{Access, CpuError};
+pub use operand::Operand;
+pub use state::{Cpu, Registers, Variant};
+pub use status::Status;
+pub use step::Interrupt;
+pub use trace::{trace_line, Trace};
@@ -1,5 +1,5 @@
 use super::address_mode::AddressMode;
-use std::collections::{HashMap, HashSet};
+use super::state::Variant;
 
 // Reference: http://obelisk.me.uk/6502/reference.html
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
@@ -60,113 +60,204 @@ pub enum Type {
     Txa, // Transfer stack pointer to accumulator
     Txs, // Transfer X to stack pointer
     Tya, // Transfer Y to accumulator
+
+    // 65C02
+    Bra, // Branch always
+    Phx, // Push X register
+    Phy, // Push Y register
+    Plx, // Pull X register
+    Ply, // Pull Y register
+    Stz, // Store zero
+    Trb, // Test and reset bits
+    Tsb, // Test and set bits
+
+    // Unofficial
+    Alr, // And, then logical shift right
+    Anc, // And, then copy negative flag to carry
+    Arr, // And, then rotate right
+    Axs, // Store A & X minus operand to X
+    Dcp, // Decrement memory, then compare
+    Isc, // Increment memory, then subtract with carry
+    Kil, // Halt the CPU
+    Las, // Load A, X and stack pointer with memory & stack pointer
+    Lax, // Load accumulator and X register
+    Lxa, // Load A and X with (A | magic) & operand; unstable
+    Rla, // Rotate left, then and
+    Rra, // Rotate right, then add with carry
+    Sax, // Store A & X
+    Sha, // Store A & X & (high byte + 1); unstable
+    Shx, // Store X & (high byte + 1); unstable
+    Shy, // Store Y & (high byte + 1); unstable
+    Slo, // Arithmetic shift left, then inclusive or
+    Sre, // Logical shift right, then exclusive or
+    Tas, // Set stack pointer to A & X, then SHA; unstable
+    Xaa, // Load A with (A | magic) & X & operand; unstable
 }
 
 impl Type {
     pub fn writes_memory(self) -> bool {
-        match self {
-            Self::Asl | Self::Dec | Self::Inc | Self::Rol | Self::Ror | Self::Sta => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Self::Asl
+                | Self::Dec
+                | Self::Inc
+                | Self::Lsr
+                | Self::Rol
+                | Self::Ror
+                | Self::Sta
+                | Self::Stz
+                | Self::Trb
+                | Self::Tsb
+                | Self::Dcp
+                | Self::Isc
+                | Self::Rla
+                | Self::Rra
+                | Self::Sax
+                | Self::Sha
+                | Self::Shx
+                | Self::Shy
+                | Self::Slo
+                | Self::Sre
+                | Self::Tas
+        )
     }
 
-    pub fn compatible_with(self, addr_mode: AddressMode) -> bool {
-        lazy_static! {
-            static ref ADDRESS_MODES_BY_OPCODE_TYPE: HashMap<Type, HashSet<AddressMode>> = {
-                let mut map: HashMap<Type, HashSet<AddressMode>> = HashMap::new();
-                for opcode in OPCODES.iter() {
-                    let mut set = if let Some(existing) = map.get(&opcode.opcode_type) {
-                        existing.clone()
-                    } else {
-                        HashSet::new()
-                    };
-                    set.insert(opcode.addr_mode);
-                    map.insert(opcode.opcode_type, set);
-                }
-                map
-            };
-        }
+    pub fn is_shift(self) -> bool {
+        matches!(self, Self::Asl | Self::Lsr | Self::Rol | Self::Ror)
+    }
 
-        ADDRESS_MODES_BY_OPCODE_TYPE
-            .get(&self)
-            .unwrap()
-            .contains(&addr_mode)
+    pub fn compatible_with(self, variant: Variant, addr_mode: AddressMode) -> bool {
+        decode_table_for(variant)
+            .iter()
+            .flatten()
+            .any(|&(t, m, _)| t == self && m == addr_mode)
     }
 
-    pub fn is_jump(self) -> bool {
-        self.compatible_with(AddressMode::Indirect)
+    pub fn is_jump(self, variant: Variant) -> bool {
+        self.compatible_with(variant, AddressMode::Indirect)
     }
 
     pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
-        match mnemonic.to_ascii_uppercase().as_str() {
-            "ADC" => Some(Self::Adc),
-            "AND" => Some(Self::And),
-            "ASL" => Some(Self::Asl),
-            "BCC" => Some(Self::Bcc),
-            "BCS" => Some(Self::Bcs),
-            "BEQ" => Some(Self::Beq),
-            "BIT" => Some(Self::Bit),
-            "BMI" => Some(Self::Bmi),
-            "BNE" => Some(Self::Bne),
-            "BPL" => Some(Self::Bpl),
-            "BRK" => Some(Self::Brk),
-            "BVC" => Some(Self::Bvc),
-            "BVS" => Some(Self::Bvs),
-            "CLC" => Some(Self::Clc),
-            "CLD" => Some(Self::Cld),
-            "CLI" => Some(Self::Cli),
-            "CLV" => Some(Self::Clv),
-            "CMP" => Some(Self::Cmp),
-            "CPX" => Some(Self::Cpx),
-            "CPY" => Some(Self::Cpy),
-            "DEC" => Some(Self::Dec),
-            "DEX" => Some(Self::Dex),
-            "DEY" => Some(Self::Dey),
-            "EOR" => Some(Self::Eor),
-            "INC" => Some(Self::Inc),
-            "INX" => Some(Self::Inx),
-            "INY" => Some(Self::Iny),
-            "JMP" => Some(Self::Jmp),
-            "JSR" => Some(Self::Jsr),
-            "LDA" => Some(Self::Lda),
-            "LDX" => Some(Self::Ldx),
-            "LDY" => Some(Self::Ldy),
-            "LSR" => Some(Self::Lsr),
-            "NOP" => Some(Self::Nop),
-            "ORA" => Some(Self::Ora),
-            "PHA" => Some(Self::Pha),
-            "PHP" => Some(Self::Php),
-            "PLA" => Some(Self::Pla),
-            "PLP" => Some(Self::Plp),
-            "ROL" => Some(Self::Rol),
-            "ROR" => Some(Self::Ror),
-            "RTI" => Some(Self::Rti),
-            "RTS" => Some(Self::Rts),
-            "SBC" => Some(Self::Sbc),
-            "SEC" => Some(Self::Sec),
-            "SED" => Some(Self::Sed),
-            "SEI" => Some(Self::Sei),
-            "STA" => Some(Self::Sta),
-            "STX" => Some(Self::Stx),
-            "STY" => Some(Self::Sty),
-            "TAX" => Some(Self::Tax),
-            "TAY" => Some(Self::Tay),
-            "TSX" => Some(Self::Tsx),
-            "TXA" => Some(Self::Txa),
-            "TXS" => Some(Self::Txs),
-            "TYA" => Some(Self::Tya),
-            _ => None,
-        }
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        MNEMONICS
+            .iter()
+            .find(|&&(_, m)| m == mnemonic)
+            .map(|&(opcode_type, _)| opcode_type)
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        MNEMONICS
+            .iter()
+            .find(|&&(opcode_type, _)| opcode_type == self)
+            .map(|&(_, m)| m)
+            .unwrap()
     }
 }
 
+const MNEMONICS: &[(Type, &str)] = &[
+    (Type::Adc, "ADC"),
+    (Type::And, "AND"),
+    (Type::Asl, "ASL"),
+    (Type::Bcc, "BCC"),
+    (Type::Bcs, "BCS"),
+    (Type::Beq, "BEQ"),
+    (Type::Bit, "BIT"),
+    (Type::Bmi, "BMI"),
+    (Type::Bne, "BNE"),
+    (Type::Bpl, "BPL"),
+    (Type::Brk, "BRK"),
+    (Type::Bvc, "BVC"),
+    (Type::Bvs, "BVS"),
+    (Type::Clc, "CLC"),
+    (Type::Cld, "CLD"),
+    (Type::Cli, "CLI"),
+    (Type::Clv, "CLV"),
+    (Type::Cmp, "CMP"),
+    (Type::Cpx, "CPX"),
+    (Type::Cpy, "CPY"),
+    (Type::Dec, "DEC"),
+    (Type::Dex, "DEX"),
+    (Type::Dey, "DEY"),
+    (Type::Eor, "EOR"),
+    (Type::Inc, "INC"),
+    (Type::Inx, "INX"),
+    (Type::Iny, "INY"),
+    (Type::Jmp, "JMP"),
+    (Type::Jsr, "JSR"),
+    (Type::Lda, "LDA"),
+    (Type::Ldx, "LDX"),
+    (Type::Ldy, "LDY"),
+    (Type::Lsr, "LSR"),
+    (Type::Nop, "NOP"),
+    (Type::Ora, "ORA"),
+    (Type::Pha, "PHA"),
+    (Type::Php, "PHP"),
+    (Type::Pla, "PLA"),
+    (Type::Plp, "PLP"),
+    (Type::Rol, "ROL"),
+    (Type::Ror, "ROR"),
+    (Type::Rti, "RTI"),
+    (Type::Rts, "RTS"),
+    (Type::Sbc, "SBC"),
+    (Type::Sec, "SEC"),
+    (Type::Sed, "SED"),
+    (Type::Sei, "SEI"),
+    (Type::Sta, "STA"),
+    (Type::Stx, "STX"),
+    (Type::Sty, "STY"),
+    (Type::Tax, "TAX"),
+    (Type::Tay, "TAY"),
+    (Type::Tsx, "TSX"),
+    (Type::Txa, "TXA"),
+    (Type::Txs, "TXS"),
+    (Type::Tya, "TYA"),
+    (Type::Bra, "BRA"),
+    (Type::Phx, "PHX"),
+    (Type::Phy, "PHY"),
+    (Type::Plx, "PLX"),
+    (Type::Ply, "PLY"),
+    (Type::Stz, "STZ"),
+    (Type::Trb, "TRB"),
+    (Type::Tsb, "TSB"),
+    (Type::Alr, "ALR"),
+    (Type::Anc, "ANC"),
+    (Type::Arr, "ARR"),
+    (Type::Axs, "AXS"),
+    (Type::Dcp, "DCP"),
+    (Type::Isc, "ISC"),
+    (Type::Kil, "KIL"),
+    (Type::Las, "LAS"),
+    (Type::Lax, "LAX"),
+    (Type::Lxa, "LXA"),
+    (Type::Rla, "RLA"),
+    (Type::Rra, "RRA"),
+    (Type::Sax, "SAX"),
+    (Type::Sha, "SHA"),
+    (Type::Shx, "SHX"),
+    (Type::Shy, "SHY"),
+    (Type::Slo, "SLO"),
+    (Type::Sre, "SRE"),
+    (Type::Tas, "TAS"),
+    (Type::Xaa, "XAA"),
+];
+
 #[test]
 fn test_opcode_type_compatibility() {
-    assert_eq!(Type::Beq.compatible_with(AddressMode::Relative), true);
-    assert_eq!(Type::Beq.compatible_with(AddressMode::Implicit), false);
-    assert_eq!(Type::Jmp.compatible_with(AddressMode::Indirect), true);
-    assert_eq!(Type::Jmp.compatible_with(AddressMode::Absolute), true);
-    assert_eq!(Type::Jmp.compatible_with(AddressMode::IndirectX), false);
+    let nmos = Variant::Ricoh2A03;
+    assert!(Type::Beq.compatible_with(nmos, AddressMode::Relative));
+    assert!(!Type::Beq.compatible_with(nmos, AddressMode::Implicit));
+    assert!(Type::Jmp.compatible_with(nmos, AddressMode::Indirect));
+    assert!(Type::Jmp.compatible_with(nmos, AddressMode::Absolute));
+    assert!(!Type::Jmp.compatible_with(nmos, AddressMode::IndirectX));
+    assert!(!Type::Lda.compatible_with(nmos, AddressMode::ZeroPageIndirect));
+    assert!(!Type::Stz.compatible_with(nmos, AddressMode::Absolute));
+
+    let cmos = Variant::Cmos65C02;
+    assert!(Type::Lda.compatible_with(cmos, AddressMode::ZeroPageIndirect));
+    assert!(Type::Stz.compatible_with(cmos, AddressMode::Absolute));
+    assert!(Type::Jmp.compatible_with(cmos, AddressMode::AbsoluteIndirectX));
+    assert!(Type::Bit.compatible_with(cmos, AddressMode::Immediate));
+    assert!(!Type::Lax.compatible_with(cmos, AddressMode::ZeroPage));
 }
 
 struct Opcode {
@@ -176,7 +267,7 @@ struct Opcode {
     encoding: u8,
 }
 
-const OPCODES: &[Opcode] = &[
+const OFFICIAL: &[Opcode] = &[
     // ADC
     Opcode {
         opcode_type: Type::Adc,
@@ -1141,43 +1232,1036 @@ const OPCODES: &[Opcode] = &[
     },
 ];
 
-/// Takes a encoded opcode and converts it to a tuple containing the opcode,
-/// addressing mode, and base cycle cost.
-///
-/// Reference: obelisk.me.uk/6502/reference.html
-pub fn decode(opcode: u8) -> Option<(Type, AddressMode, u64)> {
-    lazy_static! {
-        static ref OPCODES_BY_ENCODING: [Option<(Type, AddressMode, u64)>; 256] = {
-            let mut map = [None; 256];
-            for opcode in OPCODES.iter() {
-                map[opcode.encoding as usize] =
-                    Some((opcode.opcode_type, opcode.addr_mode, opcode.base_cycle_cost));
-            }
-            map
-        };
-    }
-
-    OPCODES_BY_ENCODING[opcode as usize]
-}
-
-pub fn encode(opcode_type: Type, addr_mode: AddressMode) -> Option<u8> {
-    lazy_static! {
-        static ref ENCODINGS_BY_TYPE_AND_ADDR_MODE: HashMap<(Type, AddressMode), u8> = {
-            let mut map = HashMap::new();
-            for opcode in OPCODES.iter() {
-                map.insert((opcode.opcode_type, opcode.addr_mode), opcode.encoding);
-            }
-            map
-        };
-    }
-
-    ENCODINGS_BY_TYPE_AND_ADDR_MODE
-        .get(&(opcode_type, addr_mode))
-        .map(|v| *v)
-}
-
-#[test]
-fn test_encode() {
-    assert_eq!(encode(Type::Adc, AddressMode::Immediate), Some(0x69));
-    assert_eq!(encode(Type::Tya, AddressMode::Implicit), Some(0x98));
+// Unofficial NMOS opcodes. Official encodings are listed first, so that
+// encode() prefers them for duplicates like SBC #imm and NOP.
+// Reference: https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+const UNOFFICIAL: &[Opcode] = &[
+    // LAX
+    Opcode {
+        opcode_type: Type::Lax,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 3,
+        encoding: 0xA7,
+    },
+    Opcode {
+        opcode_type: Type::Lax,
+        addr_mode: AddressMode::ZeroPageY,
+        base_cycle_cost: 4,
+        encoding: 0xB7,
+    },
+    Opcode {
+        opcode_type: Type::Lax,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 4,
+        encoding: 0xAF,
+    },
+    Opcode {
+        opcode_type: Type::Lax,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 4,
+        encoding: 0xBF,
+    },
+    Opcode {
+        opcode_type: Type::Lax,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 6,
+        encoding: 0xA3,
+    },
+    Opcode {
+        opcode_type: Type::Lax,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 5,
+        encoding: 0xB3,
+    },
+    // SAX
+    Opcode {
+        opcode_type: Type::Sax,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 3,
+        encoding: 0x87,
+    },
+    Opcode {
+        opcode_type: Type::Sax,
+        addr_mode: AddressMode::ZeroPageY,
+        base_cycle_cost: 4,
+        encoding: 0x97,
+    },
+    Opcode {
+        opcode_type: Type::Sax,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 4,
+        encoding: 0x8F,
+    },
+    Opcode {
+        opcode_type: Type::Sax,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 6,
+        encoding: 0x83,
+    },
+    // SLO
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0x07,
+    },
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 6,
+        encoding: 0x17,
+    },
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0x0F,
+    },
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 7,
+        encoding: 0x1F,
+    },
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 7,
+        encoding: 0x1B,
+    },
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 8,
+        encoding: 0x03,
+    },
+    Opcode {
+        opcode_type: Type::Slo,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 8,
+        encoding: 0x13,
+    },
+    // RLA
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0x27,
+    },
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 6,
+        encoding: 0x37,
+    },
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0x2F,
+    },
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 7,
+        encoding: 0x3F,
+    },
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 7,
+        encoding: 0x3B,
+    },
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 8,
+        encoding: 0x23,
+    },
+    Opcode {
+        opcode_type: Type::Rla,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 8,
+        encoding: 0x33,
+    },
+    // SRE
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0x47,
+    },
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 6,
+        encoding: 0x57,
+    },
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0x4F,
+    },
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 7,
+        encoding: 0x5F,
+    },
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 7,
+        encoding: 0x5B,
+    },
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 8,
+        encoding: 0x43,
+    },
+    Opcode {
+        opcode_type: Type::Sre,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 8,
+        encoding: 0x53,
+    },
+    // RRA
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0x67,
+    },
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 6,
+        encoding: 0x77,
+    },
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0x6F,
+    },
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 7,
+        encoding: 0x7F,
+    },
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 7,
+        encoding: 0x7B,
+    },
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 8,
+        encoding: 0x63,
+    },
+    Opcode {
+        opcode_type: Type::Rra,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 8,
+        encoding: 0x73,
+    },
+    // DCP
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0xC7,
+    },
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 6,
+        encoding: 0xD7,
+    },
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0xCF,
+    },
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 7,
+        encoding: 0xDF,
+    },
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 7,
+        encoding: 0xDB,
+    },
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 8,
+        encoding: 0xC3,
+    },
+    Opcode {
+        opcode_type: Type::Dcp,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 8,
+        encoding: 0xD3,
+    },
+    // ISC
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0xE7,
+    },
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 6,
+        encoding: 0xF7,
+    },
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0xEF,
+    },
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 7,
+        encoding: 0xFF,
+    },
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 7,
+        encoding: 0xFB,
+    },
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::IndirectX,
+        base_cycle_cost: 8,
+        encoding: 0xE3,
+    },
+    Opcode {
+        opcode_type: Type::Isc,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 8,
+        encoding: 0xF3,
+    },
+    // ANC
+    Opcode {
+        opcode_type: Type::Anc,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x0B,
+    },
+    Opcode {
+        opcode_type: Type::Anc,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x2B,
+    },
+    // ALR
+    Opcode {
+        opcode_type: Type::Alr,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x4B,
+    },
+    // ARR
+    Opcode {
+        opcode_type: Type::Arr,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x6B,
+    },
+    // AXS
+    Opcode {
+        opcode_type: Type::Axs,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0xCB,
+    },
+    // SBC
+    Opcode {
+        opcode_type: Type::Sbc,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0xEB,
+    },
+    // NOP
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x1A,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x3A,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x5A,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x7A,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0xDA,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0xFA,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x80,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x82,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x89,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0xC2,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0xE2,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 3,
+        encoding: 0x04,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 3,
+        encoding: 0x44,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 3,
+        encoding: 0x64,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0x14,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0x34,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0x54,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0x74,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0xD4,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0xF4,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 4,
+        encoding: 0x0C,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0x1C,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0x3C,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0x5C,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0x7C,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0xDC,
+    },
+    Opcode {
+        opcode_type: Type::Nop,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0xFC,
+    },
+    // LAS
+    Opcode {
+        opcode_type: Type::Las,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 4,
+        encoding: 0xBB,
+    },
+    // LXA
+    Opcode {
+        opcode_type: Type::Lxa,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0xAB,
+    },
+    // XAA
+    Opcode {
+        opcode_type: Type::Xaa,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x8B,
+    },
+    // SHA
+    Opcode {
+        opcode_type: Type::Sha,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 5,
+        encoding: 0x9F,
+    },
+    Opcode {
+        opcode_type: Type::Sha,
+        addr_mode: AddressMode::IndirectY,
+        base_cycle_cost: 6,
+        encoding: 0x93,
+    },
+    // SHX
+    Opcode {
+        opcode_type: Type::Shx,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 5,
+        encoding: 0x9E,
+    },
+    // SHY
+    Opcode {
+        opcode_type: Type::Shy,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 5,
+        encoding: 0x9C,
+    },
+    // TAS
+    Opcode {
+        opcode_type: Type::Tas,
+        addr_mode: AddressMode::AbsoluteY,
+        base_cycle_cost: 5,
+        encoding: 0x9B,
+    },
+    // KIL
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x02,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x12,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x22,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x32,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x42,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x52,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x62,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x72,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0x92,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0xB2,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0xD2,
+    },
+    Opcode {
+        opcode_type: Type::Kil,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 2,
+        encoding: 0xF2,
+    },
+];
+
+// Instructions added or changed by the CMOS 65C02. These are applied on top
+// of the official NMOS opcodes. The unofficial NMOS opcodes are not carried
+// over; the 65C02 treats most of them as NOPs of various lengths, and some
+// manufacturers assigned bit manipulation and low-power instructions to the
+// rest, so they are left undecoded.
+// Reference: http://6502.org/tutorials/65c02opcodes.html
+const CMOS: &[Opcode] = &[
+    // ADC
+    Opcode {
+        opcode_type: Type::Adc,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0x72,
+    },
+    // AND
+    Opcode {
+        opcode_type: Type::And,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0x32,
+    },
+    // BIT
+    Opcode {
+        opcode_type: Type::Bit,
+        addr_mode: AddressMode::Immediate,
+        base_cycle_cost: 2,
+        encoding: 0x89,
+    },
+    Opcode {
+        opcode_type: Type::Bit,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0x34,
+    },
+    Opcode {
+        opcode_type: Type::Bit,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 4,
+        encoding: 0x3C,
+    },
+    // BRA
+    Opcode {
+        opcode_type: Type::Bra,
+        addr_mode: AddressMode::Relative,
+        base_cycle_cost: 3,
+        encoding: 0x80,
+    },
+    // CMP
+    Opcode {
+        opcode_type: Type::Cmp,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0xD2,
+    },
+    // DEC
+    Opcode {
+        opcode_type: Type::Dec,
+        addr_mode: AddressMode::Accumulator,
+        base_cycle_cost: 2,
+        encoding: 0x3A,
+    },
+    // EOR
+    Opcode {
+        opcode_type: Type::Eor,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0x52,
+    },
+    // INC
+    Opcode {
+        opcode_type: Type::Inc,
+        addr_mode: AddressMode::Accumulator,
+        base_cycle_cost: 2,
+        encoding: 0x1A,
+    },
+    // JMP
+    Opcode {
+        opcode_type: Type::Jmp,
+        addr_mode: AddressMode::Indirect,
+        base_cycle_cost: 6,
+        encoding: 0x6C,
+    },
+    Opcode {
+        opcode_type: Type::Jmp,
+        addr_mode: AddressMode::AbsoluteIndirectX,
+        base_cycle_cost: 6,
+        encoding: 0x7C,
+    },
+    // LDA
+    Opcode {
+        opcode_type: Type::Lda,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0xB2,
+    },
+    // ORA
+    Opcode {
+        opcode_type: Type::Ora,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0x12,
+    },
+    // PHX
+    Opcode {
+        opcode_type: Type::Phx,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 3,
+        encoding: 0xDA,
+    },
+    // PHY
+    Opcode {
+        opcode_type: Type::Phy,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 3,
+        encoding: 0x5A,
+    },
+    // PLX
+    Opcode {
+        opcode_type: Type::Plx,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 4,
+        encoding: 0xFA,
+    },
+    // PLY
+    Opcode {
+        opcode_type: Type::Ply,
+        addr_mode: AddressMode::Implicit,
+        base_cycle_cost: 4,
+        encoding: 0x7A,
+    },
+    // SBC
+    Opcode {
+        opcode_type: Type::Sbc,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0xF2,
+    },
+    // STA
+    Opcode {
+        opcode_type: Type::Sta,
+        addr_mode: AddressMode::ZeroPageIndirect,
+        base_cycle_cost: 5,
+        encoding: 0x92,
+    },
+    // STZ
+    Opcode {
+        opcode_type: Type::Stz,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 3,
+        encoding: 0x64,
+    },
+    Opcode {
+        opcode_type: Type::Stz,
+        addr_mode: AddressMode::ZeroPageX,
+        base_cycle_cost: 4,
+        encoding: 0x74,
+    },
+    Opcode {
+        opcode_type: Type::Stz,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 4,
+        encoding: 0x9C,
+    },
+    Opcode {
+        opcode_type: Type::Stz,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 5,
+        encoding: 0x9E,
+    },
+    // TRB
+    Opcode {
+        opcode_type: Type::Trb,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0x14,
+    },
+    Opcode {
+        opcode_type: Type::Trb,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0x1C,
+    },
+    // TSB
+    Opcode {
+        opcode_type: Type::Tsb,
+        addr_mode: AddressMode::ZeroPage,
+        base_cycle_cost: 5,
+        encoding: 0x04,
+    },
+    Opcode {
+        opcode_type: Type::Tsb,
+        addr_mode: AddressMode::Absolute,
+        base_cycle_cost: 6,
+        encoding: 0x0C,
+    },
+    // shifts no longer take an extra cycle without a page crossing
+    Opcode {
+        opcode_type: Type::Asl,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 6,
+        encoding: 0x1E,
+    },
+    Opcode {
+        opcode_type: Type::Lsr,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 6,
+        encoding: 0x5E,
+    },
+    Opcode {
+        opcode_type: Type::Rol,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 6,
+        encoding: 0x3E,
+    },
+    Opcode {
+        opcode_type: Type::Ror,
+        addr_mode: AddressMode::AbsoluteX,
+        base_cycle_cost: 6,
+        encoding: 0x7E,
+    },
+];
+
+// The opcode lists making up each instruction set. Later lists override
+// encodings from earlier ones.
+const NMOS_OPCODES: &[&[Opcode]] = &[OFFICIAL, UNOFFICIAL];
+const CMOS_OPCODES: &[&[Opcode]] = &[OFFICIAL, CMOS];
+
+fn opcodes(variant: Variant) -> &'static [&'static [Opcode]] {
+    match variant {
+        Variant::Ricoh2A03 | Variant::Nmos6502 => NMOS_OPCODES,
+        Variant::Cmos65C02 => CMOS_OPCODES,
+    }
+}
+
+// Opcode info, indexed by encoding.
+pub const DECODE_TABLE: [Option<(Type, AddressMode, u64)>; 256] = decode_table(NMOS_OPCODES);
+pub const CMOS_DECODE_TABLE: [Option<(Type, AddressMode, u64)>; 256] = decode_table(CMOS_OPCODES);
+
+const fn decode_table(lists: &[&[Opcode]]) -> [Option<(Type, AddressMode, u64)>; 256] {
+    let mut table = [None; 256];
+    let mut i = 0;
+    while i < lists.len() {
+        let mut j = 0;
+        while j < lists[i].len() {
+            let opcode = &lists[i][j];
+            table[opcode.encoding as usize] =
+                Some((opcode.opcode_type, opcode.addr_mode, opcode.base_cycle_cost));
+            j += 1;
+        }
+        i += 1;
+    }
+    table
+}
+
+pub fn decode_table_for(variant: Variant) -> &'static [Option<(Type, AddressMode, u64)>; 256] {
+    match variant {
+        Variant::Ricoh2A03 | Variant::Nmos6502 => &DECODE_TABLE,
+        Variant::Cmos65C02 => &CMOS_DECODE_TABLE,
+    }
+}
+
+/// Takes a encoded opcode and converts it to a tuple containing the opcode,
+/// addressing mode, and base cycle cost.
+///
+/// Reference: obelisk.me.uk/6502/reference.html
+pub fn decode(variant: Variant, opcode: u8) -> Option<(Type, AddressMode, u64)> {
+    decode_table_for(variant)[opcode as usize]
+}
+
+// Whether an encoding is one of the undocumented NMOS opcodes.
+pub fn is_unofficial(variant: Variant, encoding: u8) -> bool {
+    variant != Variant::Cmos65C02 && !OFFICIAL.iter().any(|opcode| opcode.encoding == encoding)
+}
+
+pub fn encode(variant: Variant, opcode_type: Type, addr_mode: AddressMode) -> Option<u8> {
+    opcodes(variant)
+        .iter()
+        .flat_map(|list| list.iter())
+        .find(|opcode| opcode.opcode_type == opcode_type && opcode.addr_mode == addr_mode)
+        .map(|opcode| opcode.encoding)
+}
+
+#[test]
+fn test_encode() {
+    let nmos = Variant::Ricoh2A03;
+    assert_eq!(encode(nmos, Type::Adc, AddressMode::Immediate), Some(0x69));
+    assert_eq!(encode(nmos, Type::Tya, AddressMode::Implicit), Some(0x98));
+
+    // official encodings win over unofficial duplicates
+    assert_eq!(encode(nmos, Type::Sbc, AddressMode::Immediate), Some(0xE9));
+    assert_eq!(encode(nmos, Type::Nop, AddressMode::Implicit), Some(0xEA));
+    assert_eq!(encode(nmos, Type::Lax, AddressMode::ZeroPageY), Some(0xB7));
+
+    let cmos = Variant::Cmos65C02;
+    assert_eq!(encode(cmos, Type::Stz, AddressMode::AbsoluteX), Some(0x9E));
+    assert_eq!(
+        encode(cmos, Type::Inc, AddressMode::Accumulator),
+        Some(0x1A)
+    );
+    assert_eq!(encode(cmos, Type::Lax, AddressMode::ZeroPageY), None);
+    assert_eq!(encode(nmos, Type::Stz, AddressMode::AbsoluteX), None);
+}
+
+#[test]
+fn test_decode_all() {
+    for encoding in 0..=255 {
+        assert!(
+            decode(Variant::Ricoh2A03, encoding).is_some(),
+            "{:#04X}",
+            encoding
+        );
+    }
+    assert_eq!(
+        decode(Variant::Ricoh2A03, 0xEB),
+        Some((Type::Sbc, AddressMode::Immediate, 2))
+    );
+    assert_eq!(
+        decode(Variant::Ricoh2A03, 0xDF),
+        Some((Type::Dcp, AddressMode::AbsoluteX, 7))
+    );
+}
+
+#[test]
+fn test_is_unofficial() {
+    assert!(!is_unofficial(Variant::Ricoh2A03, 0xEA));
+    assert!(is_unofficial(Variant::Ricoh2A03, 0xEB));
+    assert!(is_unofficial(Variant::Ricoh2A03, 0x1A));
+    assert!(!is_unofficial(Variant::Cmos65C02, 0x1A));
+}
+
+#[test]
+fn test_mnemonic() {
+    for table in &[DECODE_TABLE, CMOS_DECODE_TABLE] {
+        for &(opcode_type, _, _) in table.iter().flatten() {
+            assert_eq!(
+                Type::from_mnemonic(opcode_type.mnemonic()),
+                Some(opcode_type)
+            );
+        }
+    }
+    assert_eq!(Type::from_mnemonic("lda"), Some(Type::Lda));
+    assert_eq!(Type::from_mnemonic("abc"), None);
+}
+
+#[test]
+fn test_decode_cmos() {
+    let cmos = Variant::Cmos65C02;
+    assert_eq!(
+        decode(cmos, 0x80),
+        Some((Type::Bra, AddressMode::Relative, 3))
+    );
+    assert_eq!(
+        decode(cmos, 0xB2),
+        Some((Type::Lda, AddressMode::ZeroPageIndirect, 5))
+    );
+    assert_eq!(
+        decode(cmos, 0x7C),
+        Some((Type::Jmp, AddressMode::AbsoluteIndirectX, 6))
+    );
+
+    // overrides of official encodings
+    assert_eq!(
+        decode(cmos, 0x6C),
+        Some((Type::Jmp, AddressMode::Indirect, 6))
+    );
+    assert_eq!(
+        decode(cmos, 0x1E),
+        Some((Type::Asl, AddressMode::AbsoluteX, 6))
+    );
+
+    // official opcodes are unchanged, unofficial ones are gone
+    assert_eq!(decode(cmos, 0xA9), decode(Variant::Ricoh2A03, 0xA9));
+    assert_eq!(decode(cmos, 0xA7), None);
+    assert_eq!(decode(cmos, 0x02), None);
 }
@@ -1,6 +1,8 @@
 use super::address_mode::AddressMode;
+use super::bus::Bus;
+use super::error::CpuError;
 use super::opcode;
-use super::state::Cpu;
+use super::state::{Cpu, Variant};
 use crate::math;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -12,27 +14,59 @@ pub enum Operand {
 }
 
 impl Operand {
-    pub fn read(self, cpu: &Cpu) -> u8 {
+    pub fn read<B: Bus>(self, cpu: &mut Cpu<B>) -> Result<u8, CpuError> {
         match self {
-            Self::Accumulator => cpu.regs.a,
-            Self::Immediate(val) => val,
-            Self::Memory(addr) => cpu.mem_read(addr),
-            other => panic!("no readable value for {:?} operand", other),
+            Self::Accumulator => Ok(cpu.regs.a),
+            Self::Immediate(val) => Ok(val),
+            Self::Memory(addr) => cpu.bus_read(addr),
+            other => Err(CpuError::InvalidOperand(other)),
         }
     }
 
-    pub fn write(self, cpu: &mut Cpu, val: u8) {
+    pub fn write<B: Bus>(self, cpu: &mut Cpu<B>, val: u8) -> Result<(), CpuError> {
         match self {
-            Self::Accumulator => cpu.regs.a = val,
-            Self::Memory(addr) => cpu.mem_write(addr, val),
-            other => panic!("no writable value for {:?} operand", other),
+            Self::Accumulator => {
+                cpu.regs.a = val;
+                Ok(())
+            }
+            Self::Memory(addr) => cpu.bus_write(addr, val),
+            other => Err(CpuError::InvalidOperand(other)),
         }
     }
 
-    pub fn address(self) -> u16 {
+    // Read-modify-write. While computing the new value, the NMOS CPU writes
+    // the unmodified value back, which memory-mapped registers will see. The
+    // 65C02 reads the address again instead.
+    pub fn modify<B: Bus>(
+        self,
+        cpu: &mut Cpu<B>,
+        f: impl FnOnce(&mut Cpu<B>, u8) -> u8,
+    ) -> Result<u8, CpuError> {
         match self {
-            Self::Memory(addr) => addr,
-            other => panic!("no address for {:?} operand", other),
+            Self::Accumulator => {
+                let res = f(cpu, cpu.regs.a);
+                cpu.regs.a = res;
+                Ok(res)
+            }
+            Self::Memory(addr) => {
+                let prev = cpu.bus_read(addr)?;
+                if cpu.variant == Variant::Cmos65C02 {
+                    cpu.bus_dummy_read(addr);
+                } else {
+                    cpu.bus_write(addr, prev)?;
+                }
+                let res = f(cpu, prev);
+                cpu.bus_write(addr, res)?;
+                Ok(res)
+            }
+            other => Err(CpuError::InvalidOperand(other)),
+        }
+    }
+
+    pub fn address(self) -> Result<u16, CpuError> {
+        match self {
+            Self::Memory(addr) => Ok(addr),
+            other => Err(CpuError::InvalidOperand(other)),
         }
     }
 }
@@ -43,17 +77,23 @@ fn test_operand_accumulator() {
     cpu.regs.a = 0xAB;
 
     let op = Operand::Accumulator;
-    assert_eq!(op.read(&cpu), 0xAB);
+    assert_eq!(op.read(&mut cpu).unwrap(), 0xAB);
 
-    op.write(&mut cpu, 0xCD);
+    op.write(&mut cpu, 0xCD).unwrap();
     assert_eq!(cpu.regs.a, 0xCD);
+
+    assert_eq!(op.modify(&mut cpu, |_, v| v + 1).unwrap(), 0xCE);
+    assert_eq!(cpu.regs.a, 0xCE);
+    assert_eq!(cpu.cycles, 0);
 }
 
 #[test]
 fn test_operand_immediate() {
-    let cpu = Cpu::new_test();
+    let mut cpu = Cpu::new_test();
     let op = Operand::Immediate(0xAB);
-    assert_eq!(op.read(&cpu), 0xAB);
+    assert_eq!(op.read(&mut cpu).unwrap(), 0xAB);
+    assert_eq!(op.write(&mut cpu, 0), Err(CpuError::InvalidOperand(op)));
+    assert_eq!(op.address(), Err(CpuError::InvalidOperand(op)));
 }
 
 #[test]
@@ -62,129 +102,184 @@ fn test_operand_memory() {
     cpu.mem_write(0x1F, 0xAB);
 
     let op = Operand::Memory(0x1F);
-    assert_eq!(op.read(&cpu), 0xAB);
+    assert_eq!(op.read(&mut cpu).unwrap(), 0xAB);
 
-    op.write(&mut cpu, 0xCD);
+    op.write(&mut cpu, 0xCD).unwrap();
     assert_eq!(cpu.mem_read(0x1F), 0xCD);
+    assert_eq!(cpu.cycles, 2);
+
+    // read, dummy write, write
+    assert_eq!(op.modify(&mut cpu, |_, v| v + 1).unwrap(), 0xCE);
+    assert_eq!(cpu.mem_read(0x1F), 0xCE);
+    assert_eq!(cpu.cycles, 5);
+}
+
+#[test]
+fn test_operand_modify_cmos() {
+    // the 65C02 reads the address twice instead of writing it twice
+    let mut cpu = Cpu::new_test();
+    cpu.variant = Variant::Cmos65C02;
+    cpu.mem_write(0x1F, 0xAB);
+    let op = Operand::Memory(0x1F);
+    assert_eq!(op.modify(&mut cpu, |_, v| v + 1).unwrap(), 0xAC);
+    assert_eq!(cpu.mem_read(0x1F), 0xAC);
+    assert_eq!(cpu.cycles, 3);
 }
 
 // Consumes bytes from the instruction "segment" to calculate an operand value,
-// based on the provided addressing mode. It returns the operand, plus any CPU
-// cycle adjustment required to process indexed memory reads that cross page
-// boundaries. See page_crossing_cycle_adjustment() for more.
-pub fn decode(cpu: &mut Cpu, opcode_type: opcode::Type, addr_mode: AddressMode) -> (Operand, u64) {
-    match addr_mode {
-        AddressMode::Implicit => (Operand::None, 0),
-        AddressMode::Accumulator => (Operand::Accumulator, 0),
-        AddressMode::Immediate => (Operand::Immediate(cpu.instruction_fetch_byte()), 0),
-        AddressMode::ZeroPage => (Operand::Memory(cpu.instruction_fetch_byte() as u16), 0),
-        AddressMode::ZeroPageX => (
-            Operand::Memory(cpu.regs.x.wrapping_add(cpu.instruction_fetch_byte()) as u16),
-            0,
-        ),
-        AddressMode::ZeroPageY => (
-            Operand::Memory(cpu.regs.y.wrapping_add(cpu.instruction_fetch_byte()) as u16),
-            0,
-        ),
-        AddressMode::Relative => (Operand::Immediate(cpu.instruction_fetch_byte()), 0),
-        AddressMode::Absolute => (
-            Operand::Memory(math::bytes_to_u16_le([
-                cpu.instruction_fetch_byte(),
-                cpu.instruction_fetch_byte(),
-            ])),
-            0,
-        ),
+// based on the provided addressing mode. This performs every bus access the
+// CPU makes before the instruction's own reads and writes, including the
+// dummy reads that some addressing modes make while the address is computed.
+#[inline(always)]
+pub fn decode<B: Bus>(
+    cpu: &mut Cpu<B>,
+    opcode_type: opcode::Type,
+    addr_mode: AddressMode,
+) -> Result<Operand, CpuError> {
+    let operand = match addr_mode {
+        // Single-byte instructions still read the following byte, then
+        // discard it.
+        AddressMode::Implicit => {
+            cpu.bus_dummy_read(cpu.regs.pc);
+            Operand::None
+        }
+        AddressMode::Accumulator => {
+            cpu.bus_dummy_read(cpu.regs.pc);
+            Operand::Accumulator
+        }
+        AddressMode::Immediate => Operand::Immediate(cpu.instruction_fetch_byte()?),
+        AddressMode::ZeroPage => Operand::Memory(cpu.instruction_fetch_byte()? as u16),
+        AddressMode::ZeroPageX => {
+            let base = cpu.instruction_fetch_byte()?;
+            cpu.bus_dummy_read(base as u16); // read while adding the index
+            Operand::Memory(base.wrapping_add(cpu.regs.x) as u16)
+        }
+        AddressMode::ZeroPageY => {
+            let base = cpu.instruction_fetch_byte()?;
+            cpu.bus_dummy_read(base as u16); // read while adding the index
+            Operand::Memory(base.wrapping_add(cpu.regs.y) as u16)
+        }
+        AddressMode::Relative => Operand::Immediate(cpu.instruction_fetch_byte()?),
+        AddressMode::Absolute => Operand::Memory(math::bytes_to_u16_le([
+            cpu.instruction_fetch_byte()?,
+            cpu.instruction_fetch_byte()?,
+        ])),
         AddressMode::AbsoluteX => {
-            let base =
-                math::bytes_to_u16_le([cpu.instruction_fetch_byte(), cpu.instruction_fetch_byte()]);
-            let addr = base + cpu.regs.x as u16;
-            (
-                Operand::Memory(addr),
-                page_crossing_cycle_adjusment(opcode_type, base, addr),
-            )
+            let base = math::bytes_to_u16_le([
+                cpu.instruction_fetch_byte()?,
+                cpu.instruction_fetch_byte()?,
+            ]);
+            indexed(cpu, opcode_type, base, cpu.regs.x)
         }
         AddressMode::AbsoluteY => {
-            let base =
-                math::bytes_to_u16_le([cpu.instruction_fetch_byte(), cpu.instruction_fetch_byte()]);
-            let addr = base + cpu.regs.y as u16;
-            (
-                Operand::Memory(addr),
-                page_crossing_cycle_adjusment(opcode_type, base, addr),
-            )
+            let base = math::bytes_to_u16_le([
+                cpu.instruction_fetch_byte()?,
+                cpu.instruction_fetch_byte()?,
+            ]);
+            indexed(cpu, opcode_type, base, cpu.regs.y)
         }
         AddressMode::Indirect => {
-            let bytes = [cpu.instruction_fetch_byte(), cpu.instruction_fetch_byte()];
-            (
-                Operand::Memory(cpu.mem_read16(math::bytes_to_u16_le(bytes))),
-                0,
-            )
+            let ptr = math::bytes_to_u16_le([
+                cpu.instruction_fetch_byte()?,
+                cpu.instruction_fetch_byte()?,
+            ]);
+            if cpu.variant == Variant::Cmos65C02 {
+                // The 65C02 spends an extra cycle to carry into the
+                // pointer's high byte.
+                cpu.bus_dummy_read(cpu.regs.pc.wrapping_sub(1));
+                Operand::Memory(cpu.bus_read16(ptr)?)
+            } else {
+                // The high byte of the target is read without carrying into
+                // the pointer's high byte, so JMP ($xxFF) reads it from $xx00.
+                let hi_ptr = (ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF);
+                Operand::Memory(math::bytes_to_u16_le([
+                    cpu.bus_read(ptr)?,
+                    cpu.bus_read(hi_ptr)?,
+                ]))
+            }
         }
         AddressMode::IndirectX => {
-            let offset = cpu.instruction_fetch_byte();
-            (
-                Operand::Memory(cpu.mem_read16(cpu.regs.x.wrapping_add(offset) as u16)),
-                0,
-            )
+            let ptr = cpu.instruction_fetch_byte()?;
+            cpu.bus_dummy_read(ptr as u16); // read while adding the index
+            Operand::Memory(zero_page_read16(cpu, ptr.wrapping_add(cpu.regs.x))?)
         }
         AddressMode::IndirectY => {
-            let indirect = cpu.instruction_fetch_byte() as u16;
-            let base = cpu.mem_read16(indirect);
-            let addr = base + cpu.regs.y as u16;
-            (
-                Operand::Memory(addr),
-                page_crossing_cycle_adjusment(opcode_type, base, addr),
-            )
+            let ptr = cpu.instruction_fetch_byte()?;
+            let base = zero_page_read16(cpu, ptr)?;
+            indexed(cpu, opcode_type, base, cpu.regs.y)
         }
-    }
+        AddressMode::ZeroPageIndirect => {
+            let ptr = cpu.instruction_fetch_byte()?;
+            Operand::Memory(zero_page_read16(cpu, ptr)?)
+        }
+        AddressMode::AbsoluteIndirectX => {
+            let base = math::bytes_to_u16_le([
+                cpu.instruction_fetch_byte()?,
+                cpu.instruction_fetch_byte()?,
+            ]);
+            cpu.bus_dummy_read(cpu.regs.pc.wrapping_sub(1)); // read while adding the index
+            Operand::Memory(cpu.bus_read16(base.wrapping_add(cpu.regs.x as u16))?)
+        }
+    };
+    Ok(operand)
 }
 
-// Calculates a cycle cost adjustment to the base cycle cost listed in the
-// opcode table in decode_raw_page(). This adjustment should only be used for
-// indexed address modes that add an 8-bit offset to a 16-bit base address.
+// Reads a pointer from the zero page. The pointer's high byte wraps around
+// to $00 rather than crossing into the stack page.
+fn zero_page_read16<B: Bus>(cpu: &mut Cpu<B>, ptr: u8) -> Result<u16, CpuError> {
+    Ok(math::bytes_to_u16_le([
+        cpu.bus_read(ptr as u16)?,
+        cpu.bus_read(ptr.wrapping_add(1) as u16)?,
+    ]))
+}
+
+// Adds an 8-bit index to a 16-bit base address, for indexed address modes.
 //
 // Since the adder unit is only 8-bit, calculating the offset address could
 // require two cycles to process in cases where there is a carry from the first
-// adder cycle. As an optimization, the CPU will execute a speculative fetch
-// using the result of the first pass through the adder. If there is no carry,
-// then the speculative fetch was correct, and no second add is required. The
-// base cycle cost assumes this optimized scenario. If there is a carry,
-// however, the cycle cost needs to be increased by 1.
+// adder cycle. As an optimization, the CPU will execute a speculative read
+// using the result of the first pass through the adder, i.e. with the base's
+// high byte. If there is no carry, then the speculative read was correct, and
+// the instruction uses it. If there is a carry, the read was from the wrong
+// address, and it is repeated once the high byte has been fixed up.
 //
 // A carry after adding an 8-bit value to a 16-bit value means that the high
 // byte of the result will differ from the high byte of the base by exactly 1,
 // e.g. 0xA0FF and 0xA100. As such, this scenario is often referred to as a
 // "page crossing" between the base address and the offset address.
 //
-// Note that the address mode alone is not enough to determine whether the base
-// cycle count needs to be adjusted. In particular, the adjustment only occurs
-// for opcodes that read from memory, but do not write to it. Speculative
-// reads are fine, but speculative writes are not. Thus, opcodes that write to
-// memory, or read from and write to the same address, must incur the full cost
-// of a two-cycle add. The base cycle cost for those opcodes accounts for this.
-fn page_crossing_cycle_adjusment(opcode_type: opcode::Type, before: u16, after: u16) -> u64 {
-    if !opcode_type.writes_memory() && math::page_crossing(before, after) {
-        1
-    } else {
-        0
+// Speculative reads are fine, but speculative writes are not. Thus, opcodes
+// that write to memory, or read from and write to the same address, always
+// make the speculative read as a dummy read, then access the fixed address.
+// The 65C02 skips the dummy read for shifts and rotates when there is no
+// carry.
+fn indexed<B: Bus>(cpu: &mut Cpu<B>, opcode_type: opcode::Type, base: u16, index: u8) -> Operand {
+    let addr = base.wrapping_add(index as u16);
+    let always = opcode_type.writes_memory()
+        && !(cpu.variant == Variant::Cmos65C02 && opcode_type.is_shift());
+    if always || math::page_crossing(base, addr) {
+        cpu.bus_dummy_read((base & 0xFF00) | (addr & 0x00FF));
     }
+    Operand::Memory(addr)
 }
 
 #[test]
 fn test_decode_implicit() {
     let mut cpu = Cpu::new_test();
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Brk, AddressMode::Implicit),
-        (Operand::None, 0)
+        decode(&mut cpu, opcode::Type::Brk, AddressMode::Implicit).unwrap(),
+        Operand::None
     );
     assert_eq!(cpu.regs.pc, 0);
+    assert_eq!(cpu.cycles, 1);
 }
 
 #[test]
 fn test_decode_accumulator() {
     let mut cpu = Cpu::new_test();
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Asl, AddressMode::Accumulator),
-        (Operand::Accumulator, 0)
+        decode(&mut cpu, opcode::Type::Asl, AddressMode::Accumulator).unwrap(),
+        Operand::Accumulator
     );
     assert_eq!(cpu.regs.pc, 0);
 }
@@ -194,8 +289,8 @@ fn test_decode_immediate() {
     let mut cpu = Cpu::new_test();
     cpu.mem_write(0, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::Immediate),
-        (Operand::Immediate(0xAB), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::Immediate).unwrap(),
+        Operand::Immediate(0xAB)
     );
     assert_eq!(cpu.regs.pc, 1);
 }
@@ -206,8 +301,8 @@ fn test_decode_zero_page() {
     cpu.mem_write(0, 0x1F);
     cpu.mem_write(0x1F, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPage),
-        (Operand::Memory(0x1F), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPage).unwrap(),
+        Operand::Memory(0x1F)
     );
     assert_eq!(cpu.regs.pc, 1);
 }
@@ -218,20 +313,22 @@ fn test_decode_zero_page_x() {
     cpu.regs.x = 1;
     cpu.mem_write(0, 0x10);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageX),
-        (Operand::Memory(0x11), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageX).unwrap(),
+        Operand::Memory(0x11)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 2);
 
     // zero-page wrapping
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 2;
     cpu.mem_write(0, 0xFF);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageX),
-        (Operand::Memory(0x01), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageX).unwrap(),
+        Operand::Memory(0x01)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 2);
 }
 
 #[test]
@@ -240,8 +337,8 @@ fn test_decode_zero_page_y() {
     cpu.regs.y = 1;
     cpu.mem_write(0, 0x10);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageY),
-        (Operand::Memory(0x11), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageY).unwrap(),
+        Operand::Memory(0x11)
     );
     assert_eq!(cpu.regs.pc, 1);
 
@@ -250,8 +347,8 @@ fn test_decode_zero_page_y() {
     cpu.regs.y = 2;
     cpu.mem_write(0, 0xFF);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageY),
-        (Operand::Memory(0x01), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::ZeroPageY).unwrap(),
+        Operand::Memory(0x01)
     );
     assert_eq!(cpu.regs.pc, 1);
 }
@@ -261,8 +358,8 @@ fn test_decode_relative() {
     let mut cpu = Cpu::new_test();
     cpu.mem_write(0, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Beq, AddressMode::Relative),
-        (Operand::Immediate(0xAB), 0)
+        decode(&mut cpu, opcode::Type::Beq, AddressMode::Relative).unwrap(),
+        Operand::Immediate(0xAB)
     );
     assert_eq!(cpu.regs.pc, 1);
 }
@@ -273,8 +370,8 @@ fn test_decode_absolute() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Jsr, AddressMode::Absolute),
-        (Operand::Memory(0xABCD), 0)
+        decode(&mut cpu, opcode::Type::Jsr, AddressMode::Absolute).unwrap(),
+        Operand::Memory(0xABCD)
     );
     assert_eq!(cpu.regs.pc, 2);
 }
@@ -287,10 +384,11 @@ fn test_decode_absolute_x() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteX),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteX).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 2);
 
     // Read-only op, page crossing
     let mut cpu = Cpu::new_test();
@@ -298,10 +396,11 @@ fn test_decode_absolute_x() {
     cpu.mem_write(0, 0xFF);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteX),
-        (Operand::Memory(0xAC00), 1)
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteX).unwrap(),
+        Operand::Memory(0xAC00)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 3);
 
     // Write-only op
     let mut cpu = Cpu::new_test();
@@ -309,10 +408,11 @@ fn test_decode_absolute_x() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Sta, AddressMode::AbsoluteX),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Sta, AddressMode::AbsoluteX).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 3);
 
     // Read/write op
     let mut cpu = Cpu::new_test();
@@ -320,10 +420,11 @@ fn test_decode_absolute_x() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Dec, AddressMode::AbsoluteX),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Dec, AddressMode::AbsoluteX).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 3);
 }
 
 #[test]
@@ -334,10 +435,11 @@ fn test_decode_absolute_y() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteY),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteY).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 2);
 
     // Read-only op, page crossing
     let mut cpu = Cpu::new_test();
@@ -345,10 +447,11 @@ fn test_decode_absolute_y() {
     cpu.mem_write(0, 0xFF);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteY),
-        (Operand::Memory(0xAC00), 1)
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::AbsoluteY).unwrap(),
+        Operand::Memory(0xAC00)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 3);
 
     // Write-only op
     let mut cpu = Cpu::new_test();
@@ -356,10 +459,11 @@ fn test_decode_absolute_y() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Sta, AddressMode::AbsoluteY),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Sta, AddressMode::AbsoluteY).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 3);
 
     // Read/write op
     let mut cpu = Cpu::new_test();
@@ -367,25 +471,75 @@ fn test_decode_absolute_y() {
     cpu.mem_write(0, 0xCD);
     cpu.mem_write(1, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Dec, AddressMode::AbsoluteY),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Dec, AddressMode::AbsoluteY).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 3);
 }
 
 #[test]
 fn test_decode_indirect() {
+    // the pointer's high byte doesn't carry
     let mut cpu = Cpu::new_test();
     cpu.regs.x = 1;
     cpu.mem_write(0, 0xFF);
     cpu.mem_write(1, 1);
     cpu.mem_write(0x1FF, 0xCD);
-    cpu.mem_write(0x200, 0xAB);
+    cpu.mem_write(0x100, 0xAB);
+    cpu.mem_write(0x200, 0xEF);
+    assert_eq!(
+        decode(&mut cpu, opcode::Type::Jmp, AddressMode::Indirect).unwrap(),
+        Operand::Memory(0xABCD)
+    );
+    assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 4);
+
+    // the 65C02 fixes this, at the cost of a cycle
+    let mut cpu = Cpu::new_test();
+    cpu.variant = Variant::Cmos65C02;
+    cpu.mem_write(0, 0xFF);
+    cpu.mem_write(1, 1);
+    cpu.mem_write(0x1FF, 0xCD);
+    cpu.mem_write(0x200, 0xEF);
+    assert_eq!(
+        decode(&mut cpu, opcode::Type::Jmp, AddressMode::Indirect).unwrap(),
+        Operand::Memory(0xEFCD)
+    );
+    assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 5);
+}
+
+#[test]
+fn test_decode_zero_page_indirect() {
+    // the pointer's high byte wraps around the zero page
+    let mut cpu = Cpu::new_test();
+    cpu.regs.pc = 0x200;
+    cpu.mem_write(0x200, 0xFF);
+    cpu.mem_write(0xFF, 0xCD);
+    cpu.mem_write(0x00, 0xAB);
+    assert_eq!(
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::ZeroPageIndirect).unwrap(),
+        Operand::Memory(0xABCD)
+    );
+    assert_eq!(cpu.regs.pc, 0x201);
+    assert_eq!(cpu.cycles, 3);
+}
+
+#[test]
+fn test_decode_absolute_indirect_x() {
+    let mut cpu = Cpu::new_test();
+    cpu.regs.x = 2;
+    cpu.mem_write(0, 0xFF);
+    cpu.mem_write(1, 0x01);
+    cpu.mem_write(0x201, 0xCD);
+    cpu.mem_write(0x202, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Jmp, AddressMode::Indirect),
-        (Operand::Memory(0xABCD), 0)
+        decode(&mut cpu, opcode::Type::Jmp, AddressMode::AbsoluteIndirectX).unwrap(),
+        Operand::Memory(0xABCD)
     );
     assert_eq!(cpu.regs.pc, 2);
+    assert_eq!(cpu.cycles, 5);
 }
 
 #[test]
@@ -396,10 +550,11 @@ fn test_decode_indirect_x() {
     cpu.mem_write(0x10, 0xCD);
     cpu.mem_write(0x11, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::IndirectX),
-        (Operand::Memory(0xABCD), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::IndirectX).unwrap(),
+        Operand::Memory(0xABCD)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 4);
 
     // zero-page wrapping
     let mut cpu = Cpu::new_test();
@@ -408,10 +563,11 @@ fn test_decode_indirect_x() {
     cpu.mem_write(1, 0xCD);
     cpu.mem_write(2, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Adc, AddressMode::IndirectX),
-        (Operand::Memory(0xABCD), 0)
+        decode(&mut cpu, opcode::Type::Adc, AddressMode::IndirectX).unwrap(),
+        Operand::Memory(0xABCD)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 4);
 }
 
 #[test]
@@ -423,10 +579,11 @@ fn test_decode_indirect_y() {
     cpu.mem_write(0xF, 0xCD);
     cpu.mem_write(0x10, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Lda, AddressMode::IndirectY),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::IndirectY).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 3);
 
     // Read-only op, page crossing
     let mut cpu = Cpu::new_test();
@@ -435,10 +592,11 @@ fn test_decode_indirect_y() {
     cpu.mem_write(0xF, 0xFF);
     cpu.mem_write(0x10, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Lda, AddressMode::IndirectY),
-        (Operand::Memory(0xAC00), 1)
+        decode(&mut cpu, opcode::Type::Lda, AddressMode::IndirectY).unwrap(),
+        Operand::Memory(0xAC00)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 4);
 
     // Write-only op
     let mut cpu = Cpu::new_test();
@@ -447,10 +605,11 @@ fn test_decode_indirect_y() {
     cpu.mem_write(0xF, 0xCD);
     cpu.mem_write(0x10, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Sta, AddressMode::IndirectY),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Sta, AddressMode::IndirectY).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 4);
 
     // Read/write op
     let mut cpu = Cpu::new_test();
@@ -459,8 +618,9 @@ fn test_decode_indirect_y() {
     cpu.mem_write(0xF, 0xCD);
     cpu.mem_write(0x10, 0xAB);
     assert_eq!(
-        decode(&mut cpu, opcode::Type::Dec, AddressMode::IndirectY),
-        (Operand::Memory(0xABCE), 0)
+        decode(&mut cpu, opcode::Type::Dec, AddressMode::IndirectY).unwrap(),
+        Operand::Memory(0xABCE)
     );
     assert_eq!(cpu.regs.pc, 1);
+    assert_eq!(cpu.cycles, 4);
 }
@@ -1,11 +1,15 @@
 use super::super::mapper;
-use super::super::ppu;
+use super::bus::{Bus, BusAccess, NesBus};
+use super::dispatch::Dispatch;
+use super::error::{Access, CpuError};
 use super::status::Status;
+use super::step::Interrupt;
+use super::trace::Trace;
 use crate::math;
+use crate::savestate::{self, State};
 
 const STACK_BASE: u16 = 0x100;
 const STACK_SIZE: usize = 0x100;
-const RAM_SIZE: usize = 1 << 11;
 
 // Reference: http://obelisk.me.uk/6502/registers.html
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -36,11 +40,11 @@ pub struct Registers {
     // 1 - Zero flag: set if the last op resulted in zero.
     // 2 - Interrupt disable: set if interrupts have been disabled by SEI, and
     //     and not yet cleared by CLI.
-    // 3 - Decimal mode: no effect on the NES. For reference, this status is set
-    //     by SED and cleared by CLD. When set, arithmetic operations will obey
-    //     Binary Coded Decimal (BCD). A byte represents a two-digit decimal
-    //     number, with the low nibble representing the low digit, and the high
-    //     nibble representing the high digit.
+    // 3 - Decimal mode: set by SED and cleared by CLD. When set, arithmetic
+    //     operations will obey Binary Coded Decimal (BCD). A byte represents a
+    //     two-digit decimal number, with the low nibble representing the low
+    //     digit, and the high nibble representing the high digit. This has no
+    //     effect on the NES, whose CPU lacks decimal mode.
     // 4 - Break command: set during an interrupt sequence if the interrupt
     //     occurred due to user command.
     // 5 - Expansion bit: unused
@@ -81,6 +85,20 @@ impl Registers {
     }
 }
 
+// The 6502 family members that the core can emulate.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Variant {
+    // The NES CPU. Its 6502 core has the decimal mode circuitry disabled.
+    Ricoh2A03,
+
+    // The original NMOS 6502, which supports binary coded decimal arithmetic.
+    Nmos6502,
+
+    // The CMOS 65C02, which adds instructions and addressing modes, fixes the
+    // JMP ($xxFF) page wrap bug, and sets N and Z correctly in decimal mode.
+    Cmos65C02,
+}
+
 #[derive(Clone, Default)]
 pub struct Vectors {
     pub nmi: u16,
@@ -88,150 +106,248 @@ pub struct Vectors {
     pub irq_brk: u16,
 }
 
-pub struct Cpu {
+pub struct Cpu<B: Bus = NesBus> {
     pub cycles: u64,
     pub regs: Registers,
-    pub ram: [u8; RAM_SIZE],
+    pub variant: Variant,
+    pub dispatch: Dispatch,
+
+    // Set by the KIL opcodes. A halted CPU stops fetching instructions, and
+    // ignores interrupts, until it is reset.
+    pub halted: bool,
+
     pub vectors: Vectors,
-    pub ppu: ppu::Ppu,
-    pub mapper_prg: Box<dyn mapper::Prg>,
+    pub bus: B,
+
+    // When set, step() records each instruction before running it.
+    pub trace: Option<Trace>,
+
+    // When set, the program's reads and writes are recorded here, except
+    // for instruction fetches and dummy reads.
+    pub accesses: Option<Vec<BusAccess>>,
+
+    // The hardware interrupt serviced by the last step, if any.
+    pub last_interrupt: Option<Interrupt>,
 }
 
 impl Cpu {
     pub fn new(mapper_prg: Box<dyn mapper::Prg>, mapper_chr: Box<dyn mapper::Ppu>) -> Cpu {
-        Cpu {
-            cycles: 0,
-            regs: Registers::new(),
-            ram: [0; RAM_SIZE],
-            vectors: Vectors::default(),
-            ppu: ppu::Ppu::new(mapper_chr),
-            mapper_prg: mapper_prg,
-        }
+        Cpu::with_bus(NesBus::new(mapper_prg, mapper_chr))
     }
 
     pub fn new_test() -> Cpu {
         let (mapper_prg, mapper_chr) = mapper::test::new();
+        Cpu::new(Box::new(mapper_prg), Box::new(mapper_chr))
+    }
+}
 
+impl<B: Bus> Cpu<B> {
+    pub fn with_bus(bus: B) -> Cpu<B> {
         Cpu {
             cycles: 0,
             regs: Registers::new(),
-            ram: [0; RAM_SIZE],
+            variant: Variant::Ricoh2A03,
+            dispatch: Dispatch::Table,
+            halted: false,
             vectors: Vectors::default(),
-            ppu: ppu::Ppu::new(Box::new(mapper_chr)),
-            mapper_prg: Box::new(mapper_prg),
+            bus,
+            trace: None,
+            accesses: None,
+            last_interrupt: None,
         }
     }
 
+    // Whether ADC and SBC use BCD arithmetic.
+    pub fn decimal_mode(&self) -> bool {
+        self.variant != Variant::Ricoh2A03 && self.regs.status_check(Status::DecimalMode)
+    }
+
     pub fn cycle_add(&mut self, amt: u64) {
         self.cycles += amt;
+        for _ in 0..amt {
+            self.bus.tick();
+        }
+    }
+
+    // Loads the program counter from the reset vector, as on power-up.
+    // https://wiki.nesdev.com/w/index.php/CPU_power_up_state
+    pub fn reset(&mut self) {
+        self.halted = false;
+        self.regs.s = self.regs.s.wrapping_sub(3);
+        self.regs.status_set(Status::InterruptDisable, true);
+        self.regs.pc = self.mem_read16(0xFFFC);
+        self.cycle_add(7);
+    }
+
+    // Bus accesses made by the running program. Each one takes a CPU cycle,
+    // and the bus is ticked for that cycle before the access, so reads and
+    // writes with side effects happen at the right time.
+    // try_mem_read() and try_mem_write() access the bus without using any
+    // time.
+    pub fn bus_read(&mut self, addr: u16) -> Result<u8, CpuError> {
+        self.cycle_add(1);
+        let v = self.try_mem_read(addr)?;
+        self.record_access(addr, Access::Read, v);
+        Ok(v)
     }
 
-    pub fn mem_read(&self, addr: u16) -> u8 {
-        match addr {
-            // Source: https://wiki.nesdev.com/w/index.php/CPU_memory_map
-            0..=0x07FF => self.ram[addr as usize],
-            0x0800..=0x0FFF => self.ram[addr as usize - 0x0800],
-            0x1000..=0x17FF => self.ram[addr as usize - 0x1000],
-            0x1800..=0x1FFF => self.ram[addr as usize - 0x1800],
-            0x2000..=0x3FFF => match addr % 8 {
-                0 => unimplemented!(),
-                1 => unimplemented!(),
-                2 => unimplemented!(),
-                3 => unimplemented!(),
-                4 => unimplemented!(),
-                5 => unimplemented!(),
-                6 => unimplemented!(),
-                7 => unimplemented!(),
-                _ => unreachable!(),
-            },
-            0x4014 => unimplemented!(),
-            0x4020..=0xFFFF => self.mapper_prg.read(addr),
-            other => panic!("no memory map for address {:?}", other),
+    pub fn bus_read16(&mut self, addr: u16) -> Result<u16, CpuError> {
+        Ok(math::bytes_to_u16_le([
+            self.bus_read(addr)?,
+            self.bus_read(addr.wrapping_add(1))?,
+        ]))
+    }
+
+    // A read whose value the CPU discards. It still has side effects on
+    // memory-mapped registers and the data bus.
+    pub fn bus_dummy_read(&mut self, addr: u16) {
+        self.cycle_add(1);
+        let _ = self.try_mem_read(addr);
+    }
+
+    pub fn bus_write(&mut self, addr: u16, v: u8) -> Result<(), CpuError> {
+        self.cycle_add(1);
+        self.try_mem_write(addr, v)?;
+        self.record_access(addr, Access::Write, v);
+        Ok(())
+    }
+
+    fn record_access(&mut self, addr: u16, access: Access, value: u8) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(BusAccess {
+                addr,
+                access,
+                value,
+            });
         }
     }
 
-    pub fn mem_read16(&self, addr: u16) -> u16 {
-        math::bytes_to_u16_le([self.mem_read(addr), self.mem_read(addr + 1)])
+    pub fn try_mem_read(&mut self, addr: u16) -> Result<u8, CpuError> {
+        self.bus.read(addr)
+    }
+
+    // The bus may stall the CPU during a write, e.g. for DMA.
+    pub fn try_mem_write(&mut self, addr: u16, v: u8) -> Result<(), CpuError> {
+        self.bus.write(addr, v)?;
+        self.cycles += self.bus.take_stall_cycles();
+        Ok(())
+    }
+
+    // Reads without side effects or time, for debuggers, tracers and
+    // viewers. Returns None where the bus can't do that.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        self.bus.peek(addr)
+    }
+
+    pub fn peek_buf(&self, addr: u16, len: usize) -> Vec<Option<u8>> {
+        (0..len)
+            .map(|i| self.peek(addr.wrapping_add(i as u16)))
+            .collect()
+    }
+
+    // Untimed accesses for setup code and tests, which expect the addresses
+    // they touch to be mapped.
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        self.try_mem_read(addr).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub fn mem_read_buf(&self, addr: u16, len: usize) -> Vec<u8> {
+    pub fn mem_read16(&mut self, addr: u16) -> u16 {
+        math::bytes_to_u16_le([self.mem_read(addr), self.mem_read(addr.wrapping_add(1))])
+    }
+
+    pub fn mem_read_buf(&mut self, addr: u16, len: usize) -> Vec<u8> {
         let mut res = Vec::with_capacity(len);
         for i in 0..len {
-            res.push(self.mem_read(addr + i as u16));
+            res.push(self.mem_read(addr.wrapping_add(i as u16)));
         }
         res
     }
 
     pub fn mem_write(&mut self, addr: u16, v: u8) {
-        match addr {
-            0..=0x07FF => self.ram[addr as usize] = v,
-            0x0800..=0x0FFF => self.ram[addr as usize - 0x0800] = v,
-            0x1000..=0x17FF => self.ram[addr as usize - 0x1000] = v,
-            0x1800..=0x1FFF => self.ram[addr as usize - 0x1800] = v,
-            0x2000..=0x3FFF => match addr % 8 {
-                0 => unimplemented!(),
-                1 => unimplemented!(),
-                2 => unimplemented!(),
-                3 => unimplemented!(),
-                4 => unimplemented!(),
-                5 => unimplemented!(),
-                6 => unimplemented!(),
-                7 => unimplemented!(),
-                _ => unreachable!(),
-            },
-            0x4014 => unimplemented!(),
-            0x4020..=0xFFFF => self.mapper_prg.write(addr, v),
-            other => panic!("no memory map for address {:?}", other),
-        }
+        self.try_mem_write(addr, v)
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
     pub fn mem_write_buf(&mut self, addr: u16, buf: Vec<u8>) {
         for (i, v) in buf.iter().enumerate() {
-            self.mem_write(addr + i as u16, *v);
+            self.mem_write(addr.wrapping_add(i as u16), *v);
         }
     }
 
-    pub fn instruction_fetch_byte(&mut self) -> u8 {
-        self.regs.pc += 1;
-        self.mem_read(self.regs.pc - 1)
+    pub fn instruction_fetch_byte(&mut self) -> Result<u8, CpuError> {
+        let addr = self.regs.pc;
+        self.regs.pc = addr.wrapping_add(1);
+        self.cycle_add(1);
+        self.try_mem_read(addr)
     }
 
     pub fn stack_pointer(&self) -> u16 {
         STACK_BASE + self.regs.s as u16
     }
 
-    pub fn stack_push(&mut self, v: u8) {
-        self.mem_write(self.stack_pointer(), v);
-        self.regs.s -= 1;
+    pub fn stack_push(&mut self, v: u8) -> Result<(), CpuError> {
+        self.bus_write(self.stack_pointer(), v)?;
+        self.regs.s = self.regs.s.wrapping_sub(1);
+        Ok(())
     }
 
-    pub fn stack_push16(&mut self, v: u16) {
+    pub fn stack_push16(&mut self, v: u16) -> Result<(), CpuError> {
         let bytes = math::u16_to_bytes_le(v);
-        self.stack_push(bytes[0]);
-        self.stack_push(bytes[1]);
+        self.stack_push(bytes[0])?;
+        self.stack_push(bytes[1])
     }
 
-    pub fn stack_pop(&mut self) -> u8 {
-        self.regs.s += 1;
-        self.mem_read(STACK_BASE + self.regs.s as u16)
+    pub fn stack_pop(&mut self) -> Result<u8, CpuError> {
+        self.regs.s = self.regs.s.wrapping_add(1);
+        self.bus_read(STACK_BASE + self.regs.s as u16)
     }
 
-    pub fn stack_pop16(&mut self) -> u16 {
-        let a = self.stack_pop();
-        let b = self.stack_pop();
-        math::bytes_to_u16_le([b, a])
+    pub fn stack_pop16(&mut self) -> Result<u16, CpuError> {
+        let a = self.stack_pop()?;
+        let b = self.stack_pop()?;
+        Ok(math::bytes_to_u16_le([b, a]))
     }
 
     /// Returns the u8 value that would be returned during a stack pop. The
     /// offset will skip backward through pushed bytes. An offset of zero
-    /// denotes the most recent byte pushed to the stack.
+    /// denotes the most recent byte pushed to the stack. Unlike a pop, this
+    /// has no side effects, and the bus must be able to peek the stack page.
     pub fn stack_peek(&self, offset: u8) -> u8 {
-        // TODO: distinguish between read and load. mem_read() may be updated
-        // to consume CPU cycles.
-        self.mem_read(STACK_BASE + (self.regs.s + offset + 1) as u16)
+        let addr = STACK_BASE + self.regs.s.wrapping_add(offset).wrapping_add(1) as u16;
+        self.peek(addr)
+            .unwrap_or_else(|| panic!("can't peek stack at ${:04X}", addr))
     }
 
     pub fn stack_peek16(&self, offset: u8) -> u16 {
-        math::bytes_to_u16_le([self.stack_peek(offset + 1), self.stack_peek(offset)])
+        math::bytes_to_u16_le([
+            self.stack_peek(offset.wrapping_add(1)),
+            self.stack_peek(offset),
+        ])
+    }
+}
+
+impl<B: Bus + State> State for Cpu<B> {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.u64(self.cycles);
+        w.u8(self.regs.a);
+        w.u8(self.regs.x);
+        w.u8(self.regs.y);
+        w.u16(self.regs.pc);
+        w.u8(self.regs.s);
+        w.u8(self.regs.p);
+        w.bool(self.halted);
+        self.bus.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.cycles = r.u64()?;
+        self.regs.a = r.u8()?;
+        self.regs.x = r.u8()?;
+        self.regs.y = r.u8()?;
+        self.regs.pc = r.u16()?;
+        self.regs.s = r.u8()?;
+        self.regs.p = r.u8()?;
+        self.halted = r.bool()?;
+        self.bus.load_state(r)
     }
 }
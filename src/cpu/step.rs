@@ -1,17 +1,81 @@
+#[cfg(test)]
+use super::address_mode::AddressMode;
+#[cfg(test)]
 use super::assemble;
-use super::execute;
+use super::bus::Bus;
+use super::dispatch::{self, Dispatch};
+#[cfg(test)]
+use super::error::Access;
+use super::error::CpuError;
+#[cfg(test)]
 use super::opcode;
-use super::operand;
 use super::state;
+#[cfg(test)]
 use super::status;
+use super::status::Status;
 
-impl state::Cpu {
-    pub fn step(&mut self) {
-        let (opcode_type, addr_mode, base_cost) =
-            opcode::decode(self.instruction_fetch_byte()).unwrap();
-        let (operand, operand_cost) = operand::decode(self, opcode_type, addr_mode);
-        execute::execute(opcode_type, self, operand);
-        self.cycle_add(base_cost + operand_cost);
+const NMI_VECTOR: u16 = 0xFFFA;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+// Hardware interrupts. BRK uses the IRQ vector, but runs as an instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+impl<B: Bus> state::Cpu<B> {
+    // Runs one instruction, or services one interrupt. On error, the CPU is
+    // left wherever execution stopped, so that the caller can inspect it.
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        self.last_interrupt = None;
+
+        // The rest of the console keeps running while the CPU is jammed.
+        if self.halted {
+            self.cycle_add(1);
+            return Err(CpuError::Halted {
+                pc: self.regs.pc.wrapping_sub(1),
+            });
+        }
+
+        if self.bus.take_nmi() {
+            self.last_interrupt = Some(Interrupt::Nmi);
+            return self.interrupt(NMI_VECTOR);
+        }
+        if self.bus.irq() && !self.regs.status_check(Status::InterruptDisable) {
+            self.last_interrupt = Some(Interrupt::Irq);
+            return self.interrupt(IRQ_VECTOR);
+        }
+
+        if self.trace.is_some() {
+            self.trace_instruction();
+        }
+
+        // Every bus access takes a cycle, so the instruction's cost is
+        // accounted for as it runs.
+        let encoding = self.instruction_fetch_byte()?;
+        match self.dispatch {
+            Dispatch::Table => dispatch::handler_for(self.variant, encoding)(self),
+            Dispatch::Decode => dispatch::decode_and_execute(self, encoding),
+        }
+    }
+
+    // Services a hardware interrupt. Unlike BRK, the pushed status has the
+    // break flag clear.
+    // https://wiki.nesdev.com/w/index.php/CPU_interrupts
+    fn interrupt(&mut self, vector: u16) -> Result<(), CpuError> {
+        // two reads of the opcode that was preempted
+        self.bus_dummy_read(self.regs.pc);
+        self.bus_dummy_read(self.regs.pc);
+        self.stack_push16(self.regs.pc)?;
+        let p = (self.regs.p & !Status::BreakCommand.mask()) | Status::ExpansionBit.mask();
+        self.stack_push(p)?;
+        self.regs.status_set(Status::InterruptDisable, true);
+        if self.variant == state::Variant::Cmos65C02 {
+            self.regs.status_set(Status::DecimalMode, false);
+        }
+        self.regs.pc = self.bus_read16(vector)?;
+        Ok(())
     }
 }
 
@@ -25,13 +89,13 @@ fn test() {
     cpu.mem_write(4, 0x69); // adc #$FF
     cpu.mem_write(5, 0xFF);
 
-    cpu.step();
+    cpu.step().unwrap();
     assert_eq!(cpu.regs.pc, 2);
     assert_eq!(cpu.regs.a, 1);
     assert_eq!(cpu.regs.p, 0);
     assert_eq!(cpu.cycles, 2);
 
-    cpu.step();
+    cpu.step().unwrap();
     assert_eq!(cpu.regs.pc, 4);
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(
@@ -40,7 +104,7 @@ fn test() {
     );
     assert_eq!(cpu.cycles, 4);
 
-    cpu.step();
+    cpu.step().unwrap();
     assert_eq!(cpu.regs.pc, 6);
     assert_eq!(cpu.regs.a, 0);
     assert_eq!(
@@ -79,7 +143,7 @@ brk
     cpu.mem_write_buf(0, assemble::assemble(asm, 0).unwrap());
 
     while !cpu.regs.status_check(status::Status::BreakCommand) {
-        cpu.step();
+        cpu.step().unwrap();
     }
 
     assert_eq!(
@@ -87,3 +151,335 @@ brk
         vec![1, 1, 2, 3, 5, 8, 13, 21, 34, 55]
     );
 }
+
+#[test]
+fn test_nmi() {
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write(0xFFFA, 0x00);
+    cpu.mem_write(0xFFFB, 0x90);
+    cpu.regs.pc = 0x8000;
+    cpu.regs.p = Status::Carry.mask();
+
+    // enable NMI and run until vblank
+    cpu.mem_write(0x2000, 0b1000_0000);
+    while cpu.mem_read(0x2002) & 0b1000_0000 == 0 {
+        cpu.cycle_add(1);
+    }
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs.pc, 0x9000);
+    assert!(cpu.regs.status_check(Status::InterruptDisable));
+    assert_eq!(
+        cpu.stack_peek(0),
+        Status::Carry.mask() | Status::ExpansionBit.mask()
+    );
+    assert_eq!(cpu.stack_peek16(1), 0x8000);
+}
+
+#[test]
+fn test_unofficial() {
+    let asm = "
+lax $10
+dcp $10
+sax $11
+kil
+    ";
+
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write_buf(0, assemble::assemble(asm, 0).unwrap());
+    cpu.mem_write(0x10, 0x37);
+
+    cpu.step().unwrap();
+    assert_eq!((cpu.regs.a, cpu.regs.x), (0x37, 0x37));
+    assert_eq!(cpu.cycles, 3);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0x36);
+    assert_eq!(cpu.cycles, 8);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.mem_read(0x11), 0x37);
+    assert_eq!(cpu.cycles, 11);
+
+    // jammed until reset
+    assert_eq!(cpu.step(), Err(CpuError::Halted { pc: 6 }));
+    assert!(cpu.halted);
+    assert_eq!(cpu.step(), Err(CpuError::Halted { pc: 6 }));
+    assert_eq!(cpu.regs.pc, 7);
+    cpu.reset();
+    assert!(!cpu.halted);
+}
+
+// The bus accesses made by each instruction should add up to its documented
+// cycle count.
+#[test]
+fn test_cycle_counts() {
+    for &variant in &[state::Variant::Ricoh2A03, state::Variant::Cmos65C02] {
+        for encoding in 0..=255 {
+            let (opcode_type, addr_mode, base_cost) = match opcode::decode(variant, encoding) {
+                Some(decoded) => decoded,
+                None => continue,
+            };
+            if addr_mode == AddressMode::Relative {
+                continue; // see branch tests
+            }
+
+            let indexed = matches!(
+                addr_mode,
+                AddressMode::AbsoluteX | AddressMode::AbsoluteY | AddressMode::IndirectY
+            );
+            for &page_crossing in &[false, true] {
+                if page_crossing && !indexed {
+                    continue;
+                }
+
+                // All operands resolve to $0310, or $040F with page crossing.
+                let mut cpu = state::Cpu::new_test();
+                cpu.variant = variant;
+                cpu.regs.pc = 0x200;
+                cpu.regs.s = 0xF0;
+                cpu.mem_write_buf(0x200, vec![encoding, 0x10, 0x03]);
+                cpu.mem_write_buf(0x10, vec![0x10, 0x03]);
+                if page_crossing {
+                    cpu.regs.x = 0xFF;
+                    cpu.regs.y = 0xFF;
+                }
+
+                if let Err(err) = cpu.step() {
+                    assert_eq!(opcode_type, opcode::Type::Kil, "{:#04X}: {}", encoding, err);
+                }
+
+                // 65C02 shifts only take the extra cycle on a page crossing
+                let fixed_cost = opcode_type.writes_memory()
+                    && !(variant == state::Variant::Cmos65C02 && opcode_type.is_shift());
+                let expected = if page_crossing && !fixed_cost {
+                    base_cost + 1
+                } else {
+                    base_cost
+                };
+                assert_eq!(
+                    cpu.cycles, expected,
+                    "{:?} {:#04X} {:?} {:?} page crossing: {}",
+                    variant, encoding, opcode_type, addr_mode, page_crossing
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dummy_read() {
+    let wait_for_vblank = |cpu: &mut state::Cpu| {
+        while cpu.bus.ppu.scanline() != 242 {
+            cpu.cycle_add(1);
+        }
+    };
+
+    // lda $2102 (a mirror of PPUSTATUS) sees the vblank flag
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write_buf(0, vec![0xAD, 0x02, 0x21]);
+    wait_for_vblank(&mut cpu);
+    cpu.step().unwrap();
+    assert_ne!(cpu.regs.a & 0b1000_0000, 0);
+
+    // lda $20FF,x reads $2002 before fixing up the high byte, which clears
+    // the flag before the real read from $2102
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write_buf(0, vec![0xBD, 0xFF, 0x20]);
+    cpu.regs.x = 3;
+    wait_for_vblank(&mut cpu);
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs.a & 0b1000_0000, 0);
+}
+
+// RAM below $8000 and nothing above it, which rejects reads and writes there.
+#[cfg(test)]
+struct StrictBus(super::bus::RamBus);
+
+#[cfg(test)]
+impl Bus for StrictBus {
+    fn read(&mut self, addr: u16) -> Result<u8, CpuError> {
+        if addr >= 0x8000 {
+            return Err(CpuError::Unmapped {
+                addr,
+                access: Access::Read,
+            });
+        }
+        self.0.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, v: u8) -> Result<(), CpuError> {
+        if addr >= 0x8000 {
+            return Err(CpuError::Unmapped {
+                addr,
+                access: Access::Write,
+            });
+        }
+        self.0.write(addr, v)
+    }
+}
+
+#[test]
+fn test_unmapped() {
+    // the NES ignores writes to ROM and the test registers
+    let (prg, chr) = crate::mapper::nrom::new(
+        &[0; crate::mapper::nrom::PRG_SIZE],
+        &[],
+        crate::mapper::Mirroring::Vertical,
+    );
+    let mut cpu = state::Cpu::new(Box::new(prg), Box::new(chr));
+    cpu.mem_write_buf(0, vec![0x8D, 0x18, 0x40, 0x8D, 0x00, 0x80]); // sta $4018; sta $8000
+    cpu.regs.pc = 0;
+    cpu.regs.a = 0xAB;
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.mem_read(0x8000), 0);
+
+    // other buses may reject them, and reads too
+    let mut cpu = state::Cpu::with_bus(StrictBus(super::bus::RamBus::new()));
+    cpu.mem_write_buf(0, vec![0x8D, 0x00, 0x80, 0xAD, 0x01, 0x80]); // sta $8000; lda $8001
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::Unmapped {
+            addr: 0x8000,
+            access: Access::Write
+        })
+    );
+    cpu.regs.pc = 3;
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::Unmapped {
+            addr: 0x8001,
+            access: Access::Read
+        })
+    );
+}
+
+#[test]
+fn test_open_bus() {
+    // The test mapper only maps $8000-$FFFF. lda $5000 sees the high byte of
+    // the address, which was the last value on the bus.
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write_buf(0, vec![0xAD, 0x00, 0x50]);
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs.a, 0x50);
+
+    // lda $4FFF,x makes a dummy read from $4F00 before reading $5000
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write_buf(0, vec![0xBD, 0xFF, 0x4F]);
+    cpu.regs.x = 1;
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs.a, 0x4F);
+
+    // lda ($10),y sees the high byte of the pointer
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write_buf(0, vec![0xB1, 0x10]);
+    cpu.mem_write_buf(0x10, vec![0x00, 0x40]);
+    cpu.regs.y = 0x18;
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs.a, 0x40);
+
+    // write-only registers, and bit 5 of $4015
+    let mut cpu = state::Cpu::new_test();
+    cpu.mem_write(0x10, 0xA5);
+    assert_eq!(cpu.mem_read(0x4000), 0xA5);
+    assert_eq!(cpu.mem_read(0x401F), 0xA5);
+    cpu.bus.data_bus = 0xFF;
+    assert_eq!(cpu.mem_read(0x4015), 0b0010_0000);
+}
+
+// Both ways of dispatching run programs identically.
+#[test]
+fn test_dispatch() {
+    let code = assemble::assemble(
+        "
+        ldx #$00
+        loop: lda $0300,x
+        adc #$03
+        sta $0300,x
+        pha
+        pla
+        ror $10
+        lda ($10),y
+        iny
+        inx
+        bne loop
+        jmp loop
+        ",
+        0x0600,
+    )
+    .unwrap();
+    let run = |dispatch| {
+        let mut cpu = state::Cpu::with_bus(super::bus::RamBus::new());
+        cpu.dispatch = dispatch;
+        cpu.mem_write_buf(0x0600, code.clone());
+        cpu.regs.pc = 0x0600;
+        for _ in 0..10_000 {
+            cpu.step().unwrap();
+        }
+        (cpu.cycles, cpu.regs, cpu.bus.mem)
+    };
+    assert!(run(Dispatch::Table) == run(Dispatch::Decode));
+}
+
+// The stack pointer and program counter wrap around, as on hardware.
+#[test]
+fn test_wrapping() {
+    let mut cpu = state::Cpu::with_bus(super::bus::RamBus::new());
+    cpu.mem_write_buf(
+        0x0600,
+        assemble::assemble("pha\npha\npha\npla\npla\npla", 0x0600).unwrap(),
+    );
+    cpu.regs.pc = 0x0600;
+    cpu.regs.s = 0x01;
+    cpu.regs.a = 0xAB;
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.regs.s, 0xFE);
+    assert_eq!(cpu.mem_read_buf(0x0100, 2), vec![0xAB, 0xAB]);
+    assert_eq!(cpu.mem_read(0x01FF), 0xAB);
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.regs.s, 0x01);
+
+    // inx at $FFFF
+    cpu.mem_write(0xFFFF, 0xE8);
+    cpu.regs.pc = 0xFFFF;
+    cpu.regs.x = 0;
+    cpu.step().unwrap();
+    assert_eq!((cpu.regs.pc, cpu.regs.x), (0x0000, 1));
+
+    // lda #$42 at $FFFF, with its operand at $0000
+    cpu.mem_write(0xFFFF, 0xA9);
+    cpu.mem_write(0x0000, 0x42);
+    cpu.regs.pc = 0xFFFF;
+    cpu.step().unwrap();
+    assert_eq!((cpu.regs.pc, cpu.regs.a), (0x0001, 0x42));
+    assert_eq!(cpu.mem_read16(0xFFFF), 0x42A9);
+}
+
+// Outside the NES memory map, the whole address space is writable RAM.
+#[test]
+fn test_ram_bus() {
+    let asm = "
+lda #$AB
+sta $4018
+sta $8000
+brk
+    ";
+
+    let mut cpu = state::Cpu::with_bus(super::bus::RamBus::new());
+    cpu.mem_write_buf(0x6000, assemble::assemble(asm, 0x6000).unwrap());
+    cpu.mem_write_buf(0xFFFC, vec![0x00, 0x60, 0x00, 0x90]);
+    cpu.reset();
+    assert_eq!(cpu.regs.pc, 0x6000);
+
+    while !cpu.regs.status_check(Status::BreakCommand) {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.mem_read(0x4018), 0xAB);
+    assert_eq!(cpu.mem_read(0x8000), 0xAB);
+    assert_eq!(cpu.regs.pc, 0x9000);
+}
@@ -0,0 +1,331 @@
+// Execution traces in the format of nestest.log, the reference log for the
+// nestest ROM, so that traces can be diffed against other emulators:
+//
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+//
+// Each line shows the CPU state before the instruction runs. Memory operands
+// show the effective address and the value there, which are read with
+// Bus::peek() so that tracing doesn't change what the program sees. Values
+// that the bus can't peek are shown as ??.
+// Reference: https://www.qmtpro.com/~nes/misc/nestest.log
+
+use super::address_mode::AddressMode;
+use super::bus::Bus;
+use super::opcode;
+use super::state::{Cpu, Variant};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+pub enum Trace {
+    // Lines are written out as they are produced.
+    Writer(Box<dyn Write>),
+
+    // Lines are kept in memory, e.g. for tests to compare.
+    Buffer(Vec<String>),
+}
+
+impl Trace {
+    pub fn file(path: &Path) -> io::Result<Trace> {
+        Ok(Trace::Writer(Box::new(io::BufWriter::new(
+            fs::File::create(path)?,
+        ))))
+    }
+
+    pub fn push(&mut self, line: String) -> io::Result<()> {
+        match self {
+            Trace::Writer(w) => writeln!(w, "{}", line),
+            Trace::Buffer(lines) => {
+                lines.push(line);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    // Records the instruction at pc. A trace that fails to write is
+    // dropped, rather than interrupting the program.
+    pub(super) fn trace_instruction(&mut self) {
+        let line = trace_line(self);
+        if let Some(trace) = &mut self.trace {
+            if let Err(e) = trace.push(line) {
+                eprintln!("trace: {}", e);
+                self.trace = None;
+            }
+        }
+    }
+}
+
+pub fn trace_line<B: Bus>(cpu: &Cpu<B>) -> String {
+    let pc = cpu.regs.pc;
+    let peek = |addr: u16| cpu.bus.peek(addr);
+
+    let encoding = peek(pc);
+    let decoded = encoding.and_then(|e| opcode::decode(cpu.variant, e));
+    let size = match decoded {
+        Some((_, addr_mode, _)) => 1 + addr_mode.operand_size(),
+        None => 1,
+    };
+    let bytes: Vec<Option<u8>> = (0..size as u16).map(|i| peek(pc.wrapping_add(i))).collect();
+    let hex = bytes.iter().map(|&b| hex8(b)).collect::<Vec<_>>().join(" ");
+
+    let (marker, text) = match (encoding, decoded) {
+        (Some(encoding), Some((opcode_type, addr_mode, _))) => {
+            let marker = if opcode::is_unofficial(cpu.variant, encoding) {
+                '*'
+            } else {
+                ' '
+            };
+            let operand = operand_text(cpu, opcode_type, addr_mode, &bytes[1..]);
+            let mnemonic = match opcode_type {
+                // nestest.log's name for ISC
+                opcode::Type::Isc => "ISB",
+                _ => opcode_type.mnemonic(),
+            };
+            if operand.is_empty() {
+                (marker, mnemonic.to_string())
+            } else {
+                (marker, format!("{} {}", mnemonic, operand))
+            }
+        }
+        _ => (' ', "???".to_string()),
+    };
+
+    let ppu = match cpu.bus.ppu_position() {
+        Some((scanline, dot)) => format!("PPU:{:>3},{:>3} ", scanline, dot),
+        None => String::new(),
+    };
+
+    format!(
+        "{:04X}  {:<9}{}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} {}CYC:{}",
+        pc,
+        hex,
+        marker,
+        text,
+        cpu.regs.a,
+        cpu.regs.x,
+        cpu.regs.y,
+        cpu.regs.p,
+        cpu.regs.s,
+        ppu,
+        cpu.cycles
+    )
+}
+
+fn operand_text<B: Bus>(
+    cpu: &Cpu<B>,
+    opcode_type: opcode::Type,
+    addr_mode: AddressMode,
+    bytes: &[Option<u8>],
+) -> String {
+    let peek = |addr: u16| cpu.bus.peek(addr);
+    let peek16 = |addr: u16, hi_addr: u16| match (peek(addr), peek(hi_addr)) {
+        (Some(lo), Some(hi)) => Some(u16::from_le_bytes([lo, hi])),
+        _ => None,
+    };
+    let zero_page16 = |ptr: u8| peek16(ptr as u16, ptr.wrapping_add(1) as u16);
+
+    // Operands that can't be peeked are treated as zero.
+    let byte = bytes.first().copied().flatten().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(1).copied().flatten().unwrap_or(0)]);
+    let (x, y) = (cpu.regs.x, cpu.regs.y);
+
+    match addr_mode {
+        AddressMode::Implicit => String::new(),
+        AddressMode::Accumulator => "A".to_string(),
+        AddressMode::Immediate => format!("#${:02X}", byte),
+        AddressMode::ZeroPage => format!("${:02X} = {}", byte, hex8(peek(byte as u16))),
+        AddressMode::ZeroPageX => {
+            let addr = byte.wrapping_add(x);
+            format!(
+                "${:02X},X @ {:02X} = {}",
+                byte,
+                addr,
+                hex8(peek(addr as u16))
+            )
+        }
+        AddressMode::ZeroPageY => {
+            let addr = byte.wrapping_add(y);
+            format!(
+                "${:02X},Y @ {:02X} = {}",
+                byte,
+                addr,
+                hex8(peek(addr as u16))
+            )
+        }
+        AddressMode::Relative => {
+            let next = cpu.regs.pc.wrapping_add(2);
+            format!("${:04X}", next.wrapping_add(byte as i8 as u16))
+        }
+        AddressMode::Absolute => {
+            if matches!(opcode_type, opcode::Type::Jmp | opcode::Type::Jsr) {
+                format!("${:04X}", word)
+            } else {
+                format!("${:04X} = {}", word, hex8(peek(word)))
+            }
+        }
+        AddressMode::AbsoluteX => {
+            let addr = word.wrapping_add(x as u16);
+            format!("${:04X},X @ {:04X} = {}", word, addr, hex8(peek(addr)))
+        }
+        AddressMode::AbsoluteY => {
+            let addr = word.wrapping_add(y as u16);
+            format!("${:04X},Y @ {:04X} = {}", word, addr, hex8(peek(addr)))
+        }
+        AddressMode::Indirect => {
+            let hi_addr = if cpu.variant == Variant::Cmos65C02 {
+                word.wrapping_add(1)
+            } else {
+                (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF)
+            };
+            format!("(${:04X}) = {}", word, hex16(peek16(word, hi_addr)))
+        }
+        AddressMode::IndirectX => {
+            let ptr = byte.wrapping_add(x);
+            let addr = zero_page16(ptr);
+            format!(
+                "(${:02X},X) @ {:02X} = {} = {}",
+                byte,
+                ptr,
+                hex16(addr),
+                hex8(addr.and_then(peek))
+            )
+        }
+        AddressMode::IndirectY => {
+            let base = zero_page16(byte);
+            let addr = base.map(|base| base.wrapping_add(y as u16));
+            format!(
+                "(${:02X}),Y = {} @ {} = {}",
+                byte,
+                hex16(base),
+                hex16(addr),
+                hex8(addr.and_then(peek))
+            )
+        }
+        AddressMode::ZeroPageIndirect => {
+            let addr = zero_page16(byte);
+            format!(
+                "(${:02X}) = {} = {}",
+                byte,
+                hex16(addr),
+                hex8(addr.and_then(peek))
+            )
+        }
+        AddressMode::AbsoluteIndirectX => {
+            let ptr = word.wrapping_add(x as u16);
+            format!(
+                "(${:04X},X) @ {:04X} = {}",
+                word,
+                ptr,
+                hex16(peek16(ptr, ptr.wrapping_add(1)))
+            )
+        }
+    }
+}
+
+fn hex8(v: Option<u8>) -> String {
+    match v {
+        Some(v) => format!("{:02X}", v),
+        None => "??".to_string(),
+    }
+}
+
+fn hex16(v: Option<u16>) -> String {
+    match v {
+        Some(v) => format!("{:04X}", v),
+        None => "????".to_string(),
+    }
+}
+
+// Lines from nestest.log, reproduced by setting up the same state.
+#[test]
+fn test_trace_line() {
+    use super::assemble;
+
+    let mut cpu = Cpu::new_test();
+    cpu.regs.pc = 0x0600;
+    cpu.regs.p = 0x24;
+    cpu.regs.s = 0xFD;
+    cpu.cycles = 7;
+    cpu.mem_write_buf(0x0600, vec![0x4C, 0xF5, 0xC5]);
+    assert_eq!(
+        trace_line(&cpu),
+        "0600  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:7"
+    );
+
+    let line = |cpu: &mut Cpu, asm: &str| {
+        cpu.mem_write_buf(0x0600, assemble::assemble(asm, 0x0600).unwrap());
+        let line = trace_line(cpu);
+        line[..48].trim_end().to_string()
+    };
+
+    cpu.regs.x = 0x02;
+    cpu.regs.y = 0x34;
+    cpu.mem_write_buf(0x80, vec![0x00, 0x02, 0x00, 0x03]);
+    cpu.mem_write(0x0200, 0x5A);
+    cpu.mem_write(0x0334, 0x89);
+    assert_eq!(line(&mut cpu, "lda #$01"), "0600  A9 01     LDA #$01");
+    assert_eq!(line(&mut cpu, "ldx $80"), "0600  A6 80     LDX $80 = 00");
+    assert_eq!(
+        line(&mut cpu, "sta $7E,x"),
+        "0600  95 7E     STA $7E,X @ 80 = 00"
+    );
+    assert_eq!(
+        line(&mut cpu, "lda $0200"),
+        "0600  AD 00 02  LDA $0200 = 5A"
+    );
+    assert_eq!(
+        line(&mut cpu, "lda $0300,y"),
+        "0600  B9 00 03  LDA $0300,Y @ 0334 = 89"
+    );
+    assert_eq!(
+        line(&mut cpu, "lda ($7E,x)"),
+        "0600  A1 7E     LDA ($7E,X) @ 80 = 0200 = 5A"
+    );
+    assert_eq!(
+        line(&mut cpu, "lda ($82),y"),
+        "0600  B1 82     LDA ($82),Y = 0300 @ 0334 = 89"
+    );
+    assert_eq!(line(&mut cpu, "lsr"), "0600  4A        LSR A");
+    assert_eq!(line(&mut cpu, "a: bne a"), "0600  D0 FE     BNE $0600");
+    assert_eq!(
+        line(&mut cpu, "jmp ($0080)"),
+        "0600  6C 80 00  JMP ($0080) = 0200"
+    );
+
+    // unofficial opcodes are marked
+    cpu.mem_write_buf(0x0600, vec![0x04, 0x80]);
+    assert_eq!(&trace_line(&cpu)[..28], "0600  04 80    *NOP $80 = 00");
+    cpu.mem_write_buf(0x0600, vec![0xE7, 0x80]);
+    assert_eq!(&trace_line(&cpu)[..28], "0600  E7 80    *ISB $80 = 00");
+
+    // registers are peeked, showing the PPU's latch here
+    cpu.mem_write(0x2003, 0x1F);
+    assert_eq!(
+        line(&mut cpu, "lda $2002"),
+        "0600  AD 02 20  LDA $2002 = 1F"
+    );
+}
+
+#[test]
+fn test_trace_step() {
+    use super::bus::RamBus;
+
+    let mut cpu = Cpu::with_bus(RamBus::new());
+    cpu.mem_write_buf(0, vec![0xE8, 0xE8]);
+    cpu.trace = Some(Trace::Buffer(Vec::new()));
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+
+    match &cpu.trace {
+        Some(Trace::Buffer(lines)) => assert_eq!(
+            lines,
+            &vec![
+                "0000  E8        INX                             A:00 X:00 Y:00 P:00 SP:FF CYC:0",
+                "0001  E8        INX                             A:00 X:01 Y:00 P:00 SP:FF CYC:2",
+            ]
+        ),
+        _ => panic!("no trace buffer"),
+    }
+}
@@ -0,0 +1,739 @@
+// Breakpoints stop emulation when something happens: the CPU reaches an
+// address or an opcode, the program reads or writes CPU memory or VRAM, or
+// an interrupt is taken. Each can have a condition on the CPU state, like
+// A == $20 && X > 3, and an ignore count that lets its next hits pass.
+//
+// Execution and opcode breakpoints stop before the instruction runs. The
+// rest stop after the instruction that caused them.
+
+use crate::cpu::{disassemble, Access, BusAccess, Cpu, CpuError, Interrupt, Variant};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+const BRK: u8 = 0x00;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Exec(RangeInclusive<u16>),
+    Read(RangeInclusive<u16>),
+    Write(RangeInclusive<u16>),
+
+    // Accesses through PPUDATA
+    VramRead(RangeInclusive<u16>),
+    VramWrite(RangeInclusive<u16>),
+
+    Opcode(Opcode),
+    Nmi,
+    Irq,
+    Brk,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Opcode {
+    Encoding(u8),
+
+    // Any encoding of the instruction
+    Mnemonic(String),
+}
+
+impl Opcode {
+    pub fn parse(s: &str, variant: Variant) -> Result<Opcode, String> {
+        if let Ok(encoding) = super::parse_byte(s) {
+            return Ok(Opcode::Encoding(encoding));
+        }
+        let mnemonic = s.to_ascii_uppercase();
+        if (0..=255).any(|e| disassemble::mnemonic(variant, e) == Some(&mnemonic)) {
+            return Ok(Opcode::Mnemonic(mnemonic));
+        }
+        Err(format!("invalid opcode {}", s))
+    }
+
+    fn matches(&self, variant: Variant, encoding: u8) -> bool {
+        match self {
+            Opcode::Encoding(e) => *e == encoding,
+            Opcode::Mnemonic(m) => disassemble::mnemonic(variant, encoding) == Some(m),
+        }
+    }
+}
+
+// Things that happen while the CPU runs.
+enum Event {
+    Exec { pc: u16, encoding: Option<u8> },
+    Access(BusAccess),
+    VramAccess(BusAccess),
+    Interrupt(Kind),
+}
+
+impl Event {
+    fn access(&self) -> Option<&BusAccess> {
+        match self {
+            Event::Access(access) | Event::VramAccess(access) => Some(access),
+            _ => None,
+        }
+    }
+}
+
+impl Kind {
+    fn matches(&self, event: &Event, variant: Variant) -> bool {
+        let in_range = |range: &RangeInclusive<u16>, access: &BusAccess, kind: Access| {
+            access.access == kind && range.contains(&access.addr)
+        };
+        match (self, event) {
+            (Kind::Exec(range), Event::Exec { pc, .. }) => range.contains(pc),
+            (Kind::Opcode(opcode), Event::Exec { encoding, .. }) => {
+                encoding.is_some_and(|e| opcode.matches(variant, e))
+            }
+            (Kind::Read(range), Event::Access(access)) => in_range(range, access, Access::Read),
+            (Kind::Write(range), Event::Access(access)) => in_range(range, access, Access::Write),
+            (Kind::VramRead(range), Event::VramAccess(access)) => {
+                in_range(range, access, Access::Read)
+            }
+            (Kind::VramWrite(range), Event::VramAccess(access)) => {
+                in_range(range, access, Access::Write)
+            }
+            (kind, Event::Interrupt(interrupt)) => kind == interrupt,
+            _ => false,
+        }
+    }
+
+    fn needs_accesses(&self) -> bool {
+        matches!(self, Kind::Read(_) | Kind::Write(_))
+    }
+
+    fn needs_vram_accesses(&self) -> bool {
+        matches!(self, Kind::VramRead(_) | Kind::VramWrite(_))
+    }
+}
+
+// Kinds are shown as the commands that create them.
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = |f: &mut fmt::Formatter, command, range: &RangeInclusive<u16>| {
+            if range.start() == range.end() {
+                write!(f, "{} ${:04X}", command, range.start())
+            } else {
+                write!(f, "{} ${:04X}-${:04X}", command, range.start(), range.end())
+            }
+        };
+        match self {
+            Kind::Exec(r) => range(f, "break", r),
+            Kind::Read(r) => range(f, "rwatch", r),
+            Kind::Write(r) => range(f, "watch", r),
+            Kind::VramRead(r) => range(f, "vrwatch", r),
+            Kind::VramWrite(r) => range(f, "vwatch", r),
+            Kind::Opcode(Opcode::Encoding(e)) => write!(f, "opbreak ${:02X}", e),
+            Kind::Opcode(Opcode::Mnemonic(m)) => write!(f, "opbreak {}", m),
+            Kind::Nmi => write!(f, "catch nmi"),
+            Kind::Irq => write!(f, "catch irq"),
+            Kind::Brk => write!(f, "catch brk"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub kind: Kind,
+    pub condition: Option<Condition>,
+
+    // Times the breakpoint has been hit, and the number of hits left that
+    // won't stop emulation.
+    pub hits: u64,
+    pub ignore: u64,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<3} {}", self.id, self.kind)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        write!(f, " (hits: {}", self.hits)?;
+        if self.ignore > 0 {
+            write!(f, ", ignoring {}", self.ignore)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// A breakpoint that stopped emulation, and the access that triggered it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hit {
+    pub id: usize,
+    pub kind: Kind,
+    pub access: Option<BusAccess>,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "breakpoint {}: {}", self.id, self.kind)?;
+        if let Some(access) = &self.access {
+            let access_str = match access.access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            write!(
+                f,
+                " ({} ${:04X} = ${:02X})",
+                access_str, access.addr, access.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    last_id: usize,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    // Returns the new breakpoint's ID.
+    pub fn add(&mut self, kind: Kind, condition: Option<Condition>) -> usize {
+        self.last_id += 1;
+        self.list.push(Breakpoint {
+            id: self.last_id,
+            kind,
+            condition,
+            hits: 0,
+            ignore: 0,
+        });
+        self.last_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.list.len();
+        self.list.retain(|b| b.id != id);
+        self.list.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.list.iter_mut().find(|b| b.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // Steps the CPU, unless a breakpoint stops it first. Breakpoints on the
+    // instruction at pc are skipped if skip_exec is set, so that emulation
+    // can resume from them.
+    pub fn step(&mut self, cpu: &mut Cpu, skip_exec: bool) -> Result<Option<Hit>, CpuError> {
+        if self.list.is_empty() {
+            return cpu.step().map(|_| None);
+        }
+
+        let pc = cpu.regs.pc;
+        let encoding = cpu.peek(pc);
+        if !skip_exec {
+            if let Some(hit) = self.check(cpu, &[Event::Exec { pc, encoding }]) {
+                return Ok(Some(hit));
+            }
+        }
+
+        // Accesses are only recorded while something is watching them.
+        if self.list.iter().any(|b| b.kind.needs_accesses()) {
+            cpu.accesses = Some(Vec::new());
+        }
+        if self.list.iter().any(|b| b.kind.needs_vram_accesses()) {
+            cpu.bus.ppu.accesses = Some(Vec::new());
+        }
+        let res = cpu.step();
+        let accesses = cpu.accesses.take().unwrap_or_default();
+        let vram_accesses = cpu.bus.ppu.accesses.take().unwrap_or_default();
+        res?;
+
+        let mut events: Vec<Event> = accesses.into_iter().map(Event::Access).collect();
+        events.extend(vram_accesses.into_iter().map(Event::VramAccess));
+        match cpu.last_interrupt {
+            Some(Interrupt::Nmi) => events.push(Event::Interrupt(Kind::Nmi)),
+            Some(Interrupt::Irq) => events.push(Event::Interrupt(Kind::Irq)),
+            None if encoding == Some(BRK) => events.push(Event::Interrupt(Kind::Brk)),
+            None => {}
+        }
+        Ok(self.check(cpu, &events))
+    }
+
+    // Counts a hit for each breakpoint that matches one of the events, and
+    // returns the first one that isn't ignored.
+    fn check(&mut self, cpu: &Cpu, events: &[Event]) -> Option<Hit> {
+        let mut stop = None;
+        for b in self.list.iter_mut() {
+            let event = events.iter().find(|event| {
+                b.kind.matches(event, cpu.variant)
+                    && b.condition
+                        .as_ref()
+                        .is_none_or(|c| c.eval(cpu, event.access()))
+            });
+            let event = match event {
+                Some(event) => event,
+                None => continue,
+            };
+
+            b.hits += 1;
+            if b.ignore > 0 {
+                b.ignore -= 1;
+            } else if stop.is_none() {
+                stop = Some(Hit {
+                    id: b.id,
+                    kind: b.kind.clone(),
+                    access: event.access().copied(),
+                });
+            }
+        }
+        stop
+    }
+}
+
+// Conditions compare registers, memory and numbers:
+//
+// A == $20 && X > 3
+// [$0300] != 0 || (SP < $F0 && PC >= $C000)
+//
+// The registers are A, X, Y, SP, P and PC. VALUE and ADDR are the value and
+// address of the access that triggered a watchpoint, and [ADDR] is memory,
+// read without side effects. Numbers are decimal, or hex with $ or 0x. A
+// comparison with something that isn't available, like VALUE for an
+// execution breakpoint, is false.
+#[derive(Debug)]
+pub struct Condition {
+    src: String,
+    expr: Expr,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Compare(Operand, Op, Operand),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Operand {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+    Value,
+    Addr,
+    Number(u32),
+    Memory(u16),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u32),
+    Op(Op),
+    And,
+    Or,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "{}", ident),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Op(op) => write!(
+                f,
+                "{}",
+                match op {
+                    Op::Eq => "==",
+                    Op::Ne => "!=",
+                    Op::Lt => "<",
+                    Op::Le => "<=",
+                    Op::Gt => ">",
+                    Op::Ge => ">=",
+                }
+            ),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+        }
+    }
+}
+
+impl Condition {
+    pub fn parse(src: &str) -> Result<Condition, String> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.next() {
+            return Err(format!("unexpected {} in condition", token));
+        }
+        Ok(Condition {
+            src: src.to_string(),
+            expr,
+        })
+    }
+
+    pub fn eval(&self, cpu: &Cpu, access: Option<&BusAccess>) -> bool {
+        eval(&self.expr, cpu, access)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+fn eval(expr: &Expr, cpu: &Cpu, access: Option<&BusAccess>) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, cpu, access) && eval(b, cpu, access),
+        Expr::Or(a, b) => eval(a, cpu, access) || eval(b, cpu, access),
+        Expr::Compare(a, op, b) => {
+            let (a, b) = match (value(*a, cpu, access), value(*b, cpu, access)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return false,
+            };
+            match op {
+                Op::Eq => a == b,
+                Op::Ne => a != b,
+                Op::Lt => a < b,
+                Op::Le => a <= b,
+                Op::Gt => a > b,
+                Op::Ge => a >= b,
+            }
+        }
+    }
+}
+
+fn value(operand: Operand, cpu: &Cpu, access: Option<&BusAccess>) -> Option<u32> {
+    let regs = &cpu.regs;
+    match operand {
+        Operand::A => Some(regs.a as u32),
+        Operand::X => Some(regs.x as u32),
+        Operand::Y => Some(regs.y as u32),
+        Operand::Sp => Some(regs.s as u32),
+        Operand::P => Some(regs.p as u32),
+        Operand::Pc => Some(regs.pc as u32),
+        Operand::Value => access.map(|a| a.value as u32),
+        Operand::Addr => access.map(|a| a.addr as u32),
+        Operand::Number(n) => Some(n),
+        Operand::Memory(addr) => cpu.peek(addr).map(|v| v as u32),
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if c.is_ascii_alphanumeric() || c == '$' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '$') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let token = if word.starts_with(|c: char| c.is_ascii_digit() || c == '$') {
+                Token::Number(parse_number(&word)?)
+            } else {
+                Token::Ident(word.to_ascii_uppercase())
+            };
+            tokens.push(token);
+            continue;
+        }
+
+        chars.next();
+        let next = chars.peek().copied();
+        let (token, len) = match (c, next) {
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('[', _) => (Token::LBracket, 1),
+            (']', _) => (Token::RBracket, 1),
+            _ => return Err(format!("unexpected {} in condition", c)),
+        };
+        if len == 2 {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_number(s: &str) -> Result<u32, String> {
+    let res = match s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        Some(digits) => u32::from_str_radix(digits, 16),
+        None => s.parse(),
+    };
+    res.map_err(|_| format!("invalid number {}", s))
+}
+
+// A recursive descent parser, where && binds more tightly than ||.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {}, found {}", expected, token)),
+            None => Err(format!("expected {}", expected)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.primary()?));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::LParen) {
+            self.next();
+            let expr = self.or()?;
+            self.expect(Token::RParen)?;
+            return Ok(expr);
+        }
+
+        let a = self.operand()?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(token) => return Err(format!("expected a comparison, found {}", token)),
+            None => return Err("expected a comparison".to_string()),
+        };
+        let b = self.operand()?;
+        Ok(Expr::Compare(a, op, b))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Operand::Number(n)),
+            Some(Token::LBracket) => {
+                let addr = match self.next() {
+                    Some(Token::Number(n)) if n <= 0xFFFF => n as u16,
+                    _ => return Err("expected an address in [ ]".to_string()),
+                };
+                self.expect(Token::RBracket)?;
+                Ok(Operand::Memory(addr))
+            }
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "A" => Ok(Operand::A),
+                "X" => Ok(Operand::X),
+                "Y" => Ok(Operand::Y),
+                "SP" => Ok(Operand::Sp),
+                "P" => Ok(Operand::P),
+                "PC" => Ok(Operand::Pc),
+                "VALUE" => Ok(Operand::Value),
+                "ADDR" => Ok(Operand::Addr),
+                _ => Err(format!("unknown register {}", ident)),
+            },
+            Some(token) => Err(format!("expected a value, found {}", token)),
+            None => Err("expected a value".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::cpu::assemble;
+
+#[cfg(test)]
+fn test_cpu(src: &str) -> Cpu {
+    let mut cpu = Cpu::new_test();
+    cpu.mem_write_buf(0x0600, assemble::assemble(src, 0x0600).unwrap());
+    cpu.regs.pc = 0x0600;
+    cpu.regs.s = 0xFD;
+    cpu
+}
+
+#[test]
+fn test_condition() {
+    let mut cpu = test_cpu("nop");
+    cpu.regs.a = 0x20;
+    cpu.regs.x = 4;
+    cpu.mem_write(0x0300, 0x7F);
+    let eval = |src: &str, cpu: &Cpu, access| Condition::parse(src).unwrap().eval(cpu, access);
+
+    assert!(eval("A == $20 && X > 3", &cpu, None));
+    assert!(!eval("A == $20 && X > 4", &cpu, None));
+    assert!(eval("a==0x20&&x>=4", &cpu, None));
+    assert!(eval("X < 3 || A != 0 && PC == $0600", &cpu, None));
+    assert!(!eval("(X < 3 || A != 0) && SP == 0", &cpu, None));
+    assert!(eval("[$0300] == 127", &cpu, None));
+    assert!(eval("[$2002] == 0", &cpu, None));
+
+    // values that aren't available
+    assert!(!eval("VALUE == 0", &cpu, None));
+    let access = BusAccess {
+        addr: 0x0300,
+        access: Access::Write,
+        value: 5,
+    };
+    assert!(eval("VALUE == 5 && ADDR == $0300", &cpu, Some(&access)));
+
+    for src in &[
+        "",
+        "A ==",
+        "A == 1 &&",
+        "Q == 1",
+        "(A == 1",
+        "A = 1",
+        "[A] == 1",
+    ] {
+        assert!(Condition::parse(src).is_err(), "{}", src);
+    }
+}
+
+#[test]
+fn test_exec_breakpoints() {
+    let mut cpu = test_cpu("a: inx\njmp a");
+    let mut breakpoints = Breakpoints::new();
+    let id = breakpoints.add(Kind::Exec(0x0600..=0x0600), None);
+
+    // stops before the instruction, and resumes past it
+    let hit = breakpoints.step(&mut cpu, false).unwrap().unwrap();
+    assert_eq!((hit.id, cpu.regs.x), (id, 0));
+    assert_eq!(breakpoints.step(&mut cpu, true).unwrap(), None);
+    assert_eq!(breakpoints.step(&mut cpu, false).unwrap(), None);
+    assert!(breakpoints.step(&mut cpu, false).unwrap().is_some());
+
+    // conditions and ignore counts
+    breakpoints.clear();
+    let cond = Condition::parse("X >= 5").unwrap();
+    let id = breakpoints.add(
+        Kind::Opcode(Opcode::Mnemonic("INX".to_string())),
+        Some(cond),
+    );
+    breakpoints.get_mut(id).unwrap().ignore = 1;
+    let mut steps = 0;
+    let hit = loop {
+        steps += 1;
+        if let Some(hit) = breakpoints.step(&mut cpu, false).unwrap() {
+            break hit;
+        }
+        assert!(steps < 100);
+    };
+    assert_eq!((hit.id, cpu.regs.x), (id, 6));
+    let b = breakpoints.iter().next().unwrap();
+    assert_eq!((b.hits, b.ignore), (2, 0));
+    assert_eq!(b.to_string(), "2   opbreak INX if X >= 5 (hits: 2)");
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut cpu = test_cpu(
+        "
+        lda #$05
+        sta $0300
+        lda $0300
+        lda #$3F
+        sta $2006
+        lda #$00
+        sta $2006
+        lda #$0F
+        sta $2007
+        brk
+        ",
+    );
+    let mut breakpoints = Breakpoints::new();
+    breakpoints.add(Kind::Write(0x0300..=0x03FF), None);
+    breakpoints.add(Kind::Read(0x0300..=0x0300), None);
+    breakpoints.add(Kind::VramWrite(0x3F00..=0x3F1F), None);
+    breakpoints.add(Kind::Brk, None);
+
+    let mut hits = Vec::new();
+    for _ in 0..10 {
+        if let Some(hit) = breakpoints.step(&mut cpu, false).unwrap() {
+            hits.push((hit.to_string(), cpu.regs.pc));
+        }
+    }
+    assert_eq!(
+        hits,
+        vec![
+            (
+                "breakpoint 1: watch $0300-$03FF (write $0300 = $05)".to_string(),
+                0x0605
+            ),
+            (
+                "breakpoint 2: rwatch $0300 (read $0300 = $05)".to_string(),
+                0x0608
+            ),
+            (
+                "breakpoint 3: vwatch $3F00-$3F1F (write $3F00 = $0F)".to_string(),
+                0x0617
+            ),
+            (
+                "breakpoint 4: catch brk".to_string(),
+                cpu.mem_read16(0xFFFE)
+            ),
+        ]
+    );
+
+    // accesses are only recorded while a watchpoint needs them
+    assert!(cpu.accesses.is_none());
+    assert!(cpu.bus.ppu.accesses.is_none());
+}
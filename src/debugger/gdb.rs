@@ -0,0 +1,705 @@
+// A stub for GDB's remote serial protocol, so that GDB and other tools that
+// speak it can debug programs over TCP. The stub is the target: it answers
+// packets like $m0300,10#xx until the client detaches or kills it.
+//
+// The registers, in the order of the g packet, are A, X, Y, SP and P (one
+// byte each), then PC (two bytes, little-endian). They're also described to
+// the client as target.xml. Memory reads are peeks, so they have no side
+// effects. Software and hardware breakpoints are both execution breakpoints,
+// since ROM can't be patched with BRK.
+// Reference: https://sourceware.org/gdb/current/onlinedocs/gdb/Remote-Protocol.html
+
+use super::breakpoint::{Breakpoints, Hit, Kind};
+use crate::cpu::{Cpu, CpuError};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+// The largest packet the client may send, in bytes. The size is in hex in
+// qSupported.
+const PACKET_SIZE: usize = 0x4000;
+
+// Memory reads are limited so that the hex reply fits in a packet.
+const MAX_READ_LEN: usize = PACKET_SIZE / 2 - 16;
+
+// Continuing checks for an interrupt from the client this often.
+const INTERRUPT_CHECK_STEPS: u64 = 10_000;
+
+// Signals in stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// The client sends this byte, outside of a packet, to stop a continue.
+const INTERRUPT: u8 = 0x03;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="nes.6502.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// Z packet types.
+const SOFTWARE_BREAKPOINT: u8 = 0;
+const HARDWARE_BREAKPOINT: u8 = 1;
+const WRITE_WATCHPOINT: u8 = 2;
+const READ_WATCHPOINT: u8 = 3;
+const ACCESS_WATCHPOINT: u8 = 4;
+
+// What a packet asks the stub to do, besides replying.
+#[derive(Debug, Eq, PartialEq)]
+enum Action {
+    Reply(String),
+    Resume { step: bool },
+    Detach,
+    Kill,
+}
+
+// A breakpoint inserted with a Z packet, and the IDs of the breakpoints that
+// implement it. An access watchpoint needs two.
+struct Inserted {
+    z_type: u8,
+    addr: u16,
+    len: u16,
+    ids: Vec<usize>,
+}
+
+pub struct GdbStub {
+    breakpoints: Breakpoints,
+    inserted: Vec<Inserted>,
+    last_stop: String,
+}
+
+impl Default for GdbStub {
+    fn default() -> GdbStub {
+        GdbStub {
+            breakpoints: Breakpoints::new(),
+            inserted: Vec::new(),
+            last_stop: format!("S{:02x}", SIGTRAP),
+        }
+    }
+}
+
+impl GdbStub {
+    pub fn new() -> GdbStub {
+        GdbStub::default()
+    }
+
+    // Debugs the CPU for a connected client, until it detaches, kills the
+    // target or disconnects.
+    pub fn serve(&mut self, cpu: &mut Cpu, stream: TcpStream) -> io::Result<()> {
+        let mut conn = Connection::new(stream);
+        while let Some(packet) = conn.recv()? {
+            match self.command(cpu, &packet) {
+                Action::Reply(reply) => conn.send(&reply)?,
+                Action::Resume { step } => {
+                    let stop = self.resume(cpu, &mut conn, step)?;
+                    self.last_stop = stop.clone();
+                    conn.send(&stop)?;
+                }
+                Action::Detach => return conn.send("OK"),
+                Action::Kill => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, cpu: &mut Cpu, packet: &str) -> Action {
+        let reply = |s: &str| Action::Reply(s.to_string());
+        let error = || reply("E01");
+
+        let name = packet.get(..1).unwrap_or("");
+        let args = packet.get(1..).unwrap_or("");
+        match name {
+            "?" => Action::Reply(self.last_stop.clone()),
+            "g" => Action::Reply(hex(&registers(cpu))),
+            "G" => match unhex(args) {
+                Some(bytes) if bytes.len() == 7 => {
+                    set_registers(cpu, &bytes);
+                    reply("OK")
+                }
+                _ => error(),
+            },
+            "p" => match parse_num(args).and_then(|n| register(cpu, n)) {
+                Some(bytes) => Action::Reply(hex(&bytes)),
+                None => error(),
+            },
+            "P" => match self.set_register(cpu, args) {
+                Some(()) => reply("OK"),
+                None => error(),
+            },
+            "m" => match read_memory(cpu, args) {
+                Some(bytes) => Action::Reply(hex(&bytes)),
+                None => error(),
+            },
+            "M" => match write_memory(cpu, args) {
+                Some(()) => reply("OK"),
+                None => error(),
+            },
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match parse_addr(args) {
+                        Some(addr) => cpu.regs.pc = addr,
+                        None => return error(),
+                    }
+                }
+                Action::Resume { step: name == "s" }
+            }
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some((z_type, addr, len)) if name == "Z" => self.insert(z_type, addr, len),
+                Some((z_type, addr, len)) => self.remove(z_type, addr, len),
+                None => error(),
+            },
+            "H" | "T" => reply("OK"),
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            _ => query(packet),
+        }
+    }
+
+    fn set_register(&mut self, cpu: &mut Cpu, args: &str) -> Option<()> {
+        let (n, value) = split2(args, '=')?;
+        let n = parse_num(n)?;
+        let bytes = unhex(value)?;
+        let mut regs = registers(cpu);
+        let offset = register_offset(n)?;
+        if bytes.len() != register(cpu, n)?.len() {
+            return None;
+        }
+        regs[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        set_registers(cpu, &regs);
+        Some(())
+    }
+
+    fn insert(&mut self, z_type: u8, addr: u16, len: u16) -> Action {
+        let end = addr.saturating_add(len.max(1) - 1);
+        let kinds = match z_type {
+            SOFTWARE_BREAKPOINT | HARDWARE_BREAKPOINT => vec![Kind::Exec(addr..=addr)],
+            WRITE_WATCHPOINT => vec![Kind::Write(addr..=end)],
+            READ_WATCHPOINT => vec![Kind::Read(addr..=end)],
+            ACCESS_WATCHPOINT => vec![Kind::Read(addr..=end), Kind::Write(addr..=end)],
+            // unsupported
+            _ => return Action::Reply(String::new()),
+        };
+        let ids = kinds
+            .into_iter()
+            .map(|kind| self.breakpoints.add(kind, None))
+            .collect();
+        self.inserted.push(Inserted {
+            z_type,
+            addr,
+            len,
+            ids,
+        });
+        Action::Reply("OK".to_string())
+    }
+
+    fn remove(&mut self, z_type: u8, addr: u16, len: u16) -> Action {
+        if z_type > ACCESS_WATCHPOINT {
+            return Action::Reply(String::new());
+        }
+        let index = self
+            .inserted
+            .iter()
+            .position(|b| b.z_type == z_type && b.addr == addr && b.len == len);
+        match index {
+            Some(index) => {
+                for id in self.inserted.remove(index).ids {
+                    self.breakpoints.remove(id);
+                }
+                Action::Reply("OK".to_string())
+            }
+            None => Action::Reply("E01".to_string()),
+        }
+    }
+
+    // Steps or continues the CPU, returning the stop reply. Like gdb's own
+    // targets, resuming steps over a breakpoint at pc.
+    fn resume(&mut self, cpu: &mut Cpu, conn: &mut Connection, step: bool) -> io::Result<String> {
+        let mut steps = 0;
+        loop {
+            match self.breakpoints.step(cpu, steps == 0) {
+                Ok(Some(hit)) => return Ok(self.stop_reply(&hit)),
+                Ok(None) => {}
+                Err(e) => {
+                    // Console output, which the client shows to the user.
+                    conn.send(&format!("O{}", hex(format!("{}\n", e).as_bytes())))?;
+                    return Ok(format!("S{:02x}", error_signal(&e)));
+                }
+            }
+            steps += 1;
+            if step {
+                return Ok(format!("S{:02x}", SIGTRAP));
+            }
+            if steps % INTERRUPT_CHECK_STEPS == 0 && conn.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    fn stop_reply(&self, hit: &Hit) -> String {
+        let z_type = self
+            .inserted
+            .iter()
+            .find(|b| b.ids.contains(&hit.id))
+            .map(|b| b.z_type);
+        let watch = match z_type {
+            Some(WRITE_WATCHPOINT) => "watch",
+            Some(READ_WATCHPOINT) => "rwatch",
+            Some(ACCESS_WATCHPOINT) => "awatch",
+            _ => return format!("S{:02x}", SIGTRAP),
+        };
+        match hit.access {
+            Some(access) => format!("T{:02x}{}:{:04x};", SIGTRAP, watch, access.addr),
+            None => format!("S{:02x}", SIGTRAP),
+        }
+    }
+}
+
+fn error_signal(e: &CpuError) -> u8 {
+    match e {
+        CpuError::Unmapped { .. } => SIGSEGV,
+        _ => SIGILL,
+    }
+}
+
+// General queries, and other packets that don't touch the CPU. Packets the
+// stub doesn't support get an empty reply.
+fn query(packet: &str) -> Action {
+    let reply = |s: &str| Action::Reply(s.to_string());
+    if packet.starts_with("qSupported") {
+        return Action::Reply(format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE));
+    }
+    if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        return match split2(args, ',') {
+            Some((offset, len)) => match (parse_num(offset), parse_num(len)) {
+                (Some(offset), Some(len)) => Action::Reply(xfer(TARGET_XML, offset, len)),
+                _ => reply("E01"),
+            },
+            None => reply("E01"),
+        };
+    }
+    match packet {
+        "qAttached" => reply("1"),
+        "qC" => reply("QC1"),
+        "qfThreadInfo" => reply("m1"),
+        "qsThreadInfo" => reply("l"),
+        "qSymbol::" => reply("OK"),
+        _ => reply(""),
+    }
+}
+
+// A chunk of an object for a qXfer read: m if there's more, l if it's the
+// last.
+fn xfer(object: &str, offset: usize, len: usize) -> String {
+    let start = offset.min(object.len());
+    let end = offset.saturating_add(len).min(object.len());
+    let more = if end < object.len() { 'm' } else { 'l' };
+    format!("{}{}", more, &object[start..end])
+}
+
+fn registers(cpu: &Cpu) -> Vec<u8> {
+    let regs = &cpu.regs;
+    let pc = regs.pc.to_le_bytes();
+    vec![regs.a, regs.x, regs.y, regs.s, regs.p, pc[0], pc[1]]
+}
+
+fn set_registers(cpu: &mut Cpu, bytes: &[u8]) {
+    let regs = &mut cpu.regs;
+    regs.a = bytes[0];
+    regs.x = bytes[1];
+    regs.y = bytes[2];
+    regs.s = bytes[3];
+    regs.p = bytes[4];
+    regs.pc = u16::from_le_bytes([bytes[5], bytes[6]]);
+}
+
+// The offset of register n in the g packet.
+fn register_offset(n: usize) -> Option<usize> {
+    match n {
+        0..=5 => Some(n),
+        _ => None,
+    }
+}
+
+fn register(cpu: &Cpu, n: usize) -> Option<Vec<u8>> {
+    let regs = registers(cpu);
+    match n {
+        0..=4 => Some(vec![regs[n]]),
+        5 => Some(regs[5..].to_vec()),
+        _ => None,
+    }
+}
+
+// m ADDR,LEN
+fn read_memory(cpu: &Cpu, args: &str) -> Option<Vec<u8>> {
+    let (addr, len) = split2(args, ',')?;
+    let addr = parse_addr(addr)?;
+    let len = parse_num(len)?
+        .min(MAX_READ_LEN)
+        .min(0x10000 - addr as usize);
+    (0..len as u16).map(|i| cpu.peek(addr + i)).collect()
+}
+
+// M ADDR,LEN:BYTES
+fn write_memory(cpu: &mut Cpu, args: &str) -> Option<()> {
+    let (target, bytes) = split2(args, ':')?;
+    let (addr, len) = split2(target, ',')?;
+    let addr = parse_addr(addr)?;
+    let bytes = unhex(bytes)?;
+    if parse_num(len)? != bytes.len() || addr as usize + bytes.len() > 0x10000 {
+        return None;
+    }
+    for (i, &v) in bytes.iter().enumerate() {
+        cpu.try_mem_write(addr + i as u16, v).ok()?;
+    }
+    Some(())
+}
+
+// Z TYPE,ADDR,KIND, with any conditions after the kind ignored. The kind is
+// a length for watchpoints.
+fn parse_breakpoint(args: &str) -> Option<(u8, u16, u16)> {
+    let args = args.split(';').next()?;
+    let mut fields = args.split(',');
+    let z_type = parse_num(fields.next()?)?;
+    let addr = parse_addr(fields.next()?)?;
+    let len = parse_num(fields.next()?)?;
+    if z_type > u8::MAX as usize || len > u16::MAX as usize {
+        return None;
+    }
+    Some((z_type as u8, addr, len as u16))
+}
+
+fn split2(s: &str, sep: char) -> Option<(&str, &str)> {
+    let i = s.find(sep)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+fn parse_num(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    u16::from_str_radix(s, 16).ok()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+// Packets are framed as $DATA#CS, where CS is the sum of DATA's bytes, and
+// each one is acknowledged with + or -, until the client turns
+// acknowledgements off.
+struct Connection {
+    stream: TcpStream,
+
+    // Bytes received but not yet handled.
+    pending: VecDeque<u8>,
+
+    // The last packet sent, in case the client asks for it again.
+    last_sent: Vec<u8>,
+    no_ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Connection {
+        Connection {
+            stream,
+            pending: VecDeque::new(),
+            last_sent: Vec::new(),
+            no_ack: false,
+        }
+    }
+
+    // Returns the next byte, or None once the client has disconnected.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            let mut buf = [0; 4096];
+            let n = self.stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.pending.extend(&buf[..n]);
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    // Returns the next packet's data, or None once the client has
+    // disconnected. Acknowledgement mode is handled here, rather than being
+    // passed on.
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => {
+                    let last_sent = self.last_sent.clone();
+                    self.stream.write_all(&last_sent)?;
+                    continue;
+                }
+                // Acknowledgements, and interrupts that arrive after the CPU
+                // has already stopped.
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut sum = [0; 2];
+            for b in sum.iter_mut() {
+                *b = match self.read_byte()? {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+            }
+
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                == Some(checksum(&data));
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if !valid {
+                continue;
+            }
+
+            let data = String::from_utf8_lossy(&data).into_owned();
+            if data == "QStartNoAckMode" {
+                self.send("OK")?;
+                self.no_ack = true;
+                continue;
+            }
+            return Ok(Some(data));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.last_sent = packet.clone().into_bytes();
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    // Returns whether the client has asked to stop, or disconnected,
+    // without waiting.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; 4096];
+        let res = self.stream.read(&mut buf);
+        self.stream.set_nonblocking(false)?;
+        match res {
+            Ok(0) => return Ok(true),
+            Ok(n) => self.pending.extend(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        let len = self.pending.len();
+        self.pending.retain(|&b| b != INTERRUPT);
+        Ok(self.pending.len() != len)
+    }
+}
+
+#[cfg(test)]
+use crate::cpu::assemble;
+
+#[cfg(test)]
+use std::net::TcpListener;
+
+#[cfg(test)]
+fn test_cpu() -> Cpu {
+    let mut cpu = Cpu::new_test();
+    let code = assemble::assemble(
+        "
+        ldx #$00
+        loop: inx
+        stx $0300
+        jmp loop
+        ",
+        0x0600,
+    )
+    .unwrap();
+    cpu.mem_write_buf(0x0600, code);
+    cpu.regs.pc = 0x0600;
+    cpu.regs.s = 0xFD;
+    // with interrupts disabled, so that the APU's frame IRQ isn't taken
+    cpu.regs.p = 0x24;
+    cpu
+}
+
+#[cfg(test)]
+fn reply(stub: &mut GdbStub, cpu: &mut Cpu, packet: &str) -> String {
+    match stub.command(cpu, packet) {
+        Action::Reply(reply) => reply,
+        action => panic!("{}: {:?}", packet, action),
+    }
+}
+
+#[test]
+fn test_commands() {
+    let mut stub = GdbStub::new();
+    let mut cpu = test_cpu();
+    cpu.regs.a = 1;
+    cpu.regs.x = 2;
+    cpu.regs.y = 3;
+
+    // registers
+    assert_eq!(reply(&mut stub, &mut cpu, "g"), "010203fd240006");
+    assert_eq!(reply(&mut stub, &mut cpu, "P5=0206"), "OK");
+    assert_eq!(reply(&mut stub, &mut cpu, "p5"), "0206");
+    assert_eq!(reply(&mut stub, &mut cpu, "p1"), "02");
+    assert_eq!(reply(&mut stub, &mut cpu, "G0a0b0cfe250006"), "OK");
+    assert_eq!((cpu.regs.a, cpu.regs.s, cpu.regs.pc), (0x0A, 0xFE, 0x0600));
+    assert_eq!(reply(&mut stub, &mut cpu, "p6"), "E01");
+    assert_eq!(reply(&mut stub, &mut cpu, "G0a0b"), "E01");
+
+    // memory
+    assert_eq!(reply(&mut stub, &mut cpu, "m0600,3"), "a200e8");
+    assert_eq!(reply(&mut stub, &mut cpu, "M0300,2:abcd"), "OK");
+    assert_eq!(reply(&mut stub, &mut cpu, "m0300,2"), "abcd");
+    assert_eq!(reply(&mut stub, &mut cpu, "mfffe,4").len(), 4);
+    assert_eq!(reply(&mut stub, &mut cpu, "M0300,2:ab"), "E01");
+    assert_eq!(reply(&mut stub, &mut cpu, "M4018,1:ab"), "OK");
+
+    // breakpoints
+    assert_eq!(reply(&mut stub, &mut cpu, "Z0,0606,1"), "OK");
+    assert_eq!(reply(&mut stub, &mut cpu, "Z4,0300,2"), "OK");
+    assert_eq!(stub.breakpoints.iter().count(), 3);
+    assert_eq!(reply(&mut stub, &mut cpu, "z4,0300,2"), "OK");
+    assert_eq!(reply(&mut stub, &mut cpu, "z4,0300,2"), "E01");
+    assert_eq!(stub.breakpoints.iter().count(), 1);
+    assert_eq!(reply(&mut stub, &mut cpu, "Z5,0300,1"), "");
+
+    // resuming
+    assert_eq!(
+        stub.command(&mut cpu, "c0602"),
+        Action::Resume { step: false }
+    );
+    assert_eq!(cpu.regs.pc, 0x0602);
+    assert_eq!(stub.command(&mut cpu, "s"), Action::Resume { step: true });
+
+    // queries
+    assert_eq!(reply(&mut stub, &mut cpu, "?"), "S05");
+    assert_eq!(reply(&mut stub, &mut cpu, "qAttached"), "1");
+    assert_eq!(reply(&mut stub, &mut cpu, "vMustReplyEmpty"), "");
+    let xml = reply(&mut stub, &mut cpu, "qXfer:features:read:target.xml:0,5");
+    assert_eq!(xml, "m<?xml");
+    let xml = reply(&mut stub, &mut cpu, "qXfer:features:read:target.xml:5,1000");
+    assert!(xml.starts_with("l") && xml.ends_with("</target>\n"));
+}
+
+// A client on another thread drives the stub through a socket.
+#[test]
+fn test_loopback() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        let send = |stream: &mut TcpStream, data: &str| {
+            let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+            stream.write_all(packet.as_bytes()).unwrap();
+        };
+        // Skips acknowledgements, and acknowledges the reply if ack is set.
+        let recv = |stream: &mut TcpStream, ack: bool| {
+            let mut b = [0];
+            while b[0] != b'$' {
+                stream.read_exact(&mut b).unwrap();
+            }
+            let mut reply = Vec::new();
+            loop {
+                stream.read_exact(&mut b).unwrap();
+                if b[0] == b'#' {
+                    break;
+                }
+                reply.push(b[0]);
+            }
+            let mut sum = [0; 2];
+            stream.read_exact(&mut sum).unwrap();
+            if ack {
+                stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(reply).unwrap()
+        };
+
+        let mut replies = Vec::new();
+        for packet in &[
+            "qSupported:swbreak+",
+            "Z2,0300,1",
+            "c",
+            "z2,0300,1",
+            "Z1,0606,1",
+            "c",
+            "g",
+            "z1,0606,1",
+        ] {
+            send(&mut stream, packet);
+            replies.push(recv(&mut stream, true));
+        }
+
+        // interrupting a continue that would never stop
+        send(&mut stream, "c");
+        stream.write_all(&[INTERRUPT]).unwrap();
+        replies.push(recv(&mut stream, true));
+
+        send(&mut stream, "QStartNoAckMode");
+        replies.push(recv(&mut stream, true));
+        send(&mut stream, "m0600,1");
+        replies.push(recv(&mut stream, false));
+        send(&mut stream, "k");
+        replies
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    let mut cpu = test_cpu();
+    GdbStub::new().serve(&mut cpu, stream).unwrap();
+
+    let replies = client.join().unwrap();
+    assert_eq!(
+        replies,
+        vec![
+            "PacketSize=4000;qXfer:features:read+",
+            "OK",
+            "T05watch:0300;",
+            "OK",
+            "OK",
+            "S05",
+            "000200fd240606",
+            "OK",
+            "S02",
+            "OK",
+            "a2",
+        ]
+    );
+}
@@ -0,0 +1,731 @@
+// A command-line debugger in the style of gdb. Commands are read a line at a
+// time; an empty line repeats the last command. Addresses and bytes are in
+// hex, with or without a leading $, and counts are in decimal.
+
+use crate::cpu::{self, disassemble, Bus, Cpu, CpuError, Registers, Status};
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+
+mod breakpoint;
+mod gdb;
+
+pub use breakpoint::{Breakpoint, Breakpoints, Condition, Hit, Kind, Opcode};
+pub use gdb::GdbStub;
+
+const HELP: &str = "commands:
+  s, step [N]                 step N instructions
+  n, next                     step, running through subroutine calls
+  f, finish                   run until the current subroutine returns
+  u, until ADDR               run until pc reaches ADDR
+  c, continue                 leave the debugger and resume
+  r, regs                     show registers and flags
+  m, mem ADDR [LEN]           dump CPU memory
+  pm, ppumem ADDR [LEN]       dump PPU memory
+  w, write ADDR BYTE...       write CPU memory
+  pw, ppuwrite ADDR BYTE...   write PPU memory
+  d, dis [ADDR] [N]           disassemble around pc, or from ADDR
+  bt, stack [N]               show the top N bytes of the stack
+  b, break RANGE [if COND]    break when pc reaches RANGE
+  rwatch RANGE [if COND]      break on CPU reads of RANGE
+  watch RANGE [if COND]       break on CPU writes to RANGE
+  vrwatch RANGE [if COND]     break on VRAM reads of RANGE, through PPUDATA
+  vwatch RANGE [if COND]      break on VRAM writes to RANGE, through PPUDATA
+  opbreak OPCODE [if COND]    break on an opcode, by mnemonic or encoding
+  catch nmi|irq|brk [if COND] break when an interrupt is taken
+  bl, breakpoints             list breakpoints and their hit counts
+  ignore ID N                 let the next N hits of a breakpoint pass
+  delete [ID]                 delete a breakpoint, or all of them
+  h, help                     show this message
+  q, quit                     quit the emulator
+
+A RANGE is an address, or two separated by -. A COND compares registers
+(A, X, Y, SP, P, PC), memory ([ADDR]) and numbers with == != < <= > >=,
+combined with && and ||. VALUE and ADDR are the value and address of the
+access that triggered a watchpoint. Numbers in conditions are decimal, or
+hex with $ or 0x.";
+
+const PROMPT: &str = "(nes) ";
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+
+// PPU addresses are 14 bits.
+const PPU_ADDR_MASK: u16 = 0x3FFF;
+
+const DEFAULT_DUMP_LEN: usize = 64;
+const DEFAULT_DIS_COUNT: usize = 8;
+const DEFAULT_STACK_LEN: usize = 8;
+const DUMP_ROW_LEN: usize = 16;
+
+// Disassembly shows up to this many instructions before pc.
+const DIS_CONTEXT: usize = 3;
+
+// What the emulator should do once the debugger returns.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Resume {
+    Continue,
+    Quit,
+}
+
+// Commands fail either with a message for the user, or because the output
+// can't be written.
+enum Error {
+    Command(String),
+    Io(io::Error),
+}
+
+impl From<String> for Error {
+    fn from(e: String) -> Error {
+        Error::Command(e)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(e: &str) -> Error {
+        Error::Command(e.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Breakpoints,
+    last_command: String,
+
+    // Set when emulation stops. Like gdb, resuming steps over breakpoints
+    // at pc, so that emulation can continue from one.
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    // Reads and runs commands until one of them resumes the emulator. The
+    // end of the input quits.
+    pub fn repl(
+        &mut self,
+        cpu: &mut Cpu,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<Resume> {
+        writeln!(output, "{}", cpu::trace_line(cpu))?;
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(Resume::Quit);
+            }
+            if let Some(resume) = self.command(cpu, &line, output)? {
+                self.resuming = true;
+                return Ok(resume);
+            }
+        }
+    }
+
+    // Runs a single command, returning whether it resumes the emulator.
+    pub fn command(
+        &mut self,
+        cpu: &mut Cpu,
+        line: &str,
+        output: &mut dyn Write,
+    ) -> io::Result<Option<Resume>> {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Ok(None),
+        };
+
+        let res = match name {
+            "c" | "continue" => return Ok(Some(Resume::Continue)),
+            "q" | "quit" => return Ok(Some(Resume::Quit)),
+            _ => self.run_command(cpu, name, args, output),
+        };
+        match res {
+            Ok(()) => Ok(None),
+            Err(Error::Command(e)) => {
+                writeln!(output, "error: {}", e)?;
+                Ok(None)
+            }
+            Err(Error::Io(e)) => Err(e),
+        }
+    }
+
+    // Runs until a breakpoint is hit or the CPU stops with an error, e.g.
+    // because it's halted.
+    pub fn run(&mut self, cpu: &mut Cpu, output: &mut dyn Write) -> io::Result<()> {
+        self.run_until(cpu, output, |_| false)
+    }
+
+    // Steps the CPU, returning the breakpoint that stopped it, if any.
+    pub fn step(&mut self, cpu: &mut Cpu) -> Result<Option<Hit>, CpuError> {
+        let resuming = std::mem::take(&mut self.resuming);
+        let hit = self.breakpoints.step(cpu, resuming)?;
+        if hit.is_some() {
+            self.resuming = true;
+        }
+        Ok(hit)
+    }
+
+    fn run_command(
+        &mut self,
+        cpu: &mut Cpu,
+        name: &str,
+        args: &[&str],
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        match name {
+            "s" | "step" => {
+                let n = parse_count(args.first(), 1)?;
+                self.step_n(cpu, n, output)?;
+            }
+            "n" | "next" => self.next(cpu, output)?,
+            "f" | "finish" => self.finish(cpu, output)?,
+            "u" | "until" => {
+                let addr = parse_addr(args.first().ok_or("an address is required")?)?;
+                self.run_until(cpu, output, |cpu| cpu.regs.pc == addr)?;
+            }
+            "r" | "regs" => writeln!(output, "{}", registers(&cpu.regs))?,
+            "m" | "mem" => dump(cpu, args, false, output)?,
+            "pm" | "ppumem" => dump(cpu, args, true, output)?,
+            "w" | "write" => write(cpu, args, false)?,
+            "pw" | "ppuwrite" => write(cpu, args, true)?,
+            "d" | "dis" => disassemble(cpu, args, output)?,
+            "bt" | "stack" => {
+                let n = parse_count(args.first(), DEFAULT_STACK_LEN)?;
+                stack(cpu, n, output)?;
+            }
+            "b" | "break" => {
+                self.add_breakpoint(args, output, |s| parse_range(s).map(Kind::Exec))?
+            }
+            "rwatch" => self.add_breakpoint(args, output, |s| parse_range(s).map(Kind::Read))?,
+            "watch" => self.add_breakpoint(args, output, |s| parse_range(s).map(Kind::Write))?,
+            "vrwatch" => {
+                self.add_breakpoint(args, output, |s| parse_range(s).map(Kind::VramRead))?
+            }
+            "vwatch" => {
+                self.add_breakpoint(args, output, |s| parse_range(s).map(Kind::VramWrite))?
+            }
+            "opbreak" => {
+                let variant = cpu.variant;
+                self.add_breakpoint(args, output, |s| {
+                    Opcode::parse(s, variant).map(Kind::Opcode)
+                })?
+            }
+            "catch" => self.add_breakpoint(args, output, parse_interrupt)?,
+            "bl" | "breakpoints" => {
+                if self.breakpoints.is_empty() {
+                    writeln!(output, "no breakpoints")?;
+                }
+                for b in self.breakpoints.iter() {
+                    writeln!(output, "{}", b)?;
+                }
+            }
+            "ignore" => {
+                let (id, n) = match args {
+                    [id, n] => (parse_count(Some(id), 0)?, parse_count(Some(n), 0)?),
+                    _ => return Err("a breakpoint ID and count are required".into()),
+                };
+                let b = self
+                    .breakpoints
+                    .get_mut(id)
+                    .ok_or_else(|| format!("no breakpoint {}", id))?;
+                b.ignore = n as u64;
+            }
+            "delete" => match args.first() {
+                Some(id) => {
+                    let id = parse_count(Some(id), 0)?;
+                    if !self.breakpoints.remove(id) {
+                        return Err(format!("no breakpoint {}", id).into());
+                    }
+                }
+                None => self.breakpoints.clear(),
+            },
+            "h" | "help" => writeln!(output, "{}", HELP)?,
+            _ => return Err(format!("unknown command {}; try help", name).into()),
+        }
+        Ok(())
+    }
+
+    // Adds a breakpoint from arguments of the form TARGET [if COND].
+    fn add_breakpoint(
+        &mut self,
+        args: &[&str],
+        output: &mut dyn Write,
+        parse_kind: impl FnOnce(&str) -> Result<Kind, String>,
+    ) -> Result<(), Error> {
+        let (target, rest) = args.split_first().ok_or("a target is required")?;
+        let kind = parse_kind(target)?;
+        let condition = match rest.split_first() {
+            Some((&"if", cond)) => Some(Condition::parse(&cond.join(" "))?),
+            Some((word, _)) => return Err(format!("expected if, found {}", word).into()),
+            None => None,
+        };
+        let id = self.breakpoints.add(kind, condition);
+        writeln!(output, "breakpoint {}", id)?;
+        Ok(())
+    }
+
+    fn step_n(&mut self, cpu: &mut Cpu, n: usize, output: &mut dyn Write) -> io::Result<()> {
+        // run_until() always takes one step
+        if n == 0 {
+            return writeln!(output, "{}", cpu::trace_line(cpu));
+        }
+        let mut steps = 0;
+        self.run_until(cpu, output, |_| {
+            steps += 1;
+            steps >= n
+        })
+    }
+
+    // Steps over a JSR by running until it returns to the next instruction.
+    fn next(&mut self, cpu: &mut Cpu, output: &mut dyn Write) -> io::Result<()> {
+        if cpu.bus.peek(cpu.regs.pc) != Some(JSR) {
+            return self.step_n(cpu, 1, output);
+        }
+        let (ret, s) = (cpu.regs.pc.wrapping_add(3), cpu.regs.s);
+        self.run_until(cpu, output, |cpu| cpu.regs.pc == ret && cpu.regs.s == s)
+    }
+
+    // Runs until an RTS returns from the current subroutine. Subroutines it
+    // calls return with their return addresses below the current stack
+    // pointer, so this is the first RTS to run with the stack pointer at or
+    // above where it is now.
+    fn finish(&mut self, cpu: &mut Cpu, output: &mut dyn Write) -> io::Result<()> {
+        let s = cpu.regs.s;
+        let mut returning = cpu.bus.peek(cpu.regs.pc) == Some(RTS);
+        self.run_until(cpu, output, |cpu| {
+            if returning {
+                return true;
+            }
+            returning = cpu.bus.peek(cpu.regs.pc) == Some(RTS) && cpu.regs.s >= s;
+            false
+        })
+    }
+
+    // Steps until stop() returns true after an instruction, or the CPU
+    // returns an error. Either way, shows where the CPU stopped.
+    fn run_until(
+        &mut self,
+        cpu: &mut Cpu,
+        output: &mut dyn Write,
+        mut stop: impl FnMut(&Cpu) -> bool,
+    ) -> io::Result<()> {
+        self.resuming = true;
+        loop {
+            match self.step(cpu) {
+                Ok(Some(hit)) => {
+                    writeln!(output, "{}", hit)?;
+                    break;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    writeln!(output, "{}", e)?;
+                    break;
+                }
+            }
+            if stop(cpu) {
+                break;
+            }
+        }
+        writeln!(output, "{}", cpu::trace_line(cpu))
+    }
+}
+
+// Memory is peeked, so that dumping registers doesn't disturb the program.
+fn dump(cpu: &Cpu, args: &[&str], ppu: bool, output: &mut dyn Write) -> Result<(), Error> {
+    let addr = parse_addr(args.first().ok_or("an address is required")?)?;
+    let len = parse_count(args.get(1), DEFAULT_DUMP_LEN)?;
+
+    let bytes = if ppu {
+        let addr = addr & PPU_ADDR_MASK;
+        let len = len.min(PPU_ADDR_MASK as usize + 1 - addr as usize);
+        (0..len as u16)
+            .map(|i| Some(cpu.bus.ppu.peek(addr + i)))
+            .collect()
+    } else {
+        let len = len.min(0x10000 - addr as usize);
+        cpu.peek_buf(addr, len)
+    };
+
+    for (i, row) in bytes.chunks(DUMP_ROW_LEN).enumerate() {
+        let hex: Vec<String> = row
+            .iter()
+            .map(|b| match b {
+                Some(b) => format!("{:02X}", b),
+                None => "??".to_string(),
+            })
+            .collect();
+        let row_addr = addr as usize + i * DUMP_ROW_LEN;
+        writeln!(output, "{:04X}  {}", row_addr, hex.join(" "))?;
+    }
+    Ok(())
+}
+
+fn write(cpu: &mut Cpu, args: &[&str], ppu: bool) -> Result<(), Error> {
+    let (addr, bytes) = match args.split_first() {
+        Some((addr, bytes)) if !bytes.is_empty() => (parse_addr(addr)?, bytes),
+        _ => return Err("an address and bytes are required".into()),
+    };
+    let bytes = bytes
+        .iter()
+        .map(|b| parse_byte(b))
+        .collect::<Result<Vec<u8>, String>>()?;
+
+    for (i, &v) in bytes.iter().enumerate() {
+        let addr = addr.wrapping_add(i as u16);
+        if ppu {
+            cpu.bus.ppu.mem_write(addr & PPU_ADDR_MASK, v);
+        } else {
+            cpu.try_mem_write(addr, v).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn disassemble(cpu: &mut Cpu, args: &[&str], output: &mut dyn Write) -> Result<(), Error> {
+    let pc = cpu.regs.pc;
+    let (start, n) = match args.first() {
+        Some(addr) => (
+            parse_addr(addr)?,
+            parse_count(args.get(1), DEFAULT_DIS_COUNT)?,
+        ),
+        None => (pc, DEFAULT_DIS_COUNT),
+    };
+
+    let mut lines = if args.is_empty() {
+        lines_before(cpu, pc)
+    } else {
+        Vec::new()
+    };
+    let symbols = disassemble::Symbols::new();
+    // Instructions are 1 to 3 bytes, so no more than 64K fit in memory.
+    let n = n.min(0x10000);
+    let end = (start as usize + 3 * n).min(0xFFFF) as u16;
+    lines.extend(
+        disassemble::disassemble_cpu(cpu, start..=end, &symbols)
+            .into_iter()
+            .take(n),
+    );
+
+    for line in lines {
+        let marker = if line.addr == pc { "=>" } else { "  " };
+        writeln!(output, "{} {}", marker, line)?;
+    }
+    Ok(())
+}
+
+// The instructions just before addr. Since instructions vary in length,
+// this looks for the earliest start that lines up with addr.
+fn lines_before(cpu: &Cpu, addr: u16) -> Vec<disassemble::Line> {
+    let symbols = disassemble::Symbols::new();
+    for back in (1..=3 * DIS_CONTEXT as u16).rev() {
+        let start = match addr.checked_sub(back) {
+            Some(start) => start,
+            None => continue,
+        };
+        let lines = disassemble::disassemble_cpu(cpu, start..=addr - 1, &symbols);
+        let end = lines
+            .last()
+            .map(|line| line.addr as usize + line.bytes.len().max(1));
+        if end == Some(addr as usize) {
+            let skip = lines.len().saturating_sub(DIS_CONTEXT);
+            return lines.into_iter().skip(skip).collect();
+        }
+    }
+    Vec::new()
+}
+
+fn stack(cpu: &mut Cpu, n: usize, output: &mut dyn Write) -> io::Result<()> {
+    // stack_peek() reaches up to the top of the stack page
+    let depth = 0xFF - cpu.regs.s as usize;
+    if depth == 0 {
+        return writeln!(output, "stack is empty");
+    }
+    for offset in 0..n.min(depth) as u8 {
+        let addr = cpu.stack_pointer() + offset as u16 + 1;
+        writeln!(output, "{:04X}  {:02X}", addr, cpu.stack_peek(offset))?;
+    }
+    Ok(())
+}
+
+fn registers(regs: &Registers) -> String {
+    const FLAGS: [(Status, char); 8] = [
+        (Status::Negative, 'N'),
+        (Status::Overflow, 'V'),
+        (Status::ExpansionBit, '-'),
+        (Status::BreakCommand, 'B'),
+        (Status::DecimalMode, 'D'),
+        (Status::InterruptDisable, 'I'),
+        (Status::Zero, 'Z'),
+        (Status::Carry, 'C'),
+    ];
+    let flags: String = FLAGS
+        .iter()
+        .map(|&(status, c)| {
+            if regs.status_check(status) {
+                c
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect();
+    format!(
+        "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} {}",
+        regs.pc, regs.a, regs.x, regs.y, regs.s, regs.p, flags
+    )
+}
+
+fn parse_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+        None => {
+            let addr = parse_addr(s)?;
+            (addr, addr)
+        }
+    };
+    if start > end {
+        return Err(format!("invalid range {}", s));
+    }
+    Ok(start..=end)
+}
+
+fn parse_interrupt(s: &str) -> Result<Kind, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "nmi" => Ok(Kind::Nmi),
+        "irq" => Ok(Kind::Irq),
+        "brk" => Ok(Kind::Brk),
+        _ => Err(format!("invalid interrupt {}; expected nmi, irq or brk", s)),
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    u32::from_str_radix(digits, 16).ok()
+}
+
+fn parse_addr(s: &str) -> Result<u16, String> {
+    match parse_hex(s) {
+        Some(v) if v <= 0xFFFF => Ok(v as u16),
+        _ => Err(format!("invalid address {}", s)),
+    }
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    match parse_hex(s) {
+        Some(v) if v <= 0xFF => Ok(v as u8),
+        _ => Err(format!("invalid byte {}", s)),
+    }
+}
+
+fn parse_count(s: Option<&&str>, default: usize) -> Result<usize, String> {
+    match s {
+        Some(s) => s.parse().map_err(|_| format!("invalid count {}", s)),
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+fn test_cpu() -> Cpu {
+    let mut cpu = Cpu::new_test();
+    let code = cpu::assemble::assemble(
+        "
+        jsr $0607
+        inx
+        done: jmp done
+        iny
+        pha
+        pla
+        rts
+        ",
+        0x0600,
+    )
+    .unwrap();
+    cpu.mem_write_buf(0x0600, code);
+    cpu.regs.pc = 0x0600;
+    cpu.regs.s = 0xFD;
+    cpu
+}
+
+#[cfg(test)]
+fn run_command(debugger: &mut Debugger, cpu: &mut Cpu, line: &str) -> String {
+    let mut output = Vec::new();
+    debugger.command(cpu, line, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_step_commands() {
+    let mut debugger = Debugger::new();
+    let mut cpu = test_cpu();
+    run_command(&mut debugger, &mut cpu, "next");
+    assert_eq!((cpu.regs.pc, cpu.regs.y), (0x0603, 1));
+
+    cpu.regs.pc = 0x0600;
+    run_command(&mut debugger, &mut cpu, "step");
+    assert_eq!(cpu.regs.pc, 0x0607);
+    run_command(&mut debugger, &mut cpu, "step 2");
+    assert_eq!(cpu.regs.pc, 0x0609);
+    run_command(&mut debugger, &mut cpu, "step 0");
+    assert_eq!(cpu.regs.pc, 0x0609);
+    run_command(&mut debugger, &mut cpu, "finish");
+    assert_eq!((cpu.regs.pc, cpu.regs.s), (0x0603, 0xFD));
+
+    run_command(&mut debugger, &mut cpu, "until $0604");
+    assert_eq!(cpu.regs.pc, 0x0604);
+
+    // an empty line repeats the last command
+    cpu.regs.pc = 0x0600;
+    run_command(&mut debugger, &mut cpu, "s");
+    run_command(&mut debugger, &mut cpu, "");
+    assert_eq!(cpu.regs.pc, 0x0608);
+
+    cpu.regs.p = 0x81;
+    let out = run_command(&mut debugger, &mut cpu, "regs");
+    assert_eq!(out, "PC:0608 A:00 X:01 Y:03 SP:FB P:81 Nv-bdizC\n");
+}
+
+#[test]
+fn test_memory_commands() {
+    let mut debugger = Debugger::new();
+    let mut cpu = test_cpu();
+    run_command(&mut debugger, &mut cpu, "w 0200 12 $34");
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "m 200 2"),
+        "0200  12 34\n"
+    );
+
+    run_command(&mut debugger, &mut cpu, "pw 3F00 0F");
+    assert_eq!(cpu.bus.ppu.mem_read(0x3F00), 0x0F);
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "pm 3F00 1"),
+        "3F00  0F\n"
+    );
+
+    // registers are peeked
+    run_command(&mut debugger, &mut cpu, "w 2003 1F");
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "m 2002 1"),
+        "2002  1F\n"
+    );
+
+    cpu.regs.a = 0xAB;
+    run_command(&mut debugger, &mut cpu, "step 3");
+    let out = run_command(&mut debugger, &mut cpu, "stack 1");
+    assert_eq!(out, "01FB  AB\n");
+    let out = run_command(&mut debugger, &mut cpu, "stack");
+    assert_eq!(out.lines().count(), 5);
+
+    let out = run_command(&mut debugger, &mut cpu, "dis");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "   0604  4C 04 06  JMP $0604");
+    assert_eq!(lines[2], "   0608  48        PHA");
+    assert_eq!(lines[3], "=> 0609  68        PLA");
+
+    // counts past the end of memory stop there
+    let out = run_command(&mut debugger, &mut cpu, "dis FFF0 18446744073709551615");
+    assert_eq!(out.lines().count(), 16);
+    assert!(out.ends_with("FFFF  00        BRK\n"), "{}", out);
+
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "m"),
+        "error: an address is required\n"
+    );
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "w 0200 100"),
+        "error: invalid byte 100\n"
+    );
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "foo"),
+        "error: unknown command foo; try help\n"
+    );
+}
+
+#[test]
+fn test_repl() {
+    let mut debugger = Debugger::new();
+    let mut cpu = test_cpu();
+    let mut output = Vec::new();
+    let resume = debugger.repl(&mut cpu, &mut &b"s\n\nc\n"[..], &mut output);
+    assert_eq!(resume.unwrap(), Resume::Continue);
+    assert_eq!(cpu.regs.pc, 0x0608);
+
+    let resume = debugger.repl(&mut cpu, &mut &b""[..], &mut output);
+    assert_eq!(resume.unwrap(), Resume::Quit);
+}
+
+#[test]
+fn test_breakpoint_commands() {
+    let mut debugger = Debugger::new();
+    let mut cpu = test_cpu();
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "b 0607 if Y == 1"),
+        "breakpoint 1\n"
+    );
+    run_command(&mut debugger, &mut cpu, "watch 01FC-01FD");
+    run_command(&mut debugger, &mut cpu, "ignore 2 1");
+
+    // the first call pushes the return address, which is ignored
+    let out = run_command(&mut debugger, &mut cpu, "until 0604");
+    assert_eq!(cpu.regs.pc, 0x0604);
+    assert!(out.starts_with("0604"), "{}", out);
+
+    cpu.regs.pc = 0x0600;
+    let out = run_command(&mut debugger, &mut cpu, "until 0604");
+    assert!(
+        out.starts_with("breakpoint 2: watch $01FC-$01FD (write"),
+        "{}",
+        out
+    );
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "bl"),
+        "1   break $0607 if Y == 1 (hits: 0)\n2   watch $01FC-$01FD (hits: 2)\n"
+    );
+
+    // resuming steps over a breakpoint at pc
+    run_command(&mut debugger, &mut cpu, "delete 2");
+    run_command(&mut debugger, &mut cpu, "until 0604");
+    assert_eq!(cpu.regs.pc, 0x0604);
+
+    cpu.regs.pc = 0x0600;
+    cpu.regs.y = 1;
+    let out = run_command(&mut debugger, &mut cpu, "until 0604");
+    assert!(out.starts_with("breakpoint 1: break $0607\n"), "{}", out);
+    assert_eq!(cpu.regs.pc, 0x0607);
+    run_command(&mut debugger, &mut cpu, "delete 1");
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "delete 1"),
+        "error: no breakpoint 1\n"
+    );
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "catch reset"),
+        "error: invalid interrupt reset; expected nmi, irq or brk\n"
+    );
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "b 0600 when A == 1"),
+        "error: expected if, found when\n"
+    );
+    run_command(&mut debugger, &mut cpu, "delete");
+    assert_eq!(
+        run_command(&mut debugger, &mut cpu, "bl"),
+        "no breakpoints\n"
+    );
+}
@@ -0,0 +1,212 @@
+// A console without a window, sound or host input, for tests and tools.
+//
+// Controller input comes from a schedule set up front, and everything runs
+// on the emulated clock, so a ROM given the same schedule produces the same
+// frames on every run.
+
+use crate::cpu::{Cpu, CpuError};
+use crate::ines;
+use crate::input::FourScore;
+use std::collections::BTreeMap;
+
+pub struct Nes {
+    pub cpu: Cpu,
+
+    // Frames completed since power-on.
+    frame: u64,
+
+    // Scheduled buttons for each controller, keyed by the frame they're
+    // first held on. Each entry lasts until the next.
+    input: BTreeMap<u64, [u8; 4]>,
+}
+
+impl Nes {
+    // Powers on a console with the given cartridge. A Four Score is
+    // connected if the header asks for one.
+    pub fn new(rom: &ines::Rom) -> Result<Nes, ines::Error> {
+        let (prg, chr) = rom.mapper()?;
+        let mut cpu = Cpu::new(prg, chr);
+        if rom.expansion_device == ines::EXPANSION_FOUR_SCORE {
+            cpu.bus.input.four_score = Some(FourScore::new());
+        }
+        cpu.reset();
+        Ok(Nes {
+            cpu,
+            frame: 0,
+            input: BTreeMap::new(),
+        })
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Holds the given buttons from the start of a frame until the next frame
+    // with input set, in the bit layout used by Controller::set_buttons.
+    // Input set for the current or an earlier frame applies at once.
+    pub fn set_input(&mut self, frame: u64, buttons: [u8; 4]) {
+        self.input.insert(frame, buttons);
+        self.apply_input();
+    }
+
+    // Runs until the PPU finishes the current frame.
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        let frame = self.frame;
+        while self.frame == frame {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // Runs for at least n CPU cycles, stopping after the instruction that
+    // reaches them.
+    pub fn run_cycles(&mut self, n: u64) -> Result<(), CpuError> {
+        let end = self.cpu.cycles + n;
+        while self.cpu.cycles < end {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // Runs until done returns true. It's checked before each instruction,
+    // so nothing runs if it's true already.
+    pub fn run_until<F: FnMut(&Nes) -> bool>(&mut self, mut done: F) -> Result<(), CpuError> {
+        while !done(self) {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // The last frame drawn, as rows of RGB pixels.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.cpu.bus.ppu.framebuf[..]
+    }
+
+    fn step(&mut self) -> Result<(), CpuError> {
+        let ppu_frame = self.cpu.bus.ppu.frame();
+        let res = self.cpu.step();
+        if self.cpu.bus.ppu.frame() != ppu_frame {
+            self.frame += 1;
+            self.apply_input();
+        }
+        res
+    }
+
+    // Controllers are left alone until the schedule has an entry, so that
+    // they can also be set directly.
+    fn apply_input(&mut self) {
+        if let Some((_, buttons)) = self.input.range(..=self.frame).next_back() {
+            for (c, b) in self.cpu.bus.input.controllers.iter_mut().zip(buttons) {
+                c.set_buttons(*b);
+            }
+        }
+    }
+}
+
+// An NROM cartridge whose NMI handler records controller 1 at $0300 plus
+// the frame number, and whose main loop spins at $C020.
+#[cfg(test)]
+fn test_rom() -> ines::Rom {
+    let code = crate::cpu::assemble::assemble(
+        "
+        lda #$3F
+        sta $2006
+        lda #$00
+        sta $2006
+        lda #$16
+        sta $2007
+        lda #$80
+        sta $2000
+        lda #$0A
+        sta $2001
+        jmp $C020
+        ",
+        0xC000,
+    )
+    .unwrap();
+    let spin = crate::cpu::assemble::assemble("spin: jmp spin", 0xC020).unwrap();
+    let nmi = crate::cpu::assemble::assemble(
+        "
+        lda #$01
+        sta $4016
+        lda #$00
+        sta $4016
+        ldx #$08
+        read: lda $4016
+        lsr
+        ror $10
+        dex
+        bne read
+        inc $11
+        ldy $11
+        lda $10
+        sta $0300,y
+        rti
+        ",
+        0xC040,
+    )
+    .unwrap();
+
+    let mut prg = vec![0; 0x4000];
+    prg[..code.len()].copy_from_slice(&code);
+    prg[0x20..0x20 + spin.len()].copy_from_slice(&spin);
+    prg[0x40..0x40 + nmi.len()].copy_from_slice(&nmi);
+    prg[0x3FFA..].copy_from_slice(&[0x40, 0xC0, 0x00, 0xC0, 0x00, 0xC0]);
+
+    let mut bytes = b"NES\x1A\x01\x01\x00\x00".to_vec();
+    bytes.resize(16, 0);
+    bytes.extend_from_slice(&prg);
+    bytes.resize(16 + 0x4000 + 0x2000, 0);
+    ines::parse(&bytes).unwrap()
+}
+
+#[test]
+fn test_run() {
+    let mut nes = Nes::new(&test_rom()).unwrap();
+    nes.run_until(|nes| nes.cpu.regs.pc == 0xC020).unwrap();
+    assert_eq!(nes.frame(), 0);
+
+    let cycles = nes.cpu.cycles;
+    nes.run_cycles(100).unwrap();
+    assert!(nes.cpu.cycles >= cycles + 100 && nes.cpu.cycles < cycles + 110);
+
+    nes.run_frame().unwrap();
+    assert_eq!(nes.frame(), 1);
+    nes.run_frame().unwrap();
+    assert_eq!(nes.frame(), 2);
+
+    // the backdrop color fills the screen
+    let rgb = &nes.framebuffer()[..3];
+    assert_ne!(rgb, [0, 0, 0]);
+    assert!(nes.framebuffer().chunks(3).all(|px| px == rgb));
+}
+
+#[test]
+fn test_input() {
+    let mut nes = Nes::new(&test_rom()).unwrap();
+    nes.set_input(2, [0x01, 0, 0, 0]);
+    nes.set_input(4, [0x81, 0, 0, 0]);
+    nes.set_input(5, [0, 0, 0, 0]);
+    for _ in 0..6 {
+        nes.run_frame().unwrap();
+    }
+
+    // NMI n runs during frame n - 1
+    let recorded: Vec<u8> = (1..=6).map(|i| nes.cpu.mem_read(0x0300 + i)).collect();
+    assert_eq!(recorded, [0, 0, 0x01, 0x01, 0x81, 0]);
+}
+
+#[test]
+fn test_deterministic() {
+    let run = || {
+        let mut nes = Nes::new(&test_rom()).unwrap();
+        for frame in 0..10 {
+            nes.set_input(frame * 2, [frame as u8, 0, 0, 0]);
+        }
+        for _ in 0..20 {
+            nes.run_frame().unwrap();
+        }
+        (crate::savestate::save(&nes.cpu), nes.framebuffer().to_vec())
+    };
+    assert!(run() == run());
+}
@@ -0,0 +1,163 @@
+// Reference: https://wiki.nesdev.com/w/index.php/INES
+
+use crate::mapper;
+use std::error;
+use std::fmt;
+
+const MAGIC: &[u8] = b"NES\x1A";
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_UNIT: usize = 1 << 14;
+const CHR_UNIT: usize = 1 << 13;
+
+// Default expansion devices, from NES 2.0 headers.
+// https://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+pub const EXPANSION_NONE: u8 = 0x00;
+pub const EXPANSION_FOUR_SCORE: u8 = 0x02;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidMagic,
+    Truncated(usize),
+    UnsupportedMapper(u8),
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "not an iNES file"),
+            Self::Truncated(len) => write!(f, "file too short for iNES data: {} bytes", len),
+            Self::UnsupportedMapper(n) => write!(f, "unsupported mapper: {}", n),
+        }
+    }
+}
+
+pub struct Rom {
+    pub mapper: u8,
+    pub mirroring: mapper::Mirroring,
+    pub battery: bool,
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>, // empty if the board uses CHR RAM
+
+    // The input device the game expects to be connected. Only NES 2.0
+    // headers specify this; it is EXPANSION_NONE otherwise.
+    pub expansion_device: u8,
+}
+
+impl Rom {
+    pub fn mapper(&self) -> Result<mapper::Cartridge, Error> {
+        match self.mapper {
+            0 => {
+                let (prg, chr) = mapper::nrom::new(&self.prg, &self.chr, self.mirroring);
+                Ok((Box::new(prg), Box::new(chr)))
+            }
+            n => Err(Error::UnsupportedMapper(n)),
+        }
+    }
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Rom, Error> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::Truncated(bytes.len()));
+    }
+
+    if &bytes[0..4] != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+
+    let prg_size = bytes[4] as usize * PRG_UNIT;
+    let chr_size = bytes[5] as usize * CHR_UNIT;
+    let flags6 = bytes[6];
+    let flags7 = bytes[7];
+
+    // Some old dumping tools wrote a signature into bytes 7-15. Unless this
+    // is an NES 2.0 header, or the tail of the header is zeroed, only trust
+    // the low nibble of the mapper number.
+    let nes2 = flags7 & 0b1100 == 0b1000;
+    let mapper_hi = if nes2 || bytes[12..16].iter().all(|&b| b == 0) {
+        flags7 & 0xF0
+    } else {
+        0
+    };
+
+    let mirroring = if flags6 & 0b1000 != 0 {
+        mapper::Mirroring::FourScreen
+    } else if flags6 & 0b1 != 0 {
+        mapper::Mirroring::Vertical
+    } else {
+        mapper::Mirroring::Horizontal
+    };
+
+    let prg_start = HEADER_SIZE + if flags6 & 0b100 != 0 { TRAINER_SIZE } else { 0 };
+    let chr_start = prg_start + prg_size;
+    let chr_end = chr_start + chr_size;
+    if bytes.len() < chr_end {
+        return Err(Error::Truncated(bytes.len()));
+    }
+
+    Ok(Rom {
+        mapper: mapper_hi | (flags6 >> 4),
+        mirroring,
+        battery: flags6 & 0b10 != 0,
+        prg: bytes[prg_start..chr_start].to_vec(),
+        chr: bytes[chr_start..chr_end].to_vec(),
+        expansion_device: if nes2 {
+            bytes[15] & 0b0011_1111
+        } else {
+            EXPANSION_NONE
+        },
+    })
+}
+
+#[cfg(test)]
+fn test_rom(prg_units: u8, chr_units: u8, flags6: u8, flags7: u8) -> Vec<u8> {
+    let mut bytes = vec![b'N', b'E', b'S', 0x1A, prg_units, chr_units, flags6, flags7];
+    bytes.resize(HEADER_SIZE, 0);
+    bytes.resize(HEADER_SIZE + prg_units as usize * PRG_UNIT, 0xAA);
+    bytes.resize(bytes.len() + chr_units as usize * CHR_UNIT, 0xBB);
+    bytes
+}
+
+#[test]
+fn test_parse() {
+    let rom = parse(&test_rom(2, 1, 0b0000_0001, 0)).unwrap();
+    assert_eq!(rom.mapper, 0);
+    assert_eq!(rom.mirroring, mapper::Mirroring::Vertical);
+    assert_eq!(rom.prg, vec![0xAA; 2 * PRG_UNIT]);
+    assert_eq!(rom.chr, vec![0xBB; CHR_UNIT]);
+    assert!(rom.mapper().is_ok());
+
+    let rom = parse(&test_rom(1, 0, 0b0001_1010, 0b0100_0000)).unwrap();
+    assert_eq!(rom.mapper, 0x41);
+    assert_eq!(rom.mirroring, mapper::Mirroring::FourScreen);
+    assert!(rom.battery);
+    assert!(rom.chr.is_empty());
+    assert_eq!(rom.mapper().err(), Some(Error::UnsupportedMapper(0x41)));
+    assert_eq!(rom.expansion_device, EXPANSION_NONE);
+
+    // NES 2.0
+    let mut bytes = test_rom(1, 1, 0b0001_0000, 0b0010_1000);
+    bytes[15] = EXPANSION_FOUR_SCORE;
+    let rom = parse(&bytes).unwrap();
+    assert_eq!(rom.mapper, 0x21);
+    assert_eq!(rom.expansion_device, EXPANSION_FOUR_SCORE);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(parse(b"NES").err(), Some(Error::Truncated(3)));
+    assert_eq!(parse(&[0; 16]).err(), Some(Error::InvalidMagic));
+
+    let mut bytes = test_rom(1, 1, 0, 0);
+    bytes.pop();
+    assert_eq!(
+        parse(&bytes).err(),
+        Some(Error::Truncated(HEADER_SIZE + PRG_UNIT + CHR_UNIT - 1))
+    );
+
+    // trainer
+    let bytes = test_rom(1, 1, 0b100, 0);
+    assert_eq!(parse(&bytes).err(), Some(Error::Truncated(bytes.len())));
+}
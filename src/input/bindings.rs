@@ -0,0 +1,712 @@
+// Maps SDL keyboard keys and game controller buttons/axes to NES controller
+// buttons for up to four players.
+//
+// Bindings are stored in a plain text config file, one setting per line:
+//
+//   turbo_a_rate = 15
+//   p1.a = key:X
+//   p1.a = pad0:B
+//   p1.left = pad0:LeftX-
+//   p2.turbo_b = key:K
+//
+// Keys use SDL keycode names (e.g. "Return", "LShift", "Num1"; see KEYS).
+// Pads are numbered in the order they were connected. Pad buttons use SDL game
+// controller button names, and pad axes are an axis name followed by the
+// direction of deflection. A button may have any number of sources. Turbo
+// rates are in presses per second, and are shared by all players: there's one
+// for A and one for B.
+
+use super::Button;
+use sdl2::controller::{Axis, Button as PadButton};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const PLAYERS: usize = 4;
+
+const FRAME_RATE: u32 = 60;
+const DEFAULT_TURBO_RATE: u32 = 15;
+
+// Axis deflection required to press a button, out of 32767.
+const AXIS_THRESHOLD: i16 = 16384;
+
+// Keys that can be named in the config file.
+const KEYS: [Keycode; 96] = [
+    Keycode::A,
+    Keycode::B,
+    Keycode::C,
+    Keycode::D,
+    Keycode::E,
+    Keycode::F,
+    Keycode::G,
+    Keycode::H,
+    Keycode::I,
+    Keycode::J,
+    Keycode::K,
+    Keycode::L,
+    Keycode::M,
+    Keycode::N,
+    Keycode::O,
+    Keycode::P,
+    Keycode::Q,
+    Keycode::R,
+    Keycode::S,
+    Keycode::T,
+    Keycode::U,
+    Keycode::V,
+    Keycode::W,
+    Keycode::X,
+    Keycode::Y,
+    Keycode::Z,
+    Keycode::Num0,
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Num4,
+    Keycode::Num5,
+    Keycode::Num6,
+    Keycode::Num7,
+    Keycode::Num8,
+    Keycode::Num9,
+    Keycode::Up,
+    Keycode::Down,
+    Keycode::Left,
+    Keycode::Right,
+    Keycode::Return,
+    Keycode::Space,
+    Keycode::Tab,
+    Keycode::Backspace,
+    Keycode::Escape,
+    Keycode::LShift,
+    Keycode::RShift,
+    Keycode::LCtrl,
+    Keycode::RCtrl,
+    Keycode::LAlt,
+    Keycode::RAlt,
+    Keycode::Comma,
+    Keycode::Period,
+    Keycode::Slash,
+    Keycode::Semicolon,
+    Keycode::Quote,
+    Keycode::LeftBracket,
+    Keycode::RightBracket,
+    Keycode::Backslash,
+    Keycode::Minus,
+    Keycode::Equals,
+    Keycode::Backquote,
+    Keycode::Insert,
+    Keycode::Delete,
+    Keycode::Home,
+    Keycode::End,
+    Keycode::PageUp,
+    Keycode::PageDown,
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F7,
+    Keycode::F8,
+    Keycode::F9,
+    Keycode::F10,
+    Keycode::F11,
+    Keycode::F12,
+    Keycode::Kp0,
+    Keycode::Kp1,
+    Keycode::Kp2,
+    Keycode::Kp3,
+    Keycode::Kp4,
+    Keycode::Kp5,
+    Keycode::Kp6,
+    Keycode::Kp7,
+    Keycode::Kp8,
+    Keycode::Kp9,
+    Keycode::KpPlus,
+    Keycode::KpMinus,
+    Keycode::KpMultiply,
+    Keycode::KpDivide,
+    Keycode::KpEnter,
+    Keycode::KpPeriod,
+];
+
+const PAD_BUTTONS: [PadButton; 15] = [
+    PadButton::A,
+    PadButton::B,
+    PadButton::X,
+    PadButton::Y,
+    PadButton::Back,
+    PadButton::Guide,
+    PadButton::Start,
+    PadButton::LeftStick,
+    PadButton::RightStick,
+    PadButton::LeftShoulder,
+    PadButton::RightShoulder,
+    PadButton::DPadUp,
+    PadButton::DPadDown,
+    PadButton::DPadLeft,
+    PadButton::DPadRight,
+];
+
+const AXES: [Axis; 6] = [
+    Axis::LeftX,
+    Axis::LeftY,
+    Axis::RightX,
+    Axis::RightY,
+    Axis::TriggerLeft,
+    Axis::TriggerRight,
+];
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidLine(usize, String),
+    InvalidSetting(usize, String),
+    InvalidSource(usize, String),
+    InvalidRate(usize, String),
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::InvalidLine(line, src) => write!(f, "line {}: invalid line: {}", line, src),
+            Self::InvalidSetting(line, src) => {
+                write!(f, "line {}: invalid setting: {}", line, src)
+            }
+            Self::InvalidSource(line, src) => write!(f, "line {}: invalid source: {}", line, src),
+            Self::InvalidRate(line, src) => write!(f, "line {}: invalid turbo rate: {}", line, src),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+// An input on the host that can be bound to an NES button.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Source {
+    Key(Keycode),
+    PadButton(usize, PadButton),
+
+    // An axis deflected past the threshold. The flag selects the positive
+    // direction.
+    PadAxis(usize, Axis, bool),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Key(k) => write!(f, "key:{:?}", k),
+            Self::PadButton(pad, b) => write!(f, "pad{}:{:?}", pad, b),
+            Self::PadAxis(pad, a, positive) => {
+                write!(f, "pad{}:{:?}{}", pad, a, if *positive { '+' } else { '-' })
+            }
+        }
+    }
+}
+
+impl Source {
+    pub fn parse(src: &str) -> Option<Source> {
+        let (device, name) = src.split_once(':')?;
+        if device == "key" {
+            return keycode_from_name(name).map(Source::Key);
+        }
+
+        let pad: usize = device.strip_prefix("pad")?.parse().ok()?;
+        if let Some(b) = PAD_BUTTONS.iter().find(|b| format!("{:?}", b) == name) {
+            return Some(Source::PadButton(pad, *b));
+        }
+
+        let (name, positive) = match name.strip_suffix('+') {
+            Some(name) => (name, true),
+            None => (name.strip_suffix('-')?, false),
+        };
+        AXES.iter()
+            .find(|a| format!("{:?}", a) == name)
+            .map(|a| Source::PadAxis(pad, *a, positive))
+    }
+}
+
+fn keycode_from_name(name: &str) -> Option<Keycode> {
+    KEYS.iter().copied().find(|k| format!("{:?}", k) == name)
+}
+
+// What a source does when held: press a button, or toggle it at that
+// button's turbo rate. Only A and B have turbo variants.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    Button(Button),
+    Turbo(Button),
+}
+
+impl Target {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Button(Button::A) => "a",
+            Self::Button(Button::B) => "b",
+            Self::Button(Button::Select) => "select",
+            Self::Button(Button::Start) => "start",
+            Self::Button(Button::Up) => "up",
+            Self::Button(Button::Down) => "down",
+            Self::Button(Button::Left) => "left",
+            Self::Button(Button::Right) => "right",
+            Self::Turbo(Button::A) => "turbo_a",
+            Self::Turbo(Button::B) => "turbo_b",
+            Self::Turbo(b) => panic!("no turbo for button {:?}", b),
+        }
+    }
+
+    fn parse(src: &str) -> Option<Target> {
+        let target = match src {
+            "a" => Target::Button(Button::A),
+            "b" => Target::Button(Button::B),
+            "select" => Target::Button(Button::Select),
+            "start" => Target::Button(Button::Start),
+            "up" => Target::Button(Button::Up),
+            "down" => Target::Button(Button::Down),
+            "left" => Target::Button(Button::Left),
+            "right" => Target::Button(Button::Right),
+            "turbo_a" => Target::Turbo(Button::A),
+            "turbo_b" => Target::Turbo(Button::B),
+            _ => return None,
+        };
+        Some(target)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Binding {
+    pub player: usize,
+    pub target: Target,
+    pub source: Source,
+}
+
+pub struct Bindings {
+    pub bindings: Vec<Binding>,
+    pub turbo_a_rate: u32,
+    pub turbo_b_rate: u32,
+
+    // Current host input state.
+    keys: HashSet<Keycode>,
+    pad_buttons: HashSet<(usize, PadButton)>,
+    pad_axes: HashMap<(usize, Axis), i16>,
+
+    // Joystick instance IDs of connected pads, indexed by pad number.
+    pads: Vec<Option<i32>>,
+}
+
+impl Default for Bindings {
+    // Player 1 on the keyboard and the first pad, and players 2-4 on the
+    // remaining pads.
+    fn default() -> Bindings {
+        let mut bindings = Bindings::empty();
+
+        let keys = [
+            (Target::Button(Button::A), Keycode::X),
+            (Target::Button(Button::B), Keycode::Z),
+            (Target::Button(Button::Select), Keycode::RShift),
+            (Target::Button(Button::Start), Keycode::Return),
+            (Target::Button(Button::Up), Keycode::Up),
+            (Target::Button(Button::Down), Keycode::Down),
+            (Target::Button(Button::Left), Keycode::Left),
+            (Target::Button(Button::Right), Keycode::Right),
+            (Target::Turbo(Button::A), Keycode::S),
+            (Target::Turbo(Button::B), Keycode::A),
+        ];
+        for (target, key) in keys.iter() {
+            bindings.bind(0, *target, Source::Key(*key));
+        }
+
+        let pad_buttons = [
+            (Target::Button(Button::A), PadButton::B),
+            (Target::Button(Button::B), PadButton::A),
+            (Target::Button(Button::Select), PadButton::Back),
+            (Target::Button(Button::Start), PadButton::Start),
+            (Target::Button(Button::Up), PadButton::DPadUp),
+            (Target::Button(Button::Down), PadButton::DPadDown),
+            (Target::Button(Button::Left), PadButton::DPadLeft),
+            (Target::Button(Button::Right), PadButton::DPadRight),
+            (Target::Turbo(Button::A), PadButton::Y),
+            (Target::Turbo(Button::B), PadButton::X),
+        ];
+        let pad_axes = [
+            (Target::Button(Button::Up), Axis::LeftY, false),
+            (Target::Button(Button::Down), Axis::LeftY, true),
+            (Target::Button(Button::Left), Axis::LeftX, false),
+            (Target::Button(Button::Right), Axis::LeftX, true),
+        ];
+        for player in 0..PLAYERS {
+            for (target, button) in pad_buttons.iter() {
+                bindings.bind(player, *target, Source::PadButton(player, *button));
+            }
+            for (target, axis, positive) in pad_axes.iter() {
+                bindings.bind(player, *target, Source::PadAxis(player, *axis, *positive));
+            }
+        }
+
+        bindings
+    }
+}
+
+impl Bindings {
+    pub fn empty() -> Bindings {
+        Bindings {
+            bindings: Vec::new(),
+            turbo_a_rate: DEFAULT_TURBO_RATE,
+            turbo_b_rate: DEFAULT_TURBO_RATE,
+            keys: HashSet::new(),
+            pad_buttons: HashSet::new(),
+            pad_axes: HashMap::new(),
+            pads: Vec::new(),
+        }
+    }
+
+    pub fn bind(&mut self, player: usize, target: Target, source: Source) {
+        self.bindings.push(Binding {
+            player,
+            target,
+            source,
+        });
+    }
+
+    pub fn parse(src: &str) -> Result<Bindings, Error> {
+        let mut bindings = Bindings::empty();
+
+        for (i, line) in src.lines().enumerate() {
+            let line_num = i + 1;
+            let line = match line.find('#') {
+                Some(n) => &line[..n],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, val) = match line.split_once('=') {
+                Some((key, val)) => (key.trim(), val.trim()),
+                None => return Err(Error::InvalidLine(line_num, line.to_string())),
+            };
+
+            match key {
+                "turbo_a_rate" | "turbo_b_rate" => {
+                    let rate = match val.parse() {
+                        Ok(rate) if (1..=FRAME_RATE / 2).contains(&rate) => rate,
+                        _ => return Err(Error::InvalidRate(line_num, val.to_string())),
+                    };
+                    if key == "turbo_a_rate" {
+                        bindings.turbo_a_rate = rate;
+                    } else {
+                        bindings.turbo_b_rate = rate;
+                    }
+                }
+                _ => {
+                    let (player, target) = parse_setting(key)
+                        .ok_or_else(|| Error::InvalidSetting(line_num, key.to_string()))?;
+                    let source = Source::parse(val)
+                        .ok_or_else(|| Error::InvalidSource(line_num, val.to_string()))?;
+                    bindings.bind(player, target, source);
+                }
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn load(path: &Path) -> Result<Bindings, Error> {
+        Bindings::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    // Registers a newly opened game controller, and returns its pad number.
+    pub fn add_pad(&mut self, instance_id: i32) -> usize {
+        match self.pads.iter().position(|p| p.is_none()) {
+            Some(n) => {
+                self.pads[n] = Some(instance_id);
+                n
+            }
+            None => {
+                self.pads.push(Some(instance_id));
+                self.pads.len() - 1
+            }
+        }
+    }
+
+    pub fn remove_pad(&mut self, instance_id: i32) {
+        if let Some(n) = self.pad_number(instance_id) {
+            self.pads[n] = None;
+            self.pad_buttons.retain(|(pad, _)| *pad != n);
+            self.pad_axes.retain(|(pad, _), _| *pad != n);
+        }
+    }
+
+    fn pad_number(&self, instance_id: i32) -> Option<usize> {
+        self.pads.iter().position(|p| *p == Some(instance_id))
+    }
+
+    // Updates host input state from an SDL event.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(k), ..
+            } => {
+                self.keys.insert(k);
+            }
+            Event::KeyUp {
+                keycode: Some(k), ..
+            } => {
+                self.keys.remove(&k);
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(pad) = self.pad_number(which) {
+                    self.pad_buttons.insert((pad, button));
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(pad) = self.pad_number(which) {
+                    self.pad_buttons.remove(&(pad, button));
+                }
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                if let Some(pad) = self.pad_number(which) {
+                    self.pad_axes.insert((pad, axis), value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn held(&self, source: Source) -> bool {
+        match source {
+            Source::Key(k) => self.keys.contains(&k),
+            Source::PadButton(pad, b) => self.pad_buttons.contains(&(pad, b)),
+            Source::PadAxis(pad, a, positive) => {
+                let v = self.pad_axes.get(&(pad, a)).copied().unwrap_or(0);
+                if positive {
+                    v >= AXIS_THRESHOLD
+                } else {
+                    v <= -AXIS_THRESHOLD
+                }
+            }
+        }
+    }
+
+    // Returns each player's buttons for the given frame, in the bit layout
+    // used by Controller::set_buttons.
+    pub fn buttons(&self, frame: u64) -> [u8; PLAYERS] {
+        let mut res = [0; PLAYERS];
+
+        for b in self.bindings.iter() {
+            if b.player >= PLAYERS || !self.held(b.source) {
+                continue;
+            }
+            match b.target {
+                Target::Button(button) => res[b.player] |= button.mask(),
+                Target::Turbo(button) => {
+                    let rate = match button {
+                        Button::A => self.turbo_a_rate,
+                        _ => self.turbo_b_rate,
+                    };
+                    if turbo_phase(rate, frame) {
+                        res[b.player] |= button.mask();
+                    }
+                }
+            }
+        }
+
+        res
+    }
+}
+
+impl fmt::Display for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "turbo_a_rate = {}", self.turbo_a_rate)?;
+        writeln!(f, "turbo_b_rate = {}", self.turbo_b_rate)?;
+        for b in self.bindings.iter() {
+            writeln!(f, "p{}.{} = {}", b.player + 1, b.target.name(), b.source)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses a binding setting name, e.g. "p2.turbo_a".
+fn parse_setting(src: &str) -> Option<(usize, Target)> {
+    let (player, target) = src.split_once('.')?;
+    let player: usize = player.strip_prefix('p')?.parse().ok()?;
+    if !(1..=PLAYERS).contains(&player) {
+        return None;
+    }
+    Some((player - 1, Target::parse(target)?))
+}
+
+// Whether a turbo button is pressed on the given frame. Turbo buttons are
+// pressed for the first half of each period.
+fn turbo_phase(rate: u32, frame: u64) -> bool {
+    let period = (FRAME_RATE / rate.max(1)).max(2) as u64;
+    frame % period < period / 2
+}
+
+#[test]
+fn test_source_names() {
+    let sources = [
+        Source::Key(Keycode::X),
+        Source::Key(Keycode::Return),
+        Source::Key(Keycode::RShift),
+        Source::PadButton(0, PadButton::DPadUp),
+        Source::PadAxis(3, Axis::LeftX, true),
+        Source::PadAxis(1, Axis::TriggerLeft, false),
+    ];
+    for s in sources.iter() {
+        assert_eq!(Source::parse(&s.to_string()), Some(*s));
+    }
+
+    assert_eq!(Source::parse("key:Nope"), None);
+    assert_eq!(Source::parse("padX:A"), None);
+    assert_eq!(Source::parse("pad0:LeftX"), None);
+}
+
+#[test]
+fn test_config_round_trip() {
+    let bindings = Bindings {
+        turbo_a_rate: 10,
+        turbo_b_rate: 30,
+        ..Bindings::default()
+    };
+
+    let parsed = Bindings::parse(&bindings.to_string()).unwrap();
+    assert_eq!(parsed.bindings, bindings.bindings);
+    assert_eq!(parsed.turbo_a_rate, 10);
+    assert_eq!(parsed.turbo_b_rate, 30);
+}
+
+#[test]
+fn test_config_errors() {
+    let parse_err = |src| Bindings::parse(src).err().unwrap().to_string();
+
+    assert_eq!(parse_err("p1.a"), "line 1: invalid line: p1.a");
+    assert_eq!(
+        parse_err("# comment\np5.a = key:X"),
+        "line 2: invalid setting: p5.a"
+    );
+    assert_eq!(
+        parse_err("p1.jump = key:X"),
+        "line 1: invalid setting: p1.jump"
+    );
+    assert_eq!(
+        parse_err("p1.a = key:Nope"),
+        "line 1: invalid source: key:Nope"
+    );
+    assert_eq!(
+        parse_err("turbo_a_rate = 0"),
+        "line 1: invalid turbo rate: 0"
+    );
+}
+
+#[test]
+fn test_buttons() {
+    let mut bindings = Bindings::parse(
+        "
+        p1.a = key:X
+        p1.a = pad0:B # a second source for the same button
+        p2.left = pad1:LeftX-
+        p2.right = pad1:LeftX+
+        ",
+    )
+    .unwrap();
+    let pad0 = bindings.add_pad(100);
+    let pad1 = bindings.add_pad(200);
+    assert_eq!((pad0, pad1), (0, 1));
+
+    let key = |keycode, down| {
+        if down {
+            Event::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod: sdl2::keyboard::Mod::empty(),
+                repeat: false,
+            }
+        } else {
+            Event::KeyUp {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod: sdl2::keyboard::Mod::empty(),
+                repeat: false,
+            }
+        }
+    };
+
+    bindings.handle_event(&key(Keycode::X, true));
+    assert_eq!(bindings.buttons(0), [Button::A.mask(), 0, 0, 0]);
+
+    bindings.handle_event(&Event::ControllerButtonDown {
+        timestamp: 0,
+        which: 100,
+        button: PadButton::B,
+    });
+    bindings.handle_event(&key(Keycode::X, false));
+    assert_eq!(bindings.buttons(0), [Button::A.mask(), 0, 0, 0]);
+
+    let axis = |value| Event::ControllerAxisMotion {
+        timestamp: 0,
+        which: 200,
+        axis: Axis::LeftX,
+        value,
+    };
+    bindings.handle_event(&axis(-AXIS_THRESHOLD + 1));
+    assert_eq!(bindings.buttons(0)[1], 0);
+    bindings.handle_event(&axis(-AXIS_THRESHOLD));
+    assert_eq!(bindings.buttons(0)[1], Button::Left.mask());
+    bindings.handle_event(&axis(i16::MAX));
+    assert_eq!(bindings.buttons(0)[1], Button::Right.mask());
+
+    // pad state is dropped on disconnect, and its number is reused
+    bindings.remove_pad(100);
+    assert_eq!(bindings.buttons(0)[0], 0);
+    assert_eq!(bindings.add_pad(300), 0);
+}
+
+#[test]
+fn test_turbo() {
+    let mut bindings = Bindings::parse(
+        "
+        turbo_a_rate = 15
+        turbo_b_rate = 30
+        p3.turbo_a = key:A
+        p3.turbo_b = key:B
+        ",
+    )
+    .unwrap();
+    bindings.keys.insert(Keycode::A);
+    bindings.keys.insert(Keycode::B);
+
+    let a: Vec<bool> = (0..8)
+        .map(|f| bindings.buttons(f)[2] & Button::A.mask() != 0)
+        .collect();
+    assert_eq!(a, vec![true, true, false, false, true, true, false, false]);
+
+    let b: Vec<bool> = (0..4)
+        .map(|f| bindings.buttons(f)[2] & Button::B.mask() != 0)
+        .collect();
+    assert_eq!(b, vec![true, false, true, false]);
+}
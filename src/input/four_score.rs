@@ -0,0 +1,57 @@
+// Reference: https://wiki.nesdev.com/w/index.php/Four_Score
+//
+// The Four Score (and NES Satellite) multiplexes four controllers onto the
+// two ports. Each port reports 24 bits: the buttons of the first controller,
+// then the second, then a signature identifying the port. Players 1 and 3
+// are on $4016, and players 2 and 4 are on $4017.
+
+use super::Controller;
+use crate::savestate::{self, State};
+
+// Signature bits, in report order.
+const SIGNATURES: [u32; 2] = [0b0000_1000, 0b0000_0100];
+
+#[derive(Clone, Default)]
+pub struct FourScore {
+    shift: [u32; 2],
+}
+
+impl FourScore {
+    pub fn new() -> FourScore {
+        FourScore::default()
+    }
+
+    // Reloads the shift registers from the current button state.
+    pub fn latch(&mut self, controllers: &[Controller; 4]) {
+        for (port, shift) in self.shift.iter_mut().enumerate() {
+            *shift = controllers[port].buttons() as u32
+                | (controllers[port + 2].buttons() as u32) << 8
+                | SIGNATURES[port] << 16;
+        }
+    }
+
+    // Returns the next report bit. Like the standard controller, reads past
+    // the end of the report return 1.
+    pub fn read(&mut self, port: usize) -> u8 {
+        let bit = (self.shift[port] & 1) as u8;
+        self.shift[port] = (self.shift[port] >> 1) | (1 << 23);
+        bit
+    }
+
+    // Returns the next report bit without shifting it out.
+    pub fn peek(&self, port: usize) -> u8 {
+        (self.shift[port] & 1) as u8
+    }
+}
+
+impl State for FourScore {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.u32(self.shift[0]);
+        w.u32(self.shift[1]);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.shift = [r.u32()?, r.u32()?];
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+// Reference: https://wiki.nesdev.com/w/index.php/Standard_controller
+//
+// Each controller port is read one bit at a time. Writing 1 to bit 0 of $4016
+// holds the strobe high, which continuously reloads the controllers' shift
+// registers with the current button state. Writing 0 latches that state, and
+// each subsequent read of $4016 (port 1) or $4017 (port 2) returns the next
+// button in bit 0.
+
+#[cfg(feature = "sdl")]
+pub mod bindings;
+mod four_score;
+mod zapper;
+
+pub use four_score::FourScore;
+pub use zapper::Zapper;
+
+use crate::ppu;
+use crate::savestate::{self, State};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    // Bit position within the controller report. Buttons are reported in
+    // declaration order, starting with A.
+    pub fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+pub const BUTTONS: [Button; 8] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+];
+
+#[derive(Clone, Default)]
+pub struct Controller {
+    // Buttons currently held, one bit per button (see Button::mask).
+    buttons: u8,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller::default()
+    }
+
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.buttons |= button.mask();
+        } else {
+            self.buttons &= !button.mask();
+        }
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        self.buttons & button.mask() != 0
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    pub fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    // Returns the next report bit. While the strobe is high, this is always
+    // the state of A. After all eight buttons have been read, official
+    // controllers return 1.
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 1;
+        }
+        let bit = self.shift & 1;
+        self.shift = (self.shift >> 1) | 0b1000_0000;
+        bit
+    }
+
+    // Returns the next report bit without shifting it out.
+    pub fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons & 1
+        } else {
+            self.shift & 1
+        }
+    }
+}
+
+// The two controller ports, as seen from the CPU. Controllers 3 and 4 are
+// only connected through a Four Score. If a Zapper is connected, it replaces
+// the device on port 2.
+#[derive(Clone, Default)]
+pub struct Input {
+    pub controllers: [Controller; 4],
+    pub zapper: Option<Zapper>,
+    pub four_score: Option<FourScore>,
+    strobe: bool,
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Input::default()
+    }
+
+    // Handles a write to $4016. Only the strobe bit is connected to the
+    // standard controllers.
+    pub fn write(&mut self, v: u8) {
+        self.strobe = v & 1 != 0;
+        for c in self.controllers.iter_mut() {
+            c.write_strobe(self.strobe);
+        }
+        if let Some(four_score) = self.four_score.as_mut() {
+            if self.strobe {
+                four_score.latch(&self.controllers);
+            }
+        }
+    }
+
+    // Handles a read from $4016 (port 0) or $4017 (port 1). The Zapper
+    // samples the picture being drawn by the PPU.
+    pub fn read(&mut self, port: usize, ppu: &ppu::Ppu, data_bus: u8) -> u8 {
+        if let (1, Some(zapper)) = (port, &self.zapper) {
+            return open_bus(data_bus, zapper.read(ppu));
+        }
+
+        match self.four_score.as_mut() {
+            Some(four_score) => {
+                if self.strobe {
+                    four_score.latch(&self.controllers);
+                }
+                open_bus(data_bus, four_score.read(port))
+            }
+            None => open_bus(data_bus, self.controllers[port].read()),
+        }
+    }
+
+    // Returns what a read from a port would, without shifting out a bit.
+    pub fn peek(&self, port: usize, ppu: &ppu::Ppu, data_bus: u8) -> u8 {
+        if let (1, Some(zapper)) = (port, &self.zapper) {
+            return open_bus(data_bus, zapper.read(ppu));
+        }
+
+        let bits = match &self.four_score {
+            // A read while strobing would latch the buttons first.
+            Some(_) if self.strobe => self.controllers[port].buttons() & 1,
+            Some(four_score) => four_score.peek(port),
+            None => self.controllers[port].peek(),
+        };
+        open_bus(data_bus, bits)
+    }
+}
+
+// The upper three bits of $4016/$4017 are not driven by the controller port,
+// and read back whatever was last on the data bus. This is usually the high
+// byte of the address, $40.
+fn open_bus(data_bus: u8, bits: u8) -> u8 {
+    (data_bus & 0b1110_0000) | bits
+}
+
+impl State for Controller {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.u8(self.buttons);
+        w.bool(self.strobe);
+        w.u8(self.shift);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.buttons = r.u8()?;
+        self.strobe = r.bool()?;
+        self.shift = r.u8()?;
+        Ok(())
+    }
+}
+
+// The Zapper's aim and trigger come from the host, so only the controllers
+// and the Four Score are saved. Which devices are connected comes from the
+// machine being loaded into.
+impl State for Input {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        for c in self.controllers.iter() {
+            c.save_state(w);
+        }
+        w.bool(self.strobe);
+        w.bool(self.four_score.is_some());
+        if let Some(four_score) = &self.four_score {
+            four_score.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        for c in self.controllers.iter_mut() {
+            c.load_state(r)?;
+        }
+        self.strobe = r.bool()?;
+        if r.bool()? {
+            let mut four_score = FourScore::new();
+            four_score.load_state(r)?;
+            if self.four_score.is_some() {
+                self.four_score = Some(four_score);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_controller_read() {
+    let mut c = Controller::new();
+    c.set(Button::A, true);
+    c.set(Button::Start, true);
+    c.set(Button::Left, true);
+
+    c.write_strobe(true);
+    c.write_strobe(false);
+
+    let bits: Vec<u8> = (0..8).map(|_| c.read()).collect();
+    assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 1, 0]);
+
+    // reads past the end of the report return 1
+    assert_eq!(c.read(), 1);
+    assert_eq!(c.read(), 1);
+
+    // state is latched, so later presses aren't visible until the next strobe
+    c.write_strobe(true);
+    c.write_strobe(false);
+    c.set(Button::B, true);
+    assert_eq!(c.read(), 1);
+    assert_eq!(c.read(), 0);
+}
+
+#[test]
+fn test_controller_strobe_held() {
+    let mut c = Controller::new();
+    c.write_strobe(true);
+    assert_eq!(c.read(), 0);
+
+    c.set(Button::A, true);
+    assert_eq!(c.read(), 1);
+    assert_eq!(c.read(), 1);
+
+    c.set(Button::A, false);
+    c.set(Button::B, true);
+    c.write_strobe(false);
+    assert_eq!(c.read(), 0);
+    assert_eq!(c.read(), 1);
+}
+
+#[test]
+fn test_input_ports() {
+    use crate::mapper;
+
+    let ppu = ppu::Ppu::new(Box::new(mapper::test::new().1));
+    let mut input = Input::new();
+    input.controllers[0].set(Button::A, true);
+    input.controllers[1].set(Button::B, true);
+
+    input.write(1);
+    input.write(0);
+
+    assert_eq!(input.peek(0, &ppu, 0x40), 0x41);
+    assert_eq!(input.read(0, &ppu, 0x40), 0x41);
+    assert_eq!(input.read(1, &ppu, 0x40), 0x40);
+    assert_eq!(input.peek(0, &ppu, 0x40), 0x40);
+    assert_eq!(input.read(0, &ppu, 0x40), 0x40);
+    assert_eq!(input.read(1, &ppu, 0x40), 0x41);
+
+    // the Zapper replaces the controller on port 2
+    input.zapper = Some(Zapper::new());
+    input.write(1);
+    input.write(0);
+    assert_eq!(input.read(0, &ppu, 0x40), 0x41);
+    assert_eq!(input.read(1, &ppu, 0x40), 0x48);
+
+    // the upper bits are whatever was last on the bus
+    assert_eq!(input.read(0, &ppu, 0xBF), 0xA0);
+    assert_eq!(input.peek(1, &ppu, 0xBF), 0xA8);
+}
+
+#[test]
+fn test_four_score() {
+    use crate::mapper;
+
+    let ppu = ppu::Ppu::new(Box::new(mapper::test::new().1));
+    let mut input = Input::new();
+    input.four_score = Some(FourScore::new());
+    input.controllers[0].set(Button::A, true);
+    input.controllers[1].set(Button::B, true);
+    input.controllers[2].set(Button::Start, true);
+    input.controllers[3].set(Button::Right, true);
+
+    input.write(1);
+    input.write(0);
+
+    let mut reports = [0u32; 2];
+    for i in 0..24 {
+        for (port, report) in reports.iter_mut().enumerate() {
+            let v = input.read(port, &ppu, 0x40);
+            assert_eq!(v & !1, 0x40);
+            *report |= ((v & 1) as u32) << i;
+        }
+    }
+
+    // player 1, player 3, signature
+    assert_eq!(reports[0], 0b0000_1000_0000_1000_0000_0001);
+    // player 2, player 4, signature
+    assert_eq!(reports[1], 0b0000_0100_1000_0000_0000_0010);
+
+    // reads past the end of the report return 1
+    assert_eq!(input.read(0, &ppu, 0x40), 0x40 | 1);
+    assert_eq!(input.read(1, &ppu, 0x40), 0x40 | 1);
+
+    // while the strobe is held, reads return player 1 and 2's A button
+    input.write(1);
+    assert_eq!(input.read(0, &ppu, 0x40), 0x40 | 1);
+    assert_eq!(input.read(0, &ppu, 0x40), 0x40 | 1);
+    assert_eq!(input.read(1, &ppu, 0x40), 0x40);
+}
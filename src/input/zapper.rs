@@ -0,0 +1,108 @@
+// Reference: https://wiki.nesdev.com/w/index.php/Zapper
+//
+// The Zapper reports two bits on its port: bit 3 is low while the
+// photodiode sees light, and bit 4 is high while the trigger is pulled.
+// Games detect a hit by drawing bright targets for a frame, and polling the
+// light sense bit while the beam passes the point the gun is aimed at.
+
+use crate::ppu;
+
+const LIGHT_SENSE_OFF: u8 = 0b0000_1000;
+const TRIGGER: u8 = 0b0001_0000;
+
+// The photodiode keeps reporting light for a while after the beam has passed
+// a bright pixel. This is the length of that window, in scanlines.
+const LIGHT_SCANLINES: usize = 20;
+
+// Pixels within this distance of the cursor, in each direction, are visible
+// to the photodiode.
+const LIGHT_RADIUS: usize = 2;
+
+// Minimum luma, out of 255, for a pixel to count as bright.
+const LIGHT_THRESHOLD: u32 = 0xC0;
+
+#[derive(Clone, Default)]
+pub struct Zapper {
+    // The screen pixel the gun is aimed at, or None when it points off
+    // screen.
+    pub position: Option<(usize, usize)>,
+    pub trigger: bool,
+}
+
+impl Zapper {
+    pub fn new() -> Zapper {
+        Zapper::default()
+    }
+
+    pub fn read(&self, ppu: &ppu::Ppu) -> u8 {
+        let mut v = 0;
+        if !self.light_sensed(ppu) {
+            v |= LIGHT_SENSE_OFF;
+        }
+        if self.trigger {
+            v |= TRIGGER;
+        }
+        v
+    }
+
+    // Returns whether the beam has recently drawn a bright pixel near the
+    // cursor. Only rows already drawn in the current frame are considered.
+    fn light_sensed(&self, ppu: &ppu::Ppu) -> bool {
+        let (x, y) = match self.position {
+            Some(pos) => pos,
+            None => return false,
+        };
+
+        let scanline = ppu.scanline();
+        if scanline <= y || scanline >= y + LIGHT_SCANLINES {
+            return false;
+        }
+
+        let rows = y.saturating_sub(LIGHT_RADIUS)..(y + LIGHT_RADIUS + 1).min(scanline);
+        let cols = x.saturating_sub(LIGHT_RADIUS)..(x + LIGHT_RADIUS + 1).min(ppu::SCREEN_WIDTH);
+        rows.filter(|&row| row < ppu::SCREEN_HEIGHT).any(|row| {
+            cols.clone().any(|col| {
+                let base = row * ppu::SCREEN_ROW_PITCH + 3 * col;
+                let rgb = &ppu.framebuf[base..base + 3];
+                let luma = (299 * rgb[0] as u32 + 587 * rgb[1] as u32 + 114 * rgb[2] as u32) / 1000;
+                luma >= LIGHT_THRESHOLD
+            })
+        })
+    }
+}
+
+#[test]
+fn test_zapper() {
+    use crate::mapper;
+
+    let mut ppu = ppu::Ppu::new(Box::new(mapper::test::new().1));
+    let mut zapper = Zapper::new();
+    assert_eq!(zapper.read(&ppu), LIGHT_SENSE_OFF);
+
+    zapper.trigger = true;
+    assert_eq!(zapper.read(&ppu), LIGHT_SENSE_OFF | TRIGGER);
+    zapper.trigger = false;
+
+    // draw a white tile at (96, 48) on a black background
+    ppu.mem_write_buf(0x10, vec![0xFF; 8]);
+    ppu.mem_write_buf(0x3F00, vec![0x0F, 0x30]);
+    ppu.mem_write(0x2000 + 6 * 32 + 12, 1);
+    ppu.write_register(1, 0b0000_1010);
+
+    zapper.position = Some((100, 50));
+    let mut lit = Vec::new();
+    while ppu.frame() == 0 {
+        if ppu.dot() == 0 && zapper.read(&ppu) & LIGHT_SENSE_OFF == 0 {
+            lit.push(ppu.scanline());
+        }
+        ppu.tick();
+    }
+    assert_eq!(lit, (51..50 + LIGHT_SCANLINES).collect::<Vec<usize>>());
+
+    // aiming at the background
+    zapper.position = Some((120, 50));
+    while ppu.frame() == 1 {
+        assert_eq!(zapper.read(&ppu), LIGHT_SENSE_OFF);
+        ppu.tick();
+    }
+}
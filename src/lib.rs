@@ -0,0 +1,21 @@
+extern crate regex;
+#[cfg(feature = "sdl")]
+extern crate sdl2;
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod apu;
+pub mod cpu;
+pub mod debugger;
+pub mod headless;
+pub mod ines;
+pub mod input;
+pub mod mapper;
+mod math;
+mod md5;
+pub mod movie;
+pub mod nsf;
+pub mod ppu;
+pub mod rewind;
+pub mod savestate;
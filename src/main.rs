@@ -1,22 +1,153 @@
-extern crate regex;
+extern crate nes;
 extern crate sdl2;
 
-#[macro_use]
-extern crate lazy_static;
-
+use nes::debugger::{Debugger, GdbStub, Resume};
+use nes::rewind::Rewind;
+use nes::{apu, cpu, ines, input, mapper, movie, nsf, ppu, savestate};
+use sdl2::controller::GameController;
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::PixelFormatEnum;
-use sdl2::render::TextureAccess;
+use sdl2::render::{Canvas, Texture, TextureAccess};
+use sdl2::video::Window;
+use sdl2::EventPump;
+use sdl2::GameControllerSubsystem;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
 use std::time::Duration;
 
-mod cpu;
-mod mapper;
-mod math;
-mod ppu;
+const USAGE: &str = "usage: nes [options] [file.nes | file.nsf]
+
+options:
+  --zapper          connect a Zapper to port 2, aimed with the mouse
+  --four-score      connect a Four Score, for players 3 and 4. It is connected
+                    automatically for games whose header asks for one.
+  --record FILE     record input from power-on to an FM2 movie
+  --play FILE       play back input from an FM2 movie
+  --trace FILE      log each CPU instruction to a file, in nestest.log format
+  --debug           start in the debugger. Pause breaks into it while running,
+                    and so does a CPU error when this is set.
+  --headless        run a ROM without a window, in the debugger
+  --gdb PORT        run a ROM without a window, debugged by a GDB client
+                    that connects to localhost on PORT
+  --rewind-interval FRAMES
+                    capture a state to rewind to every FRAMES frames
+                    (default 1)
+  --rewind-memory MB
+                    keep up to MB megabytes of states to rewind to, or 0 to
+                    turn rewinding off (default 64)
+
+keys:
+  F5                save the state to the current slot
+  F6                select the next slot, from 0 to 9
+  F7                load the state from the current slot
+  Backspace         rewind, while held
+  Pause             break into the debugger";
+
+#[derive(Default)]
+struct Options {
+    path: Option<String>,
+    zapper: bool,
+    four_score: bool,
+    record: Option<String>,
+    play: Option<String>,
+    trace: Option<String>,
+    debug: bool,
+    headless: bool,
+    gdb: Option<u16>,
+    rewind_interval: u32,
+
+    // In bytes.
+    rewind_memory: usize,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        rewind_interval: 1,
+        rewind_memory: 64 << 20,
+        ..Options::default()
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--zapper" => opts.zapper = true,
+            "--four-score" => opts.four_score = true,
+            "--debug" => opts.debug = true,
+            "--headless" => opts.headless = true,
+            "--record" | "--play" | "--trace" => {
+                let file = args
+                    .next()
+                    .ok_or_else(|| format!("{} requires a file", arg))?;
+                match arg.as_str() {
+                    "--record" => opts.record = Some(file.clone()),
+                    "--play" => opts.play = Some(file.clone()),
+                    _ => opts.trace = Some(file.clone()),
+                }
+            }
+            "--gdb" => {
+                let port = args.next().ok_or("--gdb requires a port")?;
+                let port = port.parse().map_err(|_| format!("invalid port {}", port))?;
+                opts.gdb = Some(port);
+            }
+            "--rewind-interval" | "--rewind-memory" => {
+                let n = args
+                    .next()
+                    .ok_or_else(|| format!("{} requires a number", arg))?;
+                let invalid = || format!("invalid {} {}", &arg[2..], n);
+                match arg.as_str() {
+                    "--rewind-interval" => match n.parse() {
+                        Ok(0) | Err(_) => return Err(invalid()),
+                        Ok(frames) => opts.rewind_interval = frames,
+                    },
+                    _ => {
+                        let mb: usize = n.parse().map_err(|_| invalid())?;
+                        opts.rewind_memory = mb.checked_mul(1 << 20).ok_or_else(invalid)?;
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => opts.path = Some(arg.clone()),
+        }
+    }
+    if opts.record.is_some() && opts.play.is_some() {
+        return Err("--record and --play can't be used together".to_string());
+    }
+    if opts.headless && (opts.record.is_some() || opts.play.is_some()) {
+        return Err("--headless can't be used with movies".to_string());
+    }
+    if opts.gdb.is_some() && (opts.headless || opts.record.is_some() || opts.play.is_some()) {
+        return Err("--gdb can't be used with --headless or movies".to_string());
+    }
+    Ok(opts)
+}
 
 pub fn main() {
-    let (_, mapper_ppu) = mapper::test::new();
-    let mut ppu = ppu::Ppu::new(Box::new(mapper_ppu));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    if opts.headless {
+        match &opts.path {
+            Some(path) => run_headless(path, &opts),
+            None => eprintln!("--headless requires a ROM\n{}", USAGE),
+        }
+        return;
+    }
+    if let Some(port) = opts.gdb {
+        match &opts.path {
+            Some(path) => run_gdb(path, port, &opts),
+            None => eprintln!("--gdb requires a ROM\n{}", USAGE),
+        }
+        return;
+    }
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -52,13 +183,45 @@ pub fn main() {
         .unwrap();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut host_input = HostInput::new(sdl_context.game_controller().unwrap(), opts.zapper, scale);
+
+    match &opts.path {
+        Some(path) if path.to_ascii_lowercase().ends_with(".nsf") => run_nsf(
+            path,
+            &mut canvas,
+            &mut texture,
+            &mut event_pump,
+            &mut host_input,
+        ),
+        Some(path) => run_rom(
+            path,
+            &opts,
+            &mut canvas,
+            &mut texture,
+            &mut event_pump,
+            &mut host_input,
+        ),
+        _ => run_demo(&mut canvas, &mut texture, &mut event_pump),
+    }
+}
+
+fn present(canvas: &mut Canvas<Window>, texture: &mut Texture, framebuf: &[u8]) {
+    texture
+        .update(None, framebuf, ppu::SCREEN_ROW_PITCH)
+        .unwrap();
+    canvas.copy(texture, None, None).unwrap();
+    canvas.present();
+}
+
+fn run_demo(canvas: &mut Canvas<Window>, texture: &mut Texture, event_pump: &mut EventPump) {
+    let (_, mapper_ppu) = mapper::test::new();
+    let mut ppu = ppu::Ppu::new(Box::new(mapper_ppu));
     let mut i = 0;
 
     loop {
         for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => return,
-                _ => {}
+            if let Event::Quit { .. } = event {
+                return;
             }
         }
 
@@ -70,18 +233,645 @@ pub fn main() {
         for i in 0..ppu::SCREEN_HEIGHT {
             for j in 0..ppu::SCREEN_WIDTH {
                 let base = i * ppu::SCREEN_ROW_PITCH + (3 * j);
-                ppu.framebuf[base + 0] = r;
+                ppu.framebuf[base] = r;
                 ppu.framebuf[base + 1] = g;
                 ppu.framebuf[base + 2] = b;
             }
         }
 
-        texture
-            .update(None, &ppu.framebuf, ppu::SCREEN_ROW_PITCH)
-            .unwrap();
-        canvas.copy(&texture, None, None).unwrap();
-        canvas.present();
+        present(canvas, texture, &ppu.framebuf[..]);
 
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
     }
 }
+
+const INPUT_CONFIG_PATH: &str = "nes-input.cfg";
+
+// Host input devices, and their bindings to NES controllers.
+struct HostInput {
+    bindings: input::bindings::Bindings,
+    controller_subsystem: GameControllerSubsystem,
+    pads: Vec<GameController>,
+
+    // Zapper state, driven by the mouse.
+    zapper: Option<input::Zapper>,
+    scale: u32,
+}
+
+impl HostInput {
+    // Loads bindings from the config file, or writes a default config if
+    // there isn't one.
+    fn new(controller_subsystem: GameControllerSubsystem, zapper: bool, scale: u32) -> HostInput {
+        let path = Path::new(INPUT_CONFIG_PATH);
+        let bindings = if path.exists() {
+            input::bindings::Bindings::load(path).unwrap_or_else(|e| {
+                eprintln!("{}: {}", INPUT_CONFIG_PATH, e);
+                input::bindings::Bindings::default()
+            })
+        } else {
+            let bindings = input::bindings::Bindings::default();
+            if let Err(e) = bindings.save(path) {
+                eprintln!("{}: {}", INPUT_CONFIG_PATH, e);
+            }
+            bindings
+        };
+
+        HostInput {
+            bindings,
+            controller_subsystem,
+            pads: Vec::new(),
+            zapper: if zapper {
+                Some(input::Zapper::new())
+            } else {
+                None
+            },
+            scale,
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.controller_subsystem.open(which) {
+                    Ok(pad) => {
+                        let n = self.bindings.add_pad(pad.instance_id());
+                        println!("pad{}: {}", n, pad.name());
+                        self.pads.push(pad);
+                    }
+                    Err(e) => eprintln!("could not open game controller {}: {}", which, e),
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.bindings.remove_pad(which);
+                self.pads.retain(|p| p.instance_id() != which);
+            }
+            Event::MouseMotion { x, y, .. } => {
+                if let Some(zapper) = self.zapper.as_mut() {
+                    let (x, y) = (x / self.scale as i32, y / self.scale as i32);
+                    zapper.position = if (0..ppu::SCREEN_WIDTH as i32).contains(&x)
+                        && (0..ppu::SCREEN_HEIGHT as i32).contains(&y)
+                    {
+                        Some((x as usize, y as usize))
+                    } else {
+                        None
+                    };
+                }
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                if let Some(zapper) = self.zapper.as_mut() {
+                    zapper.trigger = true;
+                }
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                if let Some(zapper) = self.zapper.as_mut() {
+                    zapper.trigger = false;
+                }
+            }
+            Event::Window {
+                win_event: WindowEvent::Leave,
+                ..
+            } => {
+                if let Some(zapper) = self.zapper.as_mut() {
+                    zapper.position = None;
+                }
+            }
+            _ => self.bindings.handle_event(event),
+        }
+    }
+
+    // Sets controller state for the given frame.
+    fn update(&self, input: &mut input::Input, frame: u64) {
+        let buttons = self.bindings.buttons(frame);
+        for (c, b) in input.controllers.iter_mut().zip(buttons.iter()) {
+            c.set_buttons(*b);
+        }
+        if let (Some(zapper), Some(state)) = (input.zapper.as_mut(), &self.zapper) {
+            zapper.position = state.position;
+            zapper.trigger = state.trigger;
+        }
+    }
+}
+
+// Runs an iNES ROM, optionally recording or playing back a movie.
+fn run_rom(
+    path: &str,
+    opts: &Options,
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+    event_pump: &mut EventPump,
+    host_input: &mut HostInput,
+) {
+    let rom = match load_rom(path) {
+        Some(rom) => rom,
+        None => return,
+    };
+
+    let mut playback = match &opts.play {
+        Some(movie_path) => match movie::Movie::load(Path::new(movie_path)) {
+            Ok(movie) => {
+                if movie.rom_checksum != movie::rom_checksum(&rom) {
+                    println!("{}: movie was recorded with a different ROM", movie_path);
+                }
+                Some(movie)
+            }
+            Err(e) => {
+                eprintln!("{}: {}", movie_path, e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let power_on = |playback: &Option<movie::Movie>| {
+        let (prg, chr) = rom.mapper().unwrap();
+        let mut cpu = cpu::Cpu::new(prg, chr);
+        match playback {
+            Some(movie) => movie.configure(&mut cpu.bus.input),
+            None => {
+                if opts.four_score || rom.expansion_device == ines::EXPANSION_FOUR_SCORE {
+                    cpu.bus.input.four_score = Some(input::FourScore::new());
+                }
+                if opts.zapper {
+                    cpu.bus.input.zapper = Some(input::Zapper::new());
+                }
+            }
+        }
+        cpu.reset();
+        cpu
+    };
+
+    let mut cpu = power_on(&playback);
+    if !start_trace(&mut cpu, opts) {
+        return;
+    }
+    let mut recording = opts
+        .record
+        .as_ref()
+        .map(|_| movie::Movie::new(path, &rom, &cpu.bus.input));
+
+    // frames since power-on
+    let mut frame: u64 = 0;
+
+    let mut debugger = Debugger::new();
+    let mut break_in = opts.debug;
+    let mut slot = 0;
+
+    let mut rewind = Rewind::new(opts.rewind_interval, opts.rewind_memory);
+    let mut rewinding = false;
+
+    'run: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'run,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Pause),
+                    ..
+                } => break_in = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => {
+                    let state_path = state_path(path, slot);
+                    match savestate::save_file(&cpu, Path::new(&state_path)) {
+                        Ok(()) => println!("saved state to slot {}", slot),
+                        Err(e) => eprintln!("{}: {}", state_path, e),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => {
+                    slot = (slot + 1) % STATE_SLOTS;
+                    println!("state slot {}", slot);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => {
+                    // Movies log input from power-on, so jumping elsewhere
+                    // would desync them.
+                    if playback.is_some() || recording.is_some() {
+                        println!("states can't be loaded while a movie is running");
+                        continue;
+                    }
+                    let state_path = state_path(path, slot);
+                    match savestate::load_file(&mut cpu, Path::new(&state_path)) {
+                        Ok(()) => println!("loaded state from slot {}", slot),
+                        Err(e) => eprintln!("{}: {}", state_path, e),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    repeat: false,
+                    ..
+                } => {
+                    if playback.is_some() || recording.is_some() {
+                        println!("rewinding isn't possible while a movie is running");
+                    } else if opts.rewind_memory == 0 {
+                        println!("rewinding is turned off");
+                    } else {
+                        rewinding = true;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                _ => host_input.handle_event(&event),
+            }
+        }
+
+        // The window doesn't update while the debugger has control.
+        if break_in {
+            break_in = false;
+            let stdin = std::io::stdin();
+            match debugger.repl(&mut cpu, &mut stdin.lock(), &mut std::io::stdout()) {
+                Ok(Resume::Continue) => {}
+                Ok(Resume::Quit) => break 'run,
+                Err(e) => {
+                    eprintln!("debugger: {}", e);
+                    break 'run;
+                }
+            }
+        }
+
+        // Each state rewound to is shown for a frame, with the picture it
+        // was saved with. Once the oldest is reached, the picture holds
+        // until the key is released.
+        if rewinding {
+            if let Some(state) = rewind.pop() {
+                if let Err(e) = savestate::load(&mut cpu, &state) {
+                    eprintln!("rewind: {}", e);
+                    rewind.clear();
+                }
+            }
+            present(canvas, texture, &cpu.bus.ppu.framebuf[..]);
+            continue;
+        }
+
+        let commands = match &playback {
+            Some(movie) => movie.apply(frame, &mut cpu.bus.input),
+            None => None,
+        };
+        match commands {
+            Some(commands) => {
+                if commands & movie::COMMAND_HARD_RESET != 0 {
+                    // the new console needs this frame's input too, and
+                    // keeps tracing to the same file
+                    let trace = cpu.trace.take();
+                    cpu = power_on(&playback);
+                    cpu.trace = trace;
+                    if let Some(movie) = &playback {
+                        movie.apply(frame, &mut cpu.bus.input);
+                    }
+                } else if commands & movie::COMMAND_SOFT_RESET != 0 {
+                    cpu.reset();
+                }
+            }
+            None => {
+                if playback.take().is_some() {
+                    println!("movie playback finished");
+                }
+                host_input.update(&mut cpu.bus.input, frame);
+            }
+        }
+        if let Some(movie) = recording.as_mut() {
+            movie.record(&cpu.bus.input);
+        }
+
+        let ppu_frame = cpu.bus.ppu.frame();
+        while cpu.bus.ppu.frame() == ppu_frame {
+            match debugger.step(&mut cpu) {
+                Ok(None) => {}
+                Ok(Some(hit)) => {
+                    println!("{}", hit);
+                    break_in = true;
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    if !opts.debug {
+                        break 'run;
+                    }
+                    break_in = true;
+                    break;
+                }
+            }
+        }
+        frame += 1;
+        if !rewinding && opts.rewind_memory != 0 && playback.is_none() && recording.is_none() {
+            rewind.frame(&cpu);
+        }
+
+        present(canvas, texture, &cpu.bus.ppu.framebuf[..]);
+    }
+
+    if let (Some(movie), Some(movie_path)) = (&recording, &opts.record) {
+        if let Err(e) = movie.save(Path::new(movie_path)) {
+            eprintln!("{}: {}", movie_path, e);
+        }
+    }
+}
+
+const STATE_SLOTS: usize = 10;
+
+// Save states are kept next to the ROM, one file per slot.
+fn state_path(rom_path: &str, slot: usize) -> String {
+    format!("{}.ss{}", rom_path, slot)
+}
+
+fn load_rom(path: &str) -> Option<ines::Rom> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return None;
+        }
+    };
+    let rom = match ines::parse(&bytes) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return None;
+        }
+    };
+    if let Err(e) = rom.mapper() {
+        eprintln!("{}: {}", path, e);
+        return None;
+    }
+    Some(rom)
+}
+
+// Starts the trace requested on the command line, if any. Returns false if
+// the trace file can't be created.
+fn start_trace(cpu: &mut cpu::Cpu, opts: &Options) -> bool {
+    if let Some(trace_path) = &opts.trace {
+        match cpu::Trace::file(Path::new(trace_path)) {
+            Ok(trace) => cpu.trace = Some(trace),
+            Err(e) => {
+                eprintln!("{}: {}", trace_path, e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// Runs an iNES ROM with no window, sound or input, under the debugger.
+// Continuing runs until the CPU stops.
+fn run_headless(path: &str, opts: &Options) {
+    let rom = match load_rom(path) {
+        Some(rom) => rom,
+        None => return,
+    };
+    let (prg, chr) = rom.mapper().unwrap();
+    let mut cpu = cpu::Cpu::new(prg, chr);
+    cpu.reset();
+    if !start_trace(&mut cpu, opts) {
+        return;
+    }
+
+    let mut debugger = Debugger::new();
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    loop {
+        let res = match debugger.repl(&mut cpu, &mut stdin.lock(), &mut stdout) {
+            Ok(Resume::Continue) => debugger.run(&mut cpu, &mut stdout),
+            Ok(Resume::Quit) => return,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            eprintln!("debugger: {}", e);
+            return;
+        }
+    }
+}
+
+// Runs an iNES ROM with no window, sound or input, for a GDB client.
+fn run_gdb(path: &str, port: u16, opts: &Options) {
+    let rom = match load_rom(path) {
+        Some(rom) => rom,
+        None => return,
+    };
+    let (prg, chr) = rom.mapper().unwrap();
+    let mut cpu = cpu::Cpu::new(prg, chr);
+    cpu.reset();
+    if !start_trace(&mut cpu, opts) {
+        return;
+    }
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("gdb: {}", e);
+            return;
+        }
+    };
+    eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+    let res = listener
+        .accept()
+        .and_then(|(stream, _)| GdbStub::new().serve(&mut cpu, stream));
+    if let Err(e) = res {
+        eprintln!("gdb: {}", e);
+    }
+}
+
+const NSF_CHANNEL_COLORS: [[u8; 3]; 5] = [
+    [0xE0, 0x40, 0x40], // pulse 1
+    [0xE0, 0xA0, 0x40], // pulse 2
+    [0x40, 0xC0, 0x60], // triangle
+    [0x40, 0x80, 0xE0], // noise
+    [0xA0, 0x60, 0xE0], // dmc
+];
+
+// Plays an NSF file. Left and right select the previous and next song, and
+// return restarts the current one.
+fn run_nsf(
+    path: &str,
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+    event_pump: &mut EventPump,
+    host_input: &mut HostInput,
+) {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return;
+        }
+    };
+    let nsf = match nsf::parse(&bytes) {
+        Ok(nsf) => nsf,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return;
+        }
+    };
+
+    let header = &nsf.header;
+    println!("{}", header.song_name);
+    println!("{}", header.artist);
+    println!("{}", header.copyright);
+    if header.extra_sound_chips != 0 {
+        println!(
+            "expansion audio is not supported (chips: {:#010b})",
+            header.extra_sound_chips
+        );
+    }
+
+    let mut player = match nsf::Player::new(&nsf) {
+        Ok(player) => player,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return;
+        }
+    };
+    let cpu_hz = header.region.cpu_hz();
+    let mut framebuf = vec![0; ppu::FRAMEBUFFER_BYTES];
+    let mut target_cycles = player.cpu.cycles;
+    let mut frame: u64 = 0;
+    print_nsf_song(&player);
+
+    loop {
+        for event in event_pump.poll_iter() {
+            host_input.handle_event(&event);
+
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    println!();
+                    return;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    let total = header.total_songs;
+                    let song = match keycode {
+                        Keycode::Left => (player.song() + total - 1) % total,
+                        Keycode::Right => (player.song() + 1) % total,
+                        Keycode::Return => player.song(),
+                        _ => continue,
+                    };
+                    if let Err(e) = player.start(song) {
+                        eprintln!("{}: {}", path, e);
+                        return;
+                    }
+                    target_cycles = player.cpu.cycles;
+                    print_nsf_song(&player);
+                }
+                _ => {}
+            }
+        }
+
+        host_input.update(&mut player.cpu.bus.input, frame);
+        target_cycles += cpu_hz / 60;
+        while player.cpu.cycles < target_cycles {
+            if let Err(e) = player.play() {
+                eprintln!("{}: {}", path, e);
+                return;
+            }
+        }
+
+        let status = player.channel_status();
+        draw_nsf_channels(&mut framebuf, &status, cpu_hz);
+        present(canvas, texture, &framebuf);
+
+        frame += 1;
+        if frame.is_multiple_of(10) {
+            print_nsf_channels(&status, cpu_hz);
+        }
+    }
+}
+
+fn print_nsf_song(player: &nsf::Player) {
+    println!(
+        "\nsong {}/{}",
+        player.song() + 1,
+        player.header().total_songs
+    );
+}
+
+// Returns the frequency of a tonal channel in Hz.
+fn channel_hz(status: &apu::ChannelStatus, cpu_hz: u64) -> Option<f64> {
+    let divisor = match status.channel {
+        apu::Channel::Pulse1 | apu::Channel::Pulse2 => 16,
+        apu::Channel::Triangle => 32,
+        _ => return None,
+    };
+    Some(cpu_hz as f64 / (divisor * (status.period as u64 + 1)) as f64)
+}
+
+fn print_nsf_channels(status: &[apu::ChannelStatus], cpu_hz: u64) {
+    let cols: Vec<String> = status
+        .iter()
+        .map(|s| {
+            if !s.active {
+                return format!("{:>8}: {:>12}", s.channel.name(), "-");
+            }
+            match channel_hz(s, cpu_hz) {
+                Some(hz) => format!("{:>8}: {:>6.0}Hz v{:<2}", s.channel.name(), hz, s.volume),
+                None => format!("{:>8}: {:>6} v{:<2}", s.channel.name(), s.period, s.volume),
+            }
+        })
+        .collect();
+    print!("\r{}", cols.join(" |"));
+    std::io::stdout().flush().unwrap();
+}
+
+// Draws one horizontal band per channel. The bar length shows the volume, and
+// the marker shows the pitch on a log scale from A0 to C8.
+fn draw_nsf_channels(framebuf: &mut [u8], status: &[apu::ChannelStatus], cpu_hz: u64) {
+    for v in framebuf.iter_mut() {
+        *v = 0x10;
+    }
+
+    let band = ppu::SCREEN_HEIGHT / status.len();
+    for (i, s) in status.iter().enumerate() {
+        let color = NSF_CHANNEL_COLORS[i];
+        let dim = [color[0] / 4, color[1] / 4, color[2] / 4];
+        let y = i * band + 4;
+        let h = band - 8;
+
+        fill_rect(framebuf, 0, y, ppu::SCREEN_WIDTH, h, dim);
+        if !s.active {
+            continue;
+        }
+
+        let w = ppu::SCREEN_WIDTH * s.volume as usize / 15;
+        fill_rect(framebuf, 0, y, w, h, color);
+
+        let pitch = match channel_hz(s, cpu_hz) {
+            Some(hz) => (hz / 27.5).ln() / (4186.0f64 / 27.5).ln(),
+            None => (15 - s.period.min(15)) as f64 / 15.0,
+        };
+        let x = (pitch.clamp(0.0, 1.0) * (ppu::SCREEN_WIDTH - 2) as f64) as usize;
+        fill_rect(framebuf, x, y, 2, h, [0xFF, 0xFF, 0xFF]);
+    }
+}
+
+fn fill_rect(framebuf: &mut [u8], x: usize, y: usize, w: usize, h: usize, color: [u8; 3]) {
+    for row in y..(y + h).min(ppu::SCREEN_HEIGHT) {
+        for col in x..(x + w).min(ppu::SCREEN_WIDTH) {
+            let base = row * ppu::SCREEN_ROW_PITCH + 3 * col;
+            framebuf[base..base + 3].copy_from_slice(&color);
+        }
+    }
+}
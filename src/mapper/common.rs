@@ -1,12 +1,70 @@
+use crate::savestate::State;
+
 // A mapper models a cartridge, which contains the following:
 // - extensions to the CPU memory map, including executable program data
 // - extensions to the PPU memory map
-pub trait Prg {
-    fn read(&self, addr: u16) -> u8;
-    fn write(&mut self, addr: u16, v: u8);
+//
+// The Prg half returns None from read(), and false from write(), for
+// addresses that the cartridge does not respond to.
+//
+// peek() reads without triggering any mapper logic, for debuggers. Mappers
+// whose reads have side effects, like MMC2's CHR latches, must override it.
+//
+// Both halves save their RAM and bank registers in save states. ROM comes
+// from the cartridge, so it isn't saved.
+pub trait Prg: State {
+    fn read(&self, addr: u16) -> Option<u8>;
+    fn write(&mut self, addr: u16, v: u8) -> bool;
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.read(addr)
+    }
 }
 
-pub trait Ppu {
+pub trait Ppu: State {
     fn read(&self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, v: u8);
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+impl Mirroring {
+    // Returns the offset into nametable memory for an address in
+    // $2000-$2FFF. Four-screen mirroring requires 4 KiB of nametable memory,
+    // and the others require 2 KiB.
+    pub fn nametable_offset(self, addr: u16) -> usize {
+        let addr = (addr as usize - 0x2000) % 0x1000;
+        let (table, offset) = (addr / 0x400, addr % 0x400);
+        let table = match self {
+            Self::Horizontal => table / 2,
+            Self::Vertical => table % 2,
+            Self::FourScreen => table,
+        };
+        table * 0x400 + offset
+    }
+}
+
+#[test]
+fn test_nametable_offset() {
+    assert_eq!(Mirroring::Horizontal.nametable_offset(0x2000), 0);
+    assert_eq!(Mirroring::Horizontal.nametable_offset(0x2401), 1);
+    assert_eq!(Mirroring::Horizontal.nametable_offset(0x2802), 0x402);
+    assert_eq!(Mirroring::Horizontal.nametable_offset(0x2C03), 0x403);
+
+    assert_eq!(Mirroring::Vertical.nametable_offset(0x2000), 0);
+    assert_eq!(Mirroring::Vertical.nametable_offset(0x2401), 0x401);
+    assert_eq!(Mirroring::Vertical.nametable_offset(0x2802), 2);
+    assert_eq!(Mirroring::Vertical.nametable_offset(0x2C03), 0x403);
+
+    assert_eq!(Mirroring::FourScreen.nametable_offset(0x2C03), 0xC03);
 }
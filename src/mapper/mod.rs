@@ -1,6 +1,11 @@
 mod common;
-pub mod nrom128;
+pub mod nrom;
+pub mod nsf;
 pub mod test;
 
+pub use common::Mirroring;
 pub use common::Ppu;
 pub use common::Prg;
+
+// The CPU- and PPU-facing halves of a cartridge.
+pub type Cartridge = (Box<dyn Prg>, Box<dyn Ppu>);
@@ -0,0 +1,166 @@
+// http://wiki.nesdev.com/w/index.php/NROM
+//
+// NROM-128 has 16 KiB of PRG ROM, mirrored at $8000 and $C000, and NROM-256
+// has 32 KiB. Boards without CHR ROM have 8 KiB of CHR RAM instead.
+
+use super::common;
+use super::common::Mirroring;
+use crate::savestate::{self, State};
+
+pub const PRG_SIZE: usize = 1 << 14;
+pub const CHR_SIZE: usize = 1 << 13;
+const PRG_RAM_SIZE: usize = 1 << 13;
+
+pub fn new(prg: &[u8], chr: &[u8], mirroring: Mirroring) -> (Prg, Ppu) {
+    let chr_ram = chr.is_empty();
+    let chr = if chr_ram {
+        vec![0; CHR_SIZE]
+    } else {
+        chr.to_vec()
+    };
+    let nametable_size = match mirroring {
+        Mirroring::FourScreen => 0x1000,
+        _ => 0x800,
+    };
+
+    (
+        Prg {
+            rom: prg.to_vec(),
+            ram: vec![0; PRG_RAM_SIZE],
+        },
+        Ppu {
+            chr,
+            chr_ram,
+            nametables: vec![0; nametable_size],
+            mirroring,
+        },
+    )
+}
+
+pub struct Prg {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl common::Prg for Prg {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => Some(self.ram[addr as usize - 0x6000]),
+            0x8000..=0xFFFF => Some(self.rom[(addr as usize - 0x8000) % self.rom.len()]),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, v: u8) -> bool {
+        match addr {
+            0x6000..=0x7FFF => self.ram[addr as usize - 0x6000] = v,
+            0x8000..=0xFFFF => {} // ROM
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl State for Prg {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        r.bytes_into(&mut self.ram, "PRG RAM")
+    }
+}
+
+pub struct Ppu {
+    chr: Vec<u8>,
+    chr_ram: bool,
+    nametables: Vec<u8>,
+    mirroring: Mirroring,
+}
+
+impl common::Ppu for Ppu {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr[addr as usize],
+            0x2000..=0x2FFF => self.nametables[self.mirroring.nametable_offset(addr)],
+            _ => panic!("invalid address: {}", addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                if self.chr_ram {
+                    self.chr[addr as usize] = v;
+                }
+            }
+            0x2000..=0x2FFF => {
+                let offset = self.mirroring.nametable_offset(addr);
+                self.nametables[offset] = v;
+            }
+            _ => panic!("invalid address: {}", addr),
+        }
+    }
+}
+
+// CHR ROM is left out, but CHR RAM is saved.
+impl State for Ppu {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(if self.chr_ram { &self.chr } else { &[] });
+        w.bytes(&self.nametables);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        if self.chr_ram {
+            r.bytes_into(&mut self.chr, "CHR RAM")?;
+        } else {
+            r.bytes_into(&mut [], "CHR RAM")?;
+        }
+        r.bytes_into(&mut self.nametables, "nametables")
+    }
+}
+
+#[test]
+fn test_prg() {
+    use common::Prg;
+
+    let mut rom = vec![0; PRG_SIZE];
+    rom[0] = 1;
+    rom[PRG_SIZE - 1] = 2;
+    let (mut prg, _) = new(&rom, &[], Mirroring::Horizontal);
+    assert_eq!(prg.read(0x8000), Some(1));
+    assert_eq!(prg.read(0xBFFF), Some(2));
+    assert_eq!(prg.read(0xC000), Some(1));
+    assert_eq!(prg.read(0xFFFF), Some(2));
+
+    prg.write(0x8000, 3);
+    assert_eq!(prg.read(0x8000), Some(1));
+    prg.write(0x6000, 3);
+    assert_eq!(prg.read(0x6000), Some(3));
+
+    // nothing mapped below PRG RAM
+    assert_eq!(prg.read(0x5000), None);
+    assert!(!prg.write(0x5000, 3));
+
+    let mut rom = vec![0; 2 * PRG_SIZE];
+    rom[PRG_SIZE] = 1;
+    let (prg, _) = new(&rom, &[], Mirroring::Horizontal);
+    assert_eq!(prg.read(0x8000), Some(0));
+    assert_eq!(prg.read(0xC000), Some(1));
+}
+
+#[test]
+fn test_ppu() {
+    use common::Ppu;
+
+    let (_, mut ppu) = new(&[0; PRG_SIZE], &[1; CHR_SIZE], Mirroring::Vertical);
+    ppu.write(0x0000, 2);
+    assert_eq!(ppu.read(0x0000), 1);
+    ppu.write(0x2000, 3);
+    assert_eq!(ppu.read(0x2800), 3);
+
+    // CHR RAM
+    let (_, mut ppu) = new(&[0; PRG_SIZE], &[], Mirroring::Vertical);
+    ppu.write(0x1FFF, 2);
+    assert_eq!(ppu.read(0x1FFF), 2);
+}
@@ -1,38 +0,0 @@
-// http://wiki.nesdev.com/w/index.php/NROM
-
-use super::common;
-
-pub const PRG_SIZE: usize = 1 << 13;
-pub const CHR_SIZE: usize = 1 << 12;
-
-pub fn new(prg: &Vec<u8>, chr: &Vec<u8>) -> (Prg, Ppu) {
-    (Prg(prg.clone()), Ppu(chr.clone()))
-}
-
-pub struct Prg(Vec<u8>);
-
-impl common::Prg for Prg {
-    fn read(&self, addr: u16) -> u8 {
-        match addr {
-            0x8000..=0xBFFF => (*self.0)[addr as usize - 0x8000],
-            0xC000..=0xFFFF => (*self.0)[addr as usize - 0xC000],
-            _ => panic!("invalid address: {}", addr),
-        }
-    }
-
-    fn write(&mut self, addr: u16, v: u8) {
-        panic!("not writeable");
-    }
-}
-
-pub struct Ppu(Vec<u8>);
-
-impl common::Ppu for Ppu {
-    fn read(&self, addr: u16) -> u8 {
-        return (*self.0)[addr as usize];
-    }
-
-    fn write(&mut self, addr: u16, v: u8) {
-        panic!("not writeable");
-    }
-}
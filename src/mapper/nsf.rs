@@ -0,0 +1,173 @@
+// https://wiki.nesdev.com/w/index.php/NSF#Bank_switching
+//
+// NSF program data is mapped into $8000-$FFFF as eight 4 KiB slots. If the
+// file specifies initial bank values, each slot can be switched by writing a
+// bank number to $5FF8-$5FFF. Otherwise, the data is mapped linearly starting
+// at the load address. $6000-$7FFF is work RAM.
+
+use super::common;
+use crate::savestate::{self, State};
+
+pub const BANK_SIZE: usize = 1 << 12;
+pub const RAM_SIZE: usize = 1 << 13;
+const SLOTS: usize = 8;
+
+pub fn new(load_addr: u16, data: &[u8], banks: Option<[u8; SLOTS]>) -> (Prg, Ppu) {
+    let prg = match banks {
+        Some(banks) => {
+            // In bankswitched mode, only the low 12 bits of the load address
+            // are used, as padding before the start of the first bank.
+            let padding = (load_addr as usize) & (BANK_SIZE - 1);
+            let len = padding + data.len();
+            let num_banks = len.div_ceil(BANK_SIZE).max(1);
+            let mut rom = vec![0; num_banks * BANK_SIZE];
+            rom[padding..len].copy_from_slice(data);
+            Prg {
+                rom,
+                ram: vec![0; RAM_SIZE],
+                banks,
+                bankswitched: true,
+            }
+        }
+        None => {
+            let mut rom = vec![0; SLOTS * BANK_SIZE];
+            let start = load_addr as usize - 0x8000;
+            let end = (start + data.len()).min(rom.len());
+            rom[start..end].copy_from_slice(&data[..end - start]);
+            Prg {
+                rom,
+                ram: vec![0; RAM_SIZE],
+                banks: [0, 1, 2, 3, 4, 5, 6, 7],
+                bankswitched: false,
+            }
+        }
+    };
+
+    (prg, Ppu(vec![0; 0x3000]))
+}
+
+pub struct Prg {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    banks: [u8; SLOTS],
+    bankswitched: bool,
+}
+
+impl Prg {
+    fn rom_offset(&self, addr: u16) -> usize {
+        let slot = (addr as usize - 0x8000) / BANK_SIZE;
+        let bank = self.banks[slot] as usize % (self.rom.len() / BANK_SIZE);
+        bank * BANK_SIZE + (addr as usize % BANK_SIZE)
+    }
+}
+
+impl common::Prg for Prg {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => Some(self.ram[addr as usize - 0x6000]),
+            0x8000..=0xFFFF => Some(self.rom[self.rom_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, v: u8) -> bool {
+        match addr {
+            0x5FF8..=0x5FFF => {
+                if self.bankswitched {
+                    self.banks[addr as usize - 0x5FF8] = v;
+                }
+            }
+            0x6000..=0x7FFF => self.ram[addr as usize - 0x6000] = v,
+            0x8000..=0xFFFF => {} // ROM
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl State for Prg {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(&self.ram);
+        w.bytes(&self.banks);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        r.bytes_into(&mut self.ram, "PRG RAM")?;
+        r.bytes_into(&mut self.banks, "NSF banks")
+    }
+}
+
+// NSF files have no CHR data. This is enough memory for pattern data and
+// nametables, so that the PPU remains usable.
+pub struct Ppu(Vec<u8>);
+
+impl common::Ppu for Ppu {
+    fn read(&self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, v: u8) {
+        self.0[addr as usize] = v;
+    }
+}
+
+impl State for Ppu {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(&self.0);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        r.bytes_into(&mut self.0, "PPU memory")
+    }
+}
+
+#[test]
+fn test_linear() {
+    use common::Prg;
+
+    let (prg, _) = new(0x8100, &[1, 2, 3], None);
+    assert_eq!(prg.read(0x8000), Some(0));
+    assert_eq!(prg.read(0x8100), Some(1));
+    assert_eq!(prg.read(0x8102), Some(3));
+    assert_eq!(prg.read(0xFFFF), Some(0));
+}
+
+#[test]
+fn test_bankswitch() {
+    use common::Prg;
+
+    // three banks of data, with 0x100 bytes of padding
+    let mut data = vec![0; 3 * BANK_SIZE - 0x100];
+    data[0] = 0xA0;
+    data[BANK_SIZE - 0x100] = 0xA1;
+    data[2 * BANK_SIZE - 0x100] = 0xA2;
+
+    let (mut prg, _) = new(0x8100, &data, Some([0, 1, 2, 0, 0, 0, 0, 2]));
+    assert_eq!(prg.read(0x8100), Some(0xA0));
+    assert_eq!(prg.read(0x9000), Some(0xA1));
+    assert_eq!(prg.read(0xA000), Some(0xA2));
+    assert_eq!(prg.read(0xF000), Some(0xA2));
+
+    prg.write(0x5FF8, 2);
+    assert_eq!(prg.read(0x8000), Some(0xA2));
+
+    // out-of-range banks wrap
+    prg.write(0x5FF9, 4);
+    assert_eq!(prg.read(0x9000), Some(0xA1));
+
+    // bank registers are ignored in linear mode
+    let (mut prg, _) = new(0x8100, &data, None);
+    prg.write(0x5FF8, 2);
+    assert_eq!(prg.read(0x8100), Some(0xA0));
+}
+
+#[test]
+fn test_ram() {
+    use common::Prg;
+
+    let (mut prg, _) = new(0x8000, &[], None);
+    prg.write(0x6000, 1);
+    prg.write(0x7FFF, 2);
+    assert_eq!(prg.read(0x6000), Some(1));
+    assert_eq!(prg.read(0x7FFF), Some(2));
+}
@@ -1,4 +1,5 @@
 use super::common;
+use crate::savestate::{self, State};
 
 pub fn new() -> (Prg, Ppu) {
     (
@@ -10,18 +11,30 @@ pub fn new() -> (Prg, Ppu) {
 pub struct Prg(Vec<u8>);
 
 impl common::Prg for Prg {
-    fn read(&self, addr: u16) -> u8 {
+    fn read(&self, addr: u16) -> Option<u8> {
         match addr {
-            0x8000..=0xFFFF => (*self.0)[addr as usize - 0x8000],
-            _ => panic!("invalid address: {}", addr),
+            0x8000..=0xFFFF => Some((*self.0)[addr as usize - 0x8000]),
+            _ => None,
         }
     }
 
-    fn write(&mut self, addr: u16, v: u8) {
+    fn write(&mut self, addr: u16, v: u8) -> bool {
         match addr {
             0x8000..=0xFFFF => (*self.0)[addr as usize - 0x8000] = v,
-            _ => panic!("invalid address: {}", addr),
+            _ => return false,
         }
+        true
+    }
+}
+
+// PRG is writable here, so it's all saved.
+impl State for Prg {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(&self.0);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        r.bytes_into(&mut self.0, "PRG")
     }
 }
 
@@ -29,10 +42,20 @@ pub struct Ppu(Vec<u8>);
 
 impl common::Ppu for Ppu {
     fn read(&self, addr: u16) -> u8 {
-        return (*self.0)[addr as usize];
+        (*self.0)[addr as usize]
     }
 
     fn write(&mut self, addr: u16, v: u8) {
         (*self.0)[addr as usize] = v;
     }
 }
+
+impl State for Ppu {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.bytes(&self.0);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        r.bytes_into(&mut self.0, "PPU memory")
+    }
+}
@@ -0,0 +1,582 @@
+// Reference: http://fceux.com/web/help/fm2.html
+//
+// A movie is a log of controller state for each frame since power-on.
+// Replaying it drives the emulator through exactly the same inputs, which
+// makes bug reports reproducible and lets tests script a ROM's input.
+//
+// Movies are stored in FCEUX's text format. Input is applied at the start
+// of each frame, so movies recorded by other emulators may desync if their
+// frame or input timing differs from ours.
+
+use crate::ines;
+use crate::input;
+use crate::md5;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const VERSION: u32 = 3;
+const EMU_VERSION: u32 = 22020;
+
+// Frame commands
+pub const COMMAND_SOFT_RESET: u8 = 1 << 0;
+pub const COMMAND_HARD_RESET: u8 = 1 << 1;
+
+// Gamepad fields list buttons from the high bit of Controller::buttons() to
+// the low bit. A space or '.' means the button is released.
+const GAMEPAD_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+// Zapper positions with a Y coordinate outside of the screen mean the gun
+// is pointed off screen.
+const ZAPPER_OFF_SCREEN: usize = 255;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidHeader(usize, String),
+    InvalidFrame(usize, String),
+    UnsupportedVersion(String),
+    UnsupportedBinary,
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::InvalidHeader(line, src) => write!(f, "line {}: invalid header: {}", line, src),
+            Self::InvalidFrame(line, src) => write!(f, "line {}: invalid frame: {}", line, src),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported movie version: {}", v),
+            Self::UnsupportedBinary => write!(f, "binary movies are not supported"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+// The device plugged into a controller port.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Port {
+    None,
+    Gamepad,
+    Zapper,
+}
+
+impl Port {
+    fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Gamepad => 1,
+            Self::Zapper => 2,
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Port> {
+        match id {
+            "0" => Some(Port::None),
+            "1" => Some(Port::Gamepad),
+            "2" => Some(Port::Zapper),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Frame {
+    pub commands: u8,
+    pub controllers: [u8; 4],
+    pub zapper_position: Option<(usize, usize)>,
+    pub zapper_trigger: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub rom_checksum: [u8; 16],
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub four_score: bool,
+    pub ports: [Port; 2],
+    pub comments: Vec<String>,
+    pub frames: Vec<Frame>,
+}
+
+// Returns the checksum FCEUX uses to identify a ROM: the MD5 digest of its
+// PRG and CHR data.
+pub fn rom_checksum(rom: &ines::Rom) -> [u8; 16] {
+    let mut data = rom.prg.clone();
+    data.extend_from_slice(&rom.chr);
+    md5::digest(&data)
+}
+
+impl Movie {
+    // Creates an empty movie for the ROM at the given path, with ports
+    // matching the devices connected to the input. FM2 names the ROM by its
+    // file name, without the directory or extension.
+    pub fn new(rom_path: &str, rom: &ines::Rom, input: &input::Input) -> Movie {
+        let rom_filename = Path::new(rom_path)
+            .file_stem()
+            .map_or(rom_path.into(), |stem| stem.to_string_lossy());
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let seed = md5::digest(format!("{}{}", rom_filename, nanos).as_bytes());
+        let hex: String = seed.iter().map(|b| format!("{:02X}", b)).collect();
+        let guid = format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32]
+        );
+
+        Movie {
+            rom_filename: rom_filename.into_owned(),
+            rom_checksum: rom_checksum(rom),
+            guid,
+            rerecord_count: 0,
+            four_score: input.four_score.is_some(),
+            ports: [
+                Port::Gamepad,
+                if input.zapper.is_some() {
+                    Port::Zapper
+                } else {
+                    Port::Gamepad
+                },
+            ],
+            comments: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    // Connects the devices the movie was recorded with.
+    pub fn configure(&self, input: &mut input::Input) {
+        input.four_score = if self.four_score {
+            Some(input::FourScore::new())
+        } else {
+            None
+        };
+        input.zapper = if self.ports[1] == Port::Zapper {
+            Some(input::Zapper::new())
+        } else {
+            None
+        };
+    }
+
+    // Appends the current input state as the next frame.
+    pub fn record(&mut self, input: &input::Input) {
+        let mut frame = Frame::default();
+        for (i, c) in input.controllers.iter().enumerate() {
+            frame.controllers[i] = c.buttons();
+        }
+        if let Some(zapper) = &input.zapper {
+            frame.zapper_position = zapper.position;
+            frame.zapper_trigger = zapper.trigger;
+        }
+        self.frames.push(frame);
+    }
+
+    // Sets input state for the given frame, and returns the frame's
+    // commands. Returns None once the movie has ended.
+    pub fn apply(&self, frame: u64, input: &mut input::Input) -> Option<u8> {
+        let f = self.frames.get(frame as usize)?;
+        for (c, buttons) in input.controllers.iter_mut().zip(f.controllers.iter()) {
+            c.set_buttons(*buttons);
+        }
+        if let Some(zapper) = input.zapper.as_mut() {
+            zapper.position = f.zapper_position;
+            zapper.trigger = f.zapper_trigger;
+        }
+        Some(f.commands)
+    }
+
+    pub fn parse(src: &str) -> Result<Movie, Error> {
+        let mut movie = Movie {
+            rom_filename: String::new(),
+            rom_checksum: [0; 16],
+            guid: String::new(),
+            rerecord_count: 0,
+            four_score: false,
+            ports: [Port::Gamepad, Port::Gamepad],
+            comments: Vec::new(),
+            frames: Vec::new(),
+        };
+        let mut version = None;
+
+        for (i, line) in src.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('|') {
+                let frame = movie
+                    .parse_frame(line)
+                    .ok_or_else(|| Error::InvalidFrame(line_num, line.to_string()))?;
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, val) = match line.split_once(' ') {
+                Some((key, val)) => (key, val),
+                None => (line, ""),
+            };
+            let invalid = || Error::InvalidHeader(line_num, line.to_string());
+            match key {
+                "version" => version = Some(val.to_string()),
+                "binary" if val != "0" => return Err(Error::UnsupportedBinary),
+                "rerecordCount" => movie.rerecord_count = val.parse().map_err(|_| invalid())?,
+                "romFilename" => movie.rom_filename = val.to_string(),
+                "romChecksum" => {
+                    let bytes = val
+                        .strip_prefix("base64:")
+                        .and_then(base64_decode)
+                        .filter(|b| b.len() == 16)
+                        .ok_or_else(invalid)?;
+                    movie.rom_checksum.copy_from_slice(&bytes);
+                }
+                "guid" => movie.guid = val.to_string(),
+                "fourscore" => movie.four_score = val == "1",
+                "port0" => movie.ports[0] = Port::from_id(val).ok_or_else(invalid)?,
+                "port1" => movie.ports[1] = Port::from_id(val).ok_or_else(invalid)?,
+                "comment" => movie.comments.push(val.to_string()),
+                _ => {} // settings that don't apply to this emulator
+            }
+        }
+
+        match version {
+            Some(v) if v == VERSION.to_string() => Ok(movie),
+            Some(v) => Err(Error::UnsupportedVersion(v)),
+            None => Err(Error::UnsupportedVersion("none".to_string())),
+        }
+    }
+
+    fn parse_frame(&self, line: &str) -> Option<Frame> {
+        let fields: Vec<&str> = line.strip_prefix('|')?.split('|').collect();
+        let mut frame = Frame {
+            commands: fields.first()?.trim().parse().ok()?,
+            ..Frame::default()
+        };
+
+        if self.four_score {
+            for (i, c) in frame.controllers.iter_mut().enumerate() {
+                *c = parse_gamepad(fields.get(i + 1)?)?;
+            }
+            return Some(frame);
+        }
+
+        for (i, port) in self.ports.iter().enumerate() {
+            let field = fields.get(i + 1)?;
+            match port {
+                Port::None => {}
+                Port::Gamepad => frame.controllers[i] = parse_gamepad(field)?,
+                Port::Zapper => {
+                    let nums: Vec<usize> = field
+                        .split_whitespace()
+                        .map(|n| n.parse().ok())
+                        .collect::<Option<_>>()?;
+                    let (x, y) = (*nums.first()?, *nums.get(1)?);
+                    if y < ZAPPER_OFF_SCREEN {
+                        frame.zapper_position = Some((x, y));
+                    }
+                    frame.zapper_trigger = *nums.get(2)? != 0;
+                }
+            }
+        }
+        Some(frame)
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, Error> {
+        Movie::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "version {}", VERSION)?;
+        writeln!(f, "emuVersion {}", EMU_VERSION)?;
+        writeln!(f, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(f, "palFlag 0")?;
+        writeln!(f, "romFilename {}", self.rom_filename)?;
+        writeln!(
+            f,
+            "romChecksum base64:{}",
+            base64_encode(&self.rom_checksum)
+        )?;
+        writeln!(f, "guid {}", self.guid)?;
+        writeln!(f, "fourscore {}", self.four_score as u8)?;
+        writeln!(f, "microphone 0")?;
+        writeln!(f, "port0 {}", self.ports[0].id())?;
+        writeln!(f, "port1 {}", self.ports[1].id())?;
+        writeln!(f, "port2 0")?;
+        writeln!(f, "FDS 0")?;
+        writeln!(f, "NewPPU 0")?;
+        for c in self.comments.iter() {
+            writeln!(f, "comment {}", c)?;
+        }
+
+        for frame in self.frames.iter() {
+            write!(f, "|{}|", frame.commands)?;
+            if self.four_score {
+                for c in frame.controllers.iter() {
+                    write!(f, "{}|", format_gamepad(*c))?;
+                }
+            } else {
+                for (i, port) in self.ports.iter().enumerate() {
+                    match port {
+                        Port::None => {}
+                        Port::Gamepad => write!(f, "{}", format_gamepad(frame.controllers[i]))?,
+                        Port::Zapper => {
+                            let (x, y) = frame.zapper_position.unwrap_or((0, ZAPPER_OFF_SCREEN));
+                            write!(f, "{} {} {} 0 0", x, y, frame.zapper_trigger as u8)?;
+                        }
+                    }
+                    write!(f, "|")?;
+                }
+            }
+            writeln!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_gamepad(field: &str) -> Option<u8> {
+    let field = field.as_bytes();
+    if field.len() != GAMEPAD_BUTTONS.len() {
+        return None;
+    }
+    let mut buttons = 0;
+    for (i, c) in field.iter().enumerate() {
+        if *c != b' ' && *c != b'.' {
+            buttons |= 1 << (7 - i);
+        }
+    }
+    Some(buttons)
+}
+
+fn format_gamepad(buttons: u8) -> String {
+    GAMEPAD_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            if buttons & (1 << (7 - i)) != 0 {
+                *c as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut res = String::new();
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+fn base64_decode(src: &str) -> Option<Vec<u8>> {
+    let src = src.trim_end_matches('=');
+    let mut res = Vec::new();
+    let mut n: u32 = 0;
+    for (i, c) in src.bytes().enumerate() {
+        n = (n << 6) | BASE64.iter().position(|&b| b == c)? as u32;
+        if i % 4 == 3 {
+            res.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8]);
+            n = 0;
+        }
+    }
+    match src.len() % 4 {
+        0 => {}
+        2 => res.push((n >> 4) as u8),
+        3 => res.extend_from_slice(&[(n >> 10) as u8, (n >> 2) as u8]),
+        _ => return None,
+    }
+    Some(res)
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!(base64_encode(b"Man"), "TWFu");
+    assert_eq!(base64_encode(b"Ma"), "TWE=");
+    assert_eq!(base64_encode(b"M"), "TQ==");
+    assert_eq!(base64_decode("TWFu"), Some(b"Man".to_vec()));
+    assert_eq!(base64_decode("TWE="), Some(b"Ma".to_vec()));
+    assert_eq!(base64_decode("TQ=="), Some(b"M".to_vec()));
+    assert_eq!(base64_decode("T"), None);
+    assert_eq!(base64_decode("T!=="), None);
+}
+
+#[test]
+fn test_parse() {
+    let src = "version 3
+emuVersion 22020
+rerecordCount 7
+palFlag 0
+romFilename smb
+romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==
+guid 2CD36E2C-0E5C-4F4E-8E38-6E3F3C2C3D2E
+fourscore 0
+port0 1
+port1 2
+port2 0
+comment author someone
+|1|........|0 255 0 0 0||
+|0|R......A|128 100 1 0 0||
+|0|.L.UT.B.|12 34 0 0 0||
+";
+    let movie = Movie::parse(src).unwrap();
+    assert_eq!(movie.rom_filename, "smb");
+    assert_eq!(movie.rerecord_count, 7);
+    assert_eq!(movie.ports, [Port::Gamepad, Port::Zapper]);
+    assert_eq!(movie.comments, vec!["author someone"]);
+    assert_eq!(movie.frames.len(), 3);
+
+    assert_eq!(movie.frames[0].commands, COMMAND_SOFT_RESET);
+    assert_eq!(movie.frames[0].zapper_position, None);
+
+    use input::Button;
+    assert_eq!(
+        movie.frames[1].controllers[0],
+        Button::Right.mask() | Button::A.mask()
+    );
+    assert_eq!(movie.frames[1].zapper_position, Some((128, 100)));
+    assert!(movie.frames[1].zapper_trigger);
+    assert_eq!(
+        movie.frames[2].controllers[0],
+        Button::Left.mask() | Button::Up.mask() | Button::Start.mask() | Button::B.mask()
+    );
+
+    // export and re-import
+    assert_eq!(Movie::parse(&movie.to_string()).unwrap(), movie);
+}
+
+#[test]
+fn test_parse_four_score() {
+    let src = "version 3
+fourscore 1
+|0|.......A|......B.|....T...|...U....||
+";
+    let movie = Movie::parse(src).unwrap();
+    assert_eq!(movie.frames[0].controllers, [0x01, 0x02, 0x08, 0x10]);
+    assert_eq!(Movie::parse(&movie.to_string()).unwrap(), movie);
+}
+
+#[test]
+fn test_parse_errors() {
+    let parse_err = |src| Movie::parse(src).err().unwrap().to_string();
+    assert_eq!(parse_err("version 2\n"), "unsupported movie version: 2");
+    assert_eq!(parse_err("port0 1\n"), "unsupported movie version: none");
+    assert_eq!(
+        parse_err("version 3\nbinary 1\n"),
+        "binary movies are not supported"
+    );
+    assert_eq!(
+        parse_err("version 3\nport0 7\n"),
+        "line 2: invalid header: port0 7"
+    );
+    assert_eq!(
+        parse_err("version 3\n|0|RLDU|........||\n"),
+        "line 2: invalid frame: |0|RLDU|........||"
+    );
+}
+
+#[test]
+fn test_playback() {
+    use crate::cpu::assemble;
+    use crate::cpu::Cpu;
+    use crate::mapper;
+
+    // Each NMI, read controller 1 and store it to $0300 + frame.
+    let asm = "
+    ldx #$00
+    lda #$80
+    sta $2000
+    loop: jmp loop
+    nmi: lda #$01
+    sta $4016
+    lda #$00
+    sta $4016
+    ldy #$08
+    read: lda $4016
+    lsr
+    ror $00
+    dey
+    bne read
+    lda $00
+    sta $0300,x
+    inx
+    rti
+    ";
+    let code = assemble::assemble(asm, 0xC000).unwrap();
+    let mut prg = vec![0; 0x4000];
+    prg[..code.len()].copy_from_slice(&code);
+    prg[0x3FFA..0x3FFE].copy_from_slice(&[0x0A, 0xC0, 0x00, 0xC0]); // NMI, RESET
+
+    let rom = ines::Rom {
+        mapper: 0,
+        mirroring: mapper::Mirroring::Horizontal,
+        battery: false,
+        prg,
+        chr: Vec::new(),
+        expansion_device: ines::EXPANSION_NONE,
+    };
+
+    let mut movie = Movie::new("roms/test.nes", &rom, &input::Input::new());
+    assert_eq!(movie.rom_filename, "test");
+    for i in 0..8 {
+        movie.frames.push(Frame {
+            controllers: [1 << i, 0, 0, 0],
+            ..Frame::default()
+        });
+    }
+
+    let (prg, chr) = rom.mapper().unwrap();
+    let mut cpu = Cpu::new(prg, chr);
+    movie.configure(&mut cpu.bus.input);
+    cpu.reset();
+    while movie
+        .apply(cpu.bus.ppu.frame(), &mut cpu.bus.input)
+        .is_some()
+    {
+        let frame = cpu.bus.ppu.frame();
+        while cpu.bus.ppu.frame() == frame {
+            cpu.step().unwrap();
+        }
+    }
+
+    // Vblank starts partway through each frame, so each NMI sees the input
+    // for the frame it occurs in.
+    assert_eq!(
+        cpu.mem_read_buf(0x300, 8),
+        vec![0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80]
+    );
+}
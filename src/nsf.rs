@@ -0,0 +1,388 @@
+// Reference: https://wiki.nesdev.com/w/index.php/NSF
+
+use crate::apu;
+use crate::cpu::{Cpu, CpuError};
+use crate::mapper;
+use crate::math;
+use std::error;
+use std::fmt;
+
+const MAGIC: &[u8] = b"NESM\x1A";
+const HEADER_SIZE: usize = 0x80;
+
+pub const NTSC_CPU_HZ: u64 = 1_789_773;
+pub const PAL_CPU_HZ: u64 = 1_662_607;
+
+// INIT and PLAY are called as subroutines. Their return address points here,
+// and the player stops stepping the CPU once the program counter reaches it.
+// Nothing is mapped at this address, so a well-behaved routine never executes
+// it.
+const RETURN_ADDR: u16 = 0x4100;
+
+// Upper bound on the number of cycles a single INIT or PLAY call may consume
+// before the player gives up on it returning, roughly one second of CPU time.
+const CALL_CYCLE_LIMIT: u64 = NTSC_CPU_HZ;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidMagic,
+    Truncated(usize),
+    UnsupportedLoadAddress(u16),
+    NoSongs,
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "not an NSF file"),
+            Self::Truncated(len) => write!(f, "file too short for NSF header: {} bytes", len),
+            Self::UnsupportedLoadAddress(addr) => {
+                write!(f, "unsupported load address: ${:04X}", addr)
+            }
+            Self::NoSongs => write!(f, "file contains no songs"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    pub fn cpu_hz(self) -> u64 {
+        match self {
+            Self::Ntsc => NTSC_CPU_HZ,
+            Self::Pal => PAL_CPU_HZ,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub version: u8,
+    pub total_songs: u8,
+    pub starting_song: u8, // 1-based
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub song_name: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ntsc_speed: u16, // microseconds between PLAY calls
+    pub pal_speed: u16,
+    pub bankswitch: Option<[u8; 8]>,
+    pub region: Region,
+    pub dual_region: bool,
+
+    // Bitfield of expansion audio chips. These are not emulated; programs
+    // that use them will play without the expansion channels.
+    pub extra_sound_chips: u8,
+}
+
+impl Header {
+    // Number of CPU cycles between PLAY calls.
+    pub fn play_period(&self) -> u64 {
+        let speed = match self.region {
+            Region::Ntsc => self.ntsc_speed,
+            Region::Pal => self.pal_speed,
+        };
+
+        // Some files leave the speed unset. Fall back to the video frame rate.
+        if speed == 0 {
+            return self.region.cpu_hz() / 60;
+        }
+
+        speed as u64 * self.region.cpu_hz() / 1_000_000
+    }
+}
+
+pub struct Nsf {
+    pub header: Header,
+    pub data: Vec<u8>,
+}
+
+fn parse_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Nsf, Error> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::Truncated(bytes.len()));
+    }
+
+    if &bytes[0..5] != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+
+    let word = |offset: usize| math::bytes_to_u16_le([bytes[offset], bytes[offset + 1]]);
+
+    let load_addr = word(0x08);
+    if load_addr < 0x8000 {
+        return Err(Error::UnsupportedLoadAddress(load_addr));
+    }
+
+    if bytes[0x06] == 0 {
+        return Err(Error::NoSongs);
+    }
+
+    let mut banks = [0; 8];
+    banks.copy_from_slice(&bytes[0x70..0x78]);
+
+    let header = Header {
+        version: bytes[0x05],
+        total_songs: bytes[0x06],
+        starting_song: bytes[0x07].max(1),
+        load_addr,
+        init_addr: word(0x0A),
+        play_addr: word(0x0C),
+        song_name: parse_string(&bytes[0x0E..0x2E]),
+        artist: parse_string(&bytes[0x2E..0x4E]),
+        copyright: parse_string(&bytes[0x4E..0x6E]),
+        ntsc_speed: word(0x6E),
+        pal_speed: word(0x78),
+        bankswitch: if banks.iter().any(|&b| b != 0) {
+            Some(banks)
+        } else {
+            None
+        },
+        region: if bytes[0x7A] & 0b01 != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        },
+        dual_region: bytes[0x7A] & 0b10 != 0,
+        extra_sound_chips: bytes[0x7B],
+    };
+
+    Ok(Nsf {
+        header,
+        data: bytes[HEADER_SIZE..].to_vec(),
+    })
+}
+
+// Drives an NSF program on a Cpu. Call start() to select a song, then call
+// play() once per play period.
+pub struct Player {
+    pub cpu: Cpu,
+    header: Header,
+    song: u8,
+    next_play: u64,
+}
+
+impl Player {
+    pub fn new(nsf: &Nsf) -> Result<Player, CpuError> {
+        let (prg, chr) = mapper::nsf::new(nsf.header.load_addr, &nsf.data, nsf.header.bankswitch);
+        let mut player = Player {
+            cpu: Cpu::new(Box::new(prg), Box::new(chr)),
+            header: nsf.header.clone(),
+            song: 0,
+            next_play: 0,
+        };
+        player.start(nsf.header.starting_song - 1)?;
+        Ok(player)
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // 0-based index of the current song.
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+
+    // Resets the machine and calls INIT for the given 0-based song index.
+    // https://wiki.nesdev.com/w/index.php/NSF#Initializing_a_tune
+    pub fn start(&mut self, song: u8) -> Result<(), CpuError> {
+        self.song = song % self.header.total_songs;
+
+        for addr in 0..0x800 {
+            self.cpu.mem_write(addr, 0);
+        }
+        for addr in 0x6000..0x8000 {
+            self.cpu.mem_write(addr, 0);
+        }
+
+        self.cpu.bus.apu = apu::Apu::new();
+        for addr in 0x4000..=0x4013 {
+            self.cpu.mem_write(addr, 0);
+        }
+        self.cpu.mem_write(0x4015, 0x00);
+        self.cpu.mem_write(0x4015, 0x0F);
+        self.cpu.mem_write(0x4017, 0x40);
+
+        if let Some(banks) = self.header.bankswitch {
+            for (i, &bank) in banks.iter().enumerate() {
+                self.cpu.mem_write(0x5FF8 + i as u16, bank);
+            }
+        }
+
+        self.cpu.regs.a = self.song;
+        self.cpu.regs.x = match self.header.region {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+        };
+        self.cpu.regs.y = 0;
+        self.cpu.regs.p = 0;
+        self.cpu.regs.s = 0xFF;
+        self.call(self.header.init_addr)?;
+
+        self.next_play = self.cpu.cycles;
+        Ok(())
+    }
+
+    // Calls PLAY, then idles the CPU until the next PLAY call is due.
+    pub fn play(&mut self) -> Result<(), CpuError> {
+        self.call(self.header.play_addr)?;
+
+        self.next_play += self.header.play_period();
+        if self.cpu.cycles < self.next_play {
+            self.cpu.cycle_add(self.next_play - self.cpu.cycles);
+        }
+        Ok(())
+    }
+
+    // Runs the subroutine at addr until it returns.
+    fn call(&mut self, addr: u16) -> Result<(), CpuError> {
+        self.cpu.stack_push16(RETURN_ADDR - 1)?;
+        self.cpu.regs.pc = addr;
+
+        let limit = self.cpu.cycles + CALL_CYCLE_LIMIT;
+        while self.cpu.regs.pc != RETURN_ADDR && self.cpu.cycles < limit {
+            self.cpu.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn channel_status(&self) -> Vec<apu::ChannelStatus> {
+        apu::CHANNELS
+            .iter()
+            .map(|&c| self.cpu.bus.apu.channel_status(c))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn test_file(bankswitch: Option<[u8; 8]>, program: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0; HEADER_SIZE];
+    bytes[0..5].copy_from_slice(MAGIC);
+    bytes[0x05] = 1; // version
+    bytes[0x06] = 3; // total songs
+    bytes[0x07] = 2; // starting song
+    bytes[0x08..0x0A].copy_from_slice(&[0x00, 0x80]); // load
+    bytes[0x0A..0x0C].copy_from_slice(&[0x00, 0x80]); // init
+    bytes[0x0C..0x0E].copy_from_slice(&[0x00, 0x81]); // play
+    bytes[0x0E..0x12].copy_from_slice(b"song");
+    bytes[0x2E..0x34].copy_from_slice(b"artist");
+    bytes[0x6E..0x70].copy_from_slice(&math::u16_to_bytes_le(16639));
+    if let Some(banks) = bankswitch {
+        bytes[0x70..0x78].copy_from_slice(&banks);
+    }
+    bytes.extend_from_slice(program);
+    bytes
+}
+
+#[test]
+fn test_parse() {
+    let nsf = parse(&test_file(None, &[1, 2, 3])).unwrap();
+    assert_eq!(nsf.header.total_songs, 3);
+    assert_eq!(nsf.header.starting_song, 2);
+    assert_eq!(nsf.header.load_addr, 0x8000);
+    assert_eq!(nsf.header.init_addr, 0x8000);
+    assert_eq!(nsf.header.play_addr, 0x8100);
+    assert_eq!(nsf.header.song_name, "song");
+    assert_eq!(nsf.header.artist, "artist");
+    assert_eq!(nsf.header.copyright, "");
+    assert_eq!(nsf.header.bankswitch, None);
+    assert_eq!(nsf.header.region, Region::Ntsc);
+    assert_eq!(nsf.header.play_period(), 29780);
+    assert_eq!(nsf.data, vec![1, 2, 3]);
+
+    let nsf = parse(&test_file(Some([0, 1, 0, 0, 0, 0, 0, 0]), &[])).unwrap();
+    assert_eq!(nsf.header.bankswitch, Some([0, 1, 0, 0, 0, 0, 0, 0]));
+
+    // errors
+    assert_eq!(parse(&[0; 4]).err(), Some(Error::Truncated(4)));
+    assert_eq!(parse(&[0; HEADER_SIZE]).err(), Some(Error::InvalidMagic));
+    let mut bytes = test_file(None, &[]);
+    bytes[0x09] = 0x60;
+    assert_eq!(
+        parse(&bytes).err(),
+        Some(Error::UnsupportedLoadAddress(0x6000))
+    );
+    let mut bytes = test_file(None, &[]);
+    bytes[0x06] = 0;
+    assert_eq!(parse(&bytes).err(), Some(Error::NoSongs));
+}
+
+#[test]
+fn test_player() {
+    use crate::cpu::assemble;
+
+    // INIT stores the song number and enables pulse 1. PLAY counts calls and
+    // keys a note on pulse 1 with a period based on the song number.
+    let init = assemble::assemble(
+        "
+sta $00
+lda #$01
+sta $4015
+rts
+",
+        0x8000,
+    )
+    .unwrap();
+    let play = assemble::assemble(
+        "
+inc $01
+lda #$BF
+sta $4000
+lda $00
+adc #$10
+sta $4002
+lda #$08
+sta $4003
+rts
+",
+        0x8100,
+    )
+    .unwrap();
+
+    let mut program = vec![0; 0x100];
+    program[..init.len()].copy_from_slice(&init);
+    program.extend_from_slice(&play);
+
+    let nsf = parse(&test_file(None, &program)).unwrap();
+    let mut player = Player::new(&nsf).unwrap();
+    assert_eq!(player.song(), 1);
+    assert_eq!(player.cpu.mem_read(0x00), 1);
+    assert!(!player.channel_status()[0].active);
+
+    player.play().unwrap();
+    player.play().unwrap();
+    assert_eq!(player.cpu.mem_read(0x01), 2);
+    assert_eq!(
+        player.channel_status()[0],
+        apu::ChannelStatus {
+            channel: apu::Channel::Pulse1,
+            active: true,
+            volume: 15,
+            period: 0x11,
+        }
+    );
+
+    // PLAY calls are spaced by the play period
+    let before = player.cpu.cycles;
+    player.play().unwrap();
+    assert_eq!(player.cpu.cycles - before, nsf.header.play_period());
+
+    // restarting resets memory and passes the new song number to INIT
+    player.start(2).unwrap();
+    assert_eq!(player.song(), 2);
+    assert_eq!(player.cpu.mem_read(0x00), 2);
+    assert_eq!(player.cpu.mem_read(0x01), 0);
+}
@@ -1,6 +1,8 @@
 use super::mapper;
+use crate::cpu::{Access, BusAccess};
+use crate::savestate::{self, State};
 
-mod palette;
+pub mod palette;
 
 // Reference: https://wiki.nesdev.com/w/index.php/PPU_programmer_reference
 #[derive(Default)]
@@ -9,26 +11,44 @@ pub struct Registers {
     pub ppumask: u8,
     pub ppustatus: u8,
     pub oamaddr: u8,
-    pub oamdata: u8,
-    pub ppuscroll: u8,
-    pub ppuaddr: u8,
-    pub ppudata: u8,
-
-    // Writing $XX will upload 256 bytes of data from CPU page $XX00-$XXFF to
-    // the internal PPU OAM. This page is typically located in internal RAM,
-    // commonly $0200-$02FF, but cartridge RAM or ROM can be used as well.
-    pub oamdma: u8,
 }
 
+// PPUCTRL
+const CTRL_NAMETABLE: u8 = 0b0000_0011;
+const CTRL_INCREMENT_32: u8 = 0b0000_0100;
+const CTRL_SPRITE_PATTERNS: u8 = 0b0000_1000;
+const CTRL_BG_PATTERNS: u8 = 0b0001_0000;
+const CTRL_SPRITE_8X16: u8 = 0b0010_0000;
+const CTRL_NMI: u8 = 0b1000_0000;
+
+// PPUMASK
+const MASK_GREYSCALE: u8 = 0b0000_0001;
+const MASK_BG_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BG: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+
+// PPUSTATUS
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+// Frame timing, in PPU dots. There are three dots per CPU cycle.
+// https://wiki.nesdev.com/w/index.php/PPU_rendering
+pub const DOTS_PER_SCANLINE: usize = 341;
+pub const SCANLINES_PER_FRAME: usize = 262;
+const VBLANK_SCANLINE: usize = 241;
+const PRERENDER_SCANLINE: usize = 261;
+
 const TILE_PIXELS: usize = 8; // 8 pixels per tile
 const TILE_ROWS: usize = 8; // 8x8 tiles
 const TILE_BITPLANES: usize = 2; // 2 bitplanes per tile
 const TILE_BYTES: usize = TILE_ROWS * TILE_BITPLANES; // 16 bytes per tile
 const NAMETABLE_COLS: usize = 32;
 const NAMETABLE_ROWS: usize = 30;
-const ATTRIBUTE_TABLE_BYTES: usize = 64;
-const NAMETABLE_BYTES: usize = (NAMETABLE_ROWS * NAMETABLE_COLS) + ATTRIBUTE_TABLE_BYTES; // 1024 bytes (0x400)
 const OAM_BYTES: usize = 256;
+const SPRITES: usize = OAM_BYTES / 4;
+const SPRITES_PER_SCANLINE: usize = 8;
 const PALETTES_PER_SET: usize = 4;
 const PALETTE_SETS: usize = 2; // BG and sprites each have a set of 4 palettes
 const COLORS_PER_PALETTE: usize = 4;
@@ -43,10 +63,39 @@ pub const FRAMEBUFFER_BYTES: usize = SCREEN_HEIGHT * SCREEN_ROW_PITCH;
 
 pub struct Ppu {
     regs: Registers,
+
+    // Internal registers. v is the current VRAM address, t is the temporary
+    // VRAM address, x is the fine X scroll, and w is the shared write toggle
+    // for PPUSCROLL and PPUADDR.
+    // https://wiki.nesdev.com/w/index.php/PPU_scrolling
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+
+    // PPUDATA reads below the palette return the contents of this buffer,
+    // which is then refilled from the current address.
+    read_buffer: u8,
+
+    // The last value written to any register. Reads of write-only registers,
+    // and the low five bits of PPUSTATUS, return this value.
+    latch: u8,
+
+    scanline: usize,
+    dot: usize,
+    frame: u64,
+    nmi_pending: bool,
+
     oam: [u8; OAM_BYTES],
     palette: [u8; ALL_PALETTES_BYTES],
     mapper: Box<dyn mapper::Ppu>,
-    pub framebuf: [u8; FRAMEBUFFER_BYTES],
+
+    // Boxed, so that the PPU and the buses that own it are cheap to move.
+    pub framebuf: Box<[u8; FRAMEBUFFER_BYTES]>,
+
+    // When set, VRAM reads and writes made through PPUDATA are recorded
+    // here, for debuggers. Rendering fetches aren't recorded.
+    pub accesses: Option<Vec<BusAccess>>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -59,10 +108,21 @@ impl Ppu {
     pub fn new(mapper: Box<dyn mapper::Ppu>) -> Ppu {
         Ppu {
             regs: Registers::default(),
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            latch: 0,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            nmi_pending: false,
             oam: [0; OAM_BYTES],
             palette: [0; ALL_PALETTES_BYTES],
-            mapper: mapper,
-            framebuf: [0; FRAMEBUFFER_BYTES],
+            mapper,
+            framebuf: Box::new([0; FRAMEBUFFER_BYTES]),
+            accesses: None,
         }
     }
 
@@ -71,18 +131,26 @@ impl Ppu {
         match addr {
             0x0000..=0x2FFF => self.mapper.read(addr),
             0x3000..=0x3EFF => self.mem_read(addr - 0x1000),
-            0x3F00..=0x3F1F => self.palette[addr as usize - 0x3F00],
-            0x3F20..=0x3F3F => self.palette[addr as usize - 0x3F20],
+            0x3F00..=0x3FFF => self.palette[palette_index(addr)],
             _ => panic!("invalid address {}", addr),
         }
     }
 
+    // Reads without side effects, for debuggers. Addresses are mirrored into
+    // $0000-$3FFF.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr & 0x3FFF {
+            addr @ 0x0000..=0x2FFF => self.mapper.peek(addr),
+            addr @ 0x3000..=0x3EFF => self.peek(addr - 0x1000),
+            addr => self.palette[palette_index(addr)],
+        }
+    }
+
     pub fn mem_write(&mut self, addr: u16, v: u8) {
         match addr {
             0x0000..=0x2FFF => self.mapper.write(addr, v),
             0x3000..=0x3EFF => self.mem_write(addr - 0x1000, v),
-            0x3F00..=0x3F1F => self.palette[addr as usize - 0x3F00] = v,
-            0x3F20..=0x3F3F => self.palette[addr as usize - 0x3F20] = v,
+            0x3F00..=0x3FFF => self.palette[palette_index(addr)] = v,
             _ => panic!("invalid address {}", addr),
         }
     }
@@ -114,19 +182,11 @@ impl Ppu {
         let nt_addr = nt_base + (yt * NAMETABLE_COLS) + xt;
         let pat_index = self.mem_read(nt_addr as u16);
 
-        // fetch pattern bitplane rows
-        let bp_base = 16 * pat_index as u16 + tile_row as u16;
-        let bp_row_lo = self.mem_read(bp_base);
-        let bp_row_hi = self.mem_read(bp_base + 8);
-
         // get index within palette
-        let mask = 1 << tile_col;
-        let pal_color = match (bp_row_hi & mask > 0, bp_row_lo & mask > 0) {
-            (false, false) => return PixelColor::Transparent,
-            (false, true) => 1,
-            (true, false) => 2,
-            (true, true) => 3,
-        };
+        let pal_color = self.pattern_pixel(self.bg_pattern_base(), pat_index, tile_row, tile_col);
+        if pal_color == 0 {
+            return PixelColor::Transparent;
+        }
 
         // fetch palette from attribute table
         // https://wiki.nesdev.com/w/index.php/PPU_attribute_tables
@@ -142,9 +202,457 @@ impl Ppu {
             (true, true) => 6,
         };
         let pal_index = (attr >> shift) & 0b11;
-        let color_id_addr = 0x3F00 + (pal_index as usize * BYTES_PER_PALETTE) + pal_color;
+        let color_id_addr = 0x3F00 + (pal_index as usize * BYTES_PER_PALETTE) + pal_color as usize;
         PixelColor::Index(self.mem_read(color_id_addr as u16))
     }
+
+    fn bg_pattern_base(&self) -> u16 {
+        if self.regs.ppuctrl & CTRL_BG_PATTERNS != 0 {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    // Returns the 2-bit color of a pixel within a tile. Column 0 is the
+    // leftmost pixel, which is stored in the high bit of each bitplane row.
+    // https://wiki.nesdev.com/w/index.php/PPU_pattern_tables
+    fn pattern_pixel(&self, base: u16, tile: u8, row: usize, col: usize) -> u8 {
+        let bp_base = base + TILE_BYTES as u16 * tile as u16 + row as u16;
+        let bp_row_lo = self.mem_read(bp_base);
+        let bp_row_hi = self.mem_read(bp_base + TILE_ROWS as u16);
+        let shift = 7 - col;
+        (((bp_row_hi >> shift) & 1) << 1) | ((bp_row_lo >> shift) & 1)
+    }
+
+    // Returns the palette index and priority of the first opaque sprite pixel
+    // at column x, along with whether it belongs to sprite 0.
+    // https://wiki.nesdev.com/w/index.php/PPU_OAM
+    fn sprite_pixel(&self, sprites: &[usize], x: usize, y: usize) -> Option<(u8, bool, bool)> {
+        let tall = self.regs.ppuctrl & CTRL_SPRITE_8X16 != 0;
+        let height = if tall { 16 } else { 8 };
+
+        for &i in sprites.iter() {
+            let entry = &self.oam[i * 4..i * 4 + 4];
+            let sprite_x = entry[3] as usize;
+            if x < sprite_x || x >= sprite_x + TILE_PIXELS {
+                continue;
+            }
+
+            let attr = entry[2];
+            let mut row = y - (entry[0] as usize + 1);
+            let mut col = x - sprite_x;
+            if attr & 0b1000_0000 != 0 {
+                row = height - 1 - row;
+            }
+            if attr & 0b0100_0000 != 0 {
+                col = TILE_PIXELS - 1 - col;
+            }
+
+            // 8x16 sprites select their pattern table with bit 0 of the tile
+            // index, and occupy two consecutive tiles.
+            let (base, tile) = if tall {
+                let base = if entry[1] & 1 != 0 { 0x1000 } else { 0 };
+                (base, (entry[1] & 0xFE) + (row / 8) as u8)
+            } else if self.regs.ppuctrl & CTRL_SPRITE_PATTERNS != 0 {
+                (0x1000, entry[1])
+            } else {
+                (0, entry[1])
+            };
+
+            let pal_color = self.pattern_pixel(base, tile, row % 8, col);
+            if pal_color == 0 {
+                continue;
+            }
+
+            let pal_index = (PALETTES_PER_SET as u8) + (attr & 0b11);
+            let color_id_addr =
+                0x3F00 + pal_index as u16 * BYTES_PER_PALETTE as u16 + pal_color as u16;
+            let behind_bg = attr & 0b0010_0000 != 0;
+            return Some((self.mem_read(color_id_addr), behind_bg, i == 0));
+        }
+
+        None
+    }
+
+    // Returns the OAM indexes of the sprites on the given scanline, and sets
+    // the overflow flag if there are too many.
+    fn evaluate_sprites(&mut self, y: usize) -> Vec<usize> {
+        let height = if self.regs.ppuctrl & CTRL_SPRITE_8X16 != 0 {
+            16
+        } else {
+            8
+        };
+
+        // Sprite data is delayed by one scanline, so sprites appear one line
+        // below their Y coordinate.
+        let mut sprites = Vec::with_capacity(SPRITES_PER_SCANLINE);
+        for i in 0..SPRITES {
+            let top = self.oam[i * 4] as usize + 1;
+            if y < top || y >= top + height {
+                continue;
+            }
+            if sprites.len() == SPRITES_PER_SCANLINE {
+                self.regs.ppustatus |= STATUS_SPRITE_OVERFLOW;
+                break;
+            }
+            sprites.push(i);
+        }
+        sprites
+    }
+
+    // Draws one line of the framebuffer using the current scroll position.
+    fn render_scanline(&mut self) {
+        let y = self.scanline;
+        let mask = self.regs.ppumask;
+        let sprites = if mask & MASK_SPRITES != 0 {
+            self.evaluate_sprites(y)
+        } else {
+            Vec::new()
+        };
+
+        // Convert the scroll position in v to worldspace coordinates. See
+        // bg_pixel_color() for the worldspace layout.
+        let coarse_x = (self.v & 0x1F) as usize;
+        let coarse_y = ((self.v >> 5) & 0x1F) as usize;
+        let nametable = ((self.v >> 10) & 0b11) as usize;
+        let fine_y = ((self.v >> 12) & 0b111) as usize;
+        let world_x = (nametable & 1) * SCREEN_WIDTH + coarse_x * TILE_PIXELS + self.x as usize;
+        let world_y = (nametable >> 1) * SCREEN_HEIGHT + coarse_y * TILE_PIXELS + fine_y;
+
+        let backdrop = self.mem_read(0x3F00);
+        for x in 0..SCREEN_WIDTH {
+            let bg = if mask & MASK_BG != 0 && (x >= TILE_PIXELS || mask & MASK_BG_LEFT != 0) {
+                self.bg_pixel_color(world_x + x, world_y)
+            } else {
+                PixelColor::Transparent
+            };
+
+            let sprite = if x >= TILE_PIXELS || mask & MASK_SPRITES_LEFT != 0 {
+                self.sprite_pixel(&sprites, x, y)
+            } else {
+                None
+            };
+
+            let color = match (bg, sprite) {
+                (PixelColor::Transparent, None) => backdrop,
+                (PixelColor::Transparent, Some((color, _, _))) => color,
+                (PixelColor::Index(color), None) => color,
+                (PixelColor::Index(bg_color), Some((color, behind_bg, sprite_zero))) => {
+                    if sprite_zero && x != SCREEN_WIDTH - 1 {
+                        self.regs.ppustatus |= STATUS_SPRITE_ZERO_HIT;
+                    }
+                    if behind_bg {
+                        bg_color
+                    } else {
+                        color
+                    }
+                }
+            };
+
+            let color = if mask & MASK_GREYSCALE != 0 {
+                color & 0x30
+            } else {
+                color & 0x3F
+            };
+            let rgb = palette::CXA2025AS[color as usize];
+            let base = y * SCREEN_ROW_PITCH + 3 * x;
+            self.framebuf[base..base + 3].copy_from_slice(&rgb);
+        }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.regs.ppumask & (MASK_BG | MASK_SPRITES) != 0
+    }
+
+    // Moves v to the next pixel row, wrapping into the next vertical
+    // nametable after the last row of tiles.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v >> 5) & 0x1F;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    // Advances the PPU by one dot.
+    pub fn tick(&mut self) {
+        let rendering = self.rendering_enabled();
+
+        match (self.scanline, self.dot) {
+            (0..=239, 256) => {
+                self.render_scanline();
+                if rendering {
+                    self.increment_y();
+                }
+            }
+            (0..=239, 257) | (PRERENDER_SCANLINE, 257) if rendering => {
+                // copy horizontal position from t
+                self.v = (self.v & !0x041F) | (self.t & 0x041F);
+            }
+            (PRERENDER_SCANLINE, 280..=304) if rendering => {
+                // copy vertical position from t
+                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            (VBLANK_SCANLINE, 1) => {
+                self.regs.ppustatus |= STATUS_VBLANK;
+                if self.regs.ppuctrl & CTRL_NMI != 0 {
+                    self.nmi_pending = true;
+                }
+            }
+            (PRERENDER_SCANLINE, 1) => {
+                self.regs.ppustatus &=
+                    !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+            }
+            _ => {}
+        }
+
+        self.dot += 1;
+
+        // The pre-render scanline is one dot shorter on odd frames when
+        // rendering is enabled.
+        if self.scanline == PRERENDER_SCANLINE
+            && self.dot == 340
+            && rendering
+            && self.frame % 2 == 1
+        {
+            self.dot += 1;
+        }
+
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+
+    // The scanline being drawn. Scanlines 0-239 are visible, 240-260 are
+    // post-render and vertical blanking, and 261 is the pre-render line.
+    pub fn scanline(&self) -> usize {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> usize {
+        self.dot
+    }
+
+    // Number of completed frames. The framebuffer holds a complete picture
+    // whenever this changes.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Returns whether an NMI has been signalled since the last call.
+    pub fn take_nmi(&mut self) -> bool {
+        let nmi = self.nmi_pending;
+        self.nmi_pending = false;
+        nmi
+    }
+
+    // Handles a CPU read of the register at $2000 + reg.
+    pub fn read_register(&mut self, reg: u16) -> u8 {
+        let v = self.peek_register(reg);
+        match reg {
+            // PPUSTATUS
+            2 => {
+                self.regs.ppustatus &= !STATUS_VBLANK;
+                self.w = false;
+            }
+            // PPUDATA
+            7 => {
+                let addr = self.v & 0x3FFF;
+                let fetched = self.mem_read(addr);
+                // Palette reads aren't buffered, but the buffer is filled with
+                // the nametable data underneath.
+                self.read_buffer = if addr >= 0x3F00 {
+                    self.mem_read(addr - 0x1000)
+                } else {
+                    fetched
+                };
+                self.record_access(addr, Access::Read, fetched);
+                self.increment_v();
+            }
+            _ => {}
+        }
+        v
+    }
+
+    // Returns what a CPU read of the register at $2000 + reg would, without
+    // clearing the vblank flag or moving the address and read buffer.
+    pub fn peek_register(&self, reg: u16) -> u8 {
+        match reg {
+            // PPUSTATUS
+            2 => (self.regs.ppustatus & 0b1110_0000) | (self.latch & 0b0001_1111),
+            // OAMDATA
+            4 => self.oam[self.regs.oamaddr as usize],
+            // PPUDATA
+            7 => {
+                let addr = self.v & 0x3FFF;
+                if addr >= 0x3F00 {
+                    self.peek(addr)
+                } else {
+                    self.read_buffer
+                }
+            }
+            // write-only registers
+            _ => self.latch,
+        }
+    }
+
+    // Handles a CPU write to the register at $2000 + reg.
+    pub fn write_register(&mut self, reg: u16, v: u8) {
+        self.latch = v;
+
+        match reg {
+            // PPUCTRL
+            0 => {
+                // Enabling NMI during vblank triggers one immediately.
+                if self.regs.ppuctrl & CTRL_NMI == 0
+                    && v & CTRL_NMI != 0
+                    && self.regs.ppustatus & STATUS_VBLANK != 0
+                {
+                    self.nmi_pending = true;
+                }
+                self.regs.ppuctrl = v;
+                self.t = (self.t & !0x0C00) | (((v & CTRL_NAMETABLE) as u16) << 10);
+            }
+            // PPUMASK
+            1 => self.regs.ppumask = v,
+            // PPUSTATUS
+            2 => {}
+            // OAMADDR
+            3 => self.regs.oamaddr = v,
+            // OAMDATA
+            4 => {
+                self.oam[self.regs.oamaddr as usize] = v;
+                self.regs.oamaddr = self.regs.oamaddr.wrapping_add(1);
+            }
+            // PPUSCROLL
+            5 => {
+                if !self.w {
+                    self.t = (self.t & !0x001F) | (v >> 3) as u16;
+                    self.x = v & 0b111;
+                } else {
+                    self.t = (self.t & !0x73E0)
+                        | (((v & 0b111) as u16) << 12)
+                        | (((v >> 3) as u16) << 5);
+                }
+                self.w = !self.w;
+            }
+            // PPUADDR
+            6 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | (((v & 0x3F) as u16) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | v as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            }
+            // PPUDATA
+            7 => {
+                let addr = self.v & 0x3FFF;
+                self.mem_write(addr, v);
+                self.record_access(addr, Access::Write, v);
+                self.increment_v();
+            }
+            _ => panic!("invalid register {}", reg),
+        }
+    }
+
+    fn record_access(&mut self, addr: u16, access: Access, value: u8) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(BusAccess {
+                addr,
+                access,
+                value,
+            });
+        }
+    }
+
+    fn increment_v(&mut self) {
+        let amt = if self.regs.ppuctrl & CTRL_INCREMENT_32 != 0 {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(amt) & 0x7FFF;
+    }
+}
+
+// Returns the index into palette memory for an address in $3F00-$3FFF. The
+// backdrop entries of the sprite palettes mirror those of the BG palettes.
+// https://wiki.nesdev.com/w/index.php/PPU_palettes
+fn palette_index(addr: u16) -> usize {
+    let i = (addr as usize - 0x3F00) % ALL_PALETTES_BYTES;
+    if i >= 0x10 && i.is_multiple_of(BYTES_PER_PALETTE) {
+        i - 0x10
+    } else {
+        i
+    }
+}
+
+// The framebuffer is saved too, so that a loaded state shows its picture
+// straight away rather than after the next frame is drawn.
+impl State for Ppu {
+    fn save_state(&self, w: &mut savestate::Writer) {
+        w.u8(self.regs.ppuctrl);
+        w.u8(self.regs.ppumask);
+        w.u8(self.regs.ppustatus);
+        w.u8(self.regs.oamaddr);
+        w.u16(self.v);
+        w.u16(self.t);
+        w.u8(self.x);
+        w.bool(self.w);
+        w.u8(self.read_buffer);
+        w.u8(self.latch);
+        w.usize(self.scanline);
+        w.usize(self.dot);
+        w.u64(self.frame);
+        w.bool(self.nmi_pending);
+        w.bytes(&self.oam);
+        w.bytes(&self.palette);
+        w.bytes(&self.framebuf[..]);
+        self.mapper.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut savestate::Reader) -> Result<(), savestate::Error> {
+        self.regs.ppuctrl = r.u8()?;
+        self.regs.ppumask = r.u8()?;
+        self.regs.ppustatus = r.u8()?;
+        self.regs.oamaddr = r.u8()?;
+        self.v = r.u16()?;
+        self.t = r.u16()?;
+        self.x = r.u8()?;
+        self.w = r.bool()?;
+        self.read_buffer = r.u8()?;
+        self.latch = r.u8()?;
+        self.scanline = r.usize()?;
+        self.dot = r.usize()?;
+        if self.scanline >= SCANLINES_PER_FRAME || self.dot >= DOTS_PER_SCANLINE {
+            return Err(savestate::Error::Invalid("PPU position"));
+        }
+        self.frame = r.u64()?;
+        self.nmi_pending = r.bool()?;
+        r.bytes_into(&mut self.oam, "OAM")?;
+        r.bytes_into(&mut self.palette, "palette")?;
+        r.bytes_into(&mut self.framebuf[..], "framebuffer")?;
+        self.mapper.load_state(r)
+    }
 }
 
 #[test]
@@ -164,8 +672,8 @@ fn test_bg_pixel_color() {
     );
 
     // pattern data (tile 0x10)
-    ppu.mem_write_buf(0x100, vec![0b10101010, 0b10101010]); // first two rows of low bitplane
-    ppu.mem_write_buf(0x108, vec![0b11001100, 0b11001100]); // first two rows of high bitplane
+    ppu.mem_write_buf(0x100, vec![0b01010101, 0b01010101]); // first two rows of low bitplane
+    ppu.mem_write_buf(0x108, vec![0b00110011, 0b00110011]); // first two rows of high bitplane
 
     // attribute data (palette selection)
     ppu.mem_write(
@@ -217,11 +725,11 @@ fn test_bg_pixel_color() {
 
     // Nametable wrapping
     assert_eq!(
-        ppu.bg_pixel_color(105 + (0 * 256), 168 + (2 * 240)),
+        ppu.bg_pixel_color(105, 168 + (2 * 240)),
         PixelColor::Index(1)
     );
     assert_eq!(
-        ppu.bg_pixel_color(105 + (2 * 256), 168 + (0 * 240)),
+        ppu.bg_pixel_color(105 + (2 * 256), 168),
         PixelColor::Index(1)
     );
     assert_eq!(
@@ -229,3 +737,174 @@ fn test_bg_pixel_color() {
         PixelColor::Index(1)
     );
 }
+
+#[test]
+fn test_palette_mirroring() {
+    let mut ppu = Ppu::new(Box::new(mapper::test::new().1));
+    ppu.mem_write(0x3F10, 1);
+    assert_eq!(ppu.mem_read(0x3F00), 1);
+    ppu.mem_write(0x3F04, 2);
+    assert_eq!(ppu.mem_read(0x3F14), 2);
+    ppu.mem_write(0x3F11, 3);
+    assert_eq!(ppu.mem_read(0x3F01), 0);
+    assert_eq!(ppu.mem_read(0x3F31), 3);
+    assert_eq!(ppu.mem_read(0x3FF1), 3);
+}
+
+#[test]
+fn test_ppudata() {
+    let mut ppu = Ppu::new(Box::new(mapper::test::new().1));
+
+    // write two bytes at $2100
+    ppu.write_register(6, 0x21);
+    ppu.write_register(6, 0x00);
+    ppu.write_register(7, 1);
+    ppu.write_register(7, 2);
+    assert_eq!(ppu.mem_read(0x2100), 1);
+    assert_eq!(ppu.mem_read(0x2101), 2);
+
+    // reads are delayed by one through the read buffer, and peeks see the
+    // buffer without advancing
+    ppu.write_register(6, 0x21);
+    ppu.write_register(6, 0x00);
+    ppu.read_register(7);
+    assert_eq!(ppu.peek_register(7), 1);
+    assert_eq!(ppu.peek_register(7), 1);
+    assert_eq!(ppu.read_register(7), 1);
+    assert_eq!(ppu.read_register(7), 2);
+
+    // palette reads are immediate
+    ppu.mem_write(0x3F01, 0x2A);
+    ppu.write_register(6, 0x3F);
+    ppu.write_register(6, 0x01);
+    assert_eq!(ppu.read_register(7), 0x2A);
+
+    // increment by 32
+    ppu.write_register(0, CTRL_INCREMENT_32);
+    ppu.write_register(6, 0x20);
+    ppu.write_register(6, 0x00);
+    ppu.write_register(7, 3);
+    ppu.write_register(7, 4);
+    assert_eq!(ppu.mem_read(0x2000), 3);
+    assert_eq!(ppu.mem_read(0x2020), 4);
+}
+
+#[test]
+#[allow(clippy::unusual_byte_groupings)]
+fn test_scroll_registers() {
+    // Example from: https://wiki.nesdev.com/w/index.php/PPU_scrolling#Summary
+    // Values of t are grouped by field: fine Y, nametable, coarse Y, coarse X.
+    let mut ppu = Ppu::new(Box::new(mapper::test::new().1));
+
+    ppu.write_register(0, 0);
+    assert_eq!(ppu.t & 0x0C00, 0);
+
+    ppu.read_register(2);
+    assert!(!ppu.w);
+
+    ppu.write_register(5, 0b0111_1101);
+    assert_eq!(ppu.t, 0b000_00_00000_01111);
+    assert_eq!(ppu.x, 0b101);
+    assert!(ppu.w);
+
+    ppu.write_register(5, 0b0101_1110);
+    assert_eq!(ppu.t, 0b110_00_01011_01111);
+    assert!(!ppu.w);
+
+    ppu.write_register(6, 0b0011_1101);
+    assert_eq!(ppu.t, 0b011_11_01011_01111);
+
+    ppu.write_register(6, 0b1111_0000);
+    assert_eq!(ppu.t, 0b011_11_01111_10000);
+    assert_eq!(ppu.v, ppu.t);
+}
+
+#[test]
+fn test_vblank() {
+    let mut ppu = Ppu::new(Box::new(mapper::test::new().1));
+    ppu.write_register(0, CTRL_NMI);
+
+    let vblank_start = VBLANK_SCANLINE * DOTS_PER_SCANLINE + 1;
+    for _ in 0..vblank_start {
+        ppu.tick();
+    }
+    assert!(!ppu.take_nmi());
+    assert_eq!(ppu.regs.ppustatus & STATUS_VBLANK, 0);
+
+    ppu.tick();
+    assert!(ppu.take_nmi());
+    assert!(!ppu.take_nmi());
+
+    // reading PPUSTATUS clears the flag, but peeking it doesn't
+    assert_eq!(ppu.peek_register(2) & STATUS_VBLANK, STATUS_VBLANK);
+    assert_eq!(ppu.read_register(2) & STATUS_VBLANK, STATUS_VBLANK);
+    assert_eq!(ppu.read_register(2) & STATUS_VBLANK, 0);
+
+    // enabling NMI during vblank triggers one
+    ppu.write_register(0, 0);
+    ppu.regs.ppustatus |= STATUS_VBLANK;
+    ppu.write_register(0, CTRL_NMI);
+    assert!(ppu.take_nmi());
+
+    while ppu.frame() == 0 {
+        ppu.tick();
+    }
+    assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+    assert_eq!(ppu.regs.ppustatus & STATUS_VBLANK, 0);
+}
+
+#[test]
+fn test_render() {
+    let mut ppu = Ppu::new(Box::new(mapper::test::new().1));
+
+    // tile 1 is solid color 1, tile 2 is solid color 3
+    ppu.mem_write_buf(0x10, vec![0xFF; 8]);
+    ppu.mem_write_buf(0x20, vec![0xFF; 16]);
+
+    // BG palette 0 and sprite palette 1
+    ppu.mem_write_buf(0x3F00, vec![0x0F, 0x30]);
+    ppu.mem_write(0x3F17, 0x16);
+
+    // fill the top-left tile of the first nametable
+    ppu.mem_write(0x2000, 1);
+
+    // sprite 0 at (4, 4), overlapping the BG tile, using palette 1
+    ppu.oam[0..4].copy_from_slice(&[3, 2, 0b01, 4]);
+
+    ppu.write_register(1, MASK_BG | MASK_SPRITES | MASK_BG_LEFT | MASK_SPRITES_LEFT);
+    while ppu.scanline() < 12 {
+        ppu.tick();
+    }
+
+    // sprite 0 hit is set where opaque sprite and BG pixels overlap
+    assert_eq!(
+        ppu.regs.ppustatus & STATUS_SPRITE_ZERO_HIT,
+        STATUS_SPRITE_ZERO_HIT
+    );
+
+    while ppu.frame() == 0 {
+        ppu.tick();
+    }
+
+    let pixel = |ppu: &Ppu, x: usize, y: usize| {
+        let base = y * SCREEN_ROW_PITCH + 3 * x;
+        [
+            ppu.framebuf[base],
+            ppu.framebuf[base + 1],
+            ppu.framebuf[base + 2],
+        ]
+    };
+    assert_eq!(pixel(&ppu, 0, 0), palette::CXA2025AS[0x30]);
+    assert_eq!(pixel(&ppu, 4, 4), palette::CXA2025AS[0x16]);
+    assert_eq!(pixel(&ppu, 11, 11), palette::CXA2025AS[0x16]);
+    assert_eq!(pixel(&ppu, 12, 12), palette::CXA2025AS[0x0F]);
+    assert_eq!(pixel(&ppu, 8, 0), palette::CXA2025AS[0x0F]);
+
+    // sprites behind the BG are hidden by opaque BG pixels
+    ppu.oam[2] |= 0b0010_0000;
+    while ppu.frame() == 1 {
+        ppu.tick();
+    }
+    assert_eq!(pixel(&ppu, 4, 4), palette::CXA2025AS[0x30]);
+    assert_eq!(pixel(&ppu, 11, 11), palette::CXA2025AS[0x16]);
+}
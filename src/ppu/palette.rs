@@ -2,6 +2,9 @@ pub const PALETTE_COLORS: usize = 64;
 
 // The palettes in this file are taken from the Nestopia project:
 // https://github.com/libretro/nestopia/blob/master/libretro/libretro.cpp
+//
+// The PPU renders with CXA2025AS. The others are here for tools that want
+// to recolor its output.
 
 pub const CXA2025AS: [[u8; 3]; PALETTE_COLORS] = [
     [0x58, 0x58, 0x58],
@@ -0,0 +1,246 @@
+// A ring buffer of recent save states, for playing the game backward.
+//
+// A state is captured every few frames. Only the newest is kept whole; each
+// older one is stored as a delta against the state after it, which is small
+// since little changes between frames. Rewinding takes states off the new
+// end, and the oldest are dropped to stay within the memory budget.
+
+use crate::cpu::Cpu;
+use crate::savestate;
+use std::collections::VecDeque;
+
+pub struct Rewind {
+    // Frames between captures.
+    interval: u32,
+
+    // The most memory that captured states may use, in bytes.
+    budget: usize,
+
+    // Frames since the last capture.
+    frames: u32,
+
+    newest: Option<Vec<u8>>,
+
+    // Older states, oldest first.
+    deltas: VecDeque<Vec<u8>>,
+
+    // Bytes used by newest and deltas.
+    size: usize,
+}
+
+impl Rewind {
+    pub fn new(interval: u32, budget: usize) -> Rewind {
+        Rewind {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    // Call once per emulated frame, to capture a state every interval
+    // frames.
+    pub fn frame(&mut self, cpu: &Cpu) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(savestate::save(cpu));
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            let delta = encode(&newest, &state);
+            self.size += delta.len();
+            self.size -= newest.len();
+            self.deltas.push_back(delta);
+        }
+        self.size += state.len();
+        self.newest = Some(state);
+
+        while self.size > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Removes and returns the newest state. The next capture is a full
+    // interval after this.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.newest.take()?;
+        self.size -= state.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            let prev = decode(&delta, &state);
+            self.size += prev.len();
+            self.size -= delta.len();
+            self.newest = Some(prev);
+        }
+        self.frames = 0;
+        Some(state)
+    }
+
+    // The number of states held.
+    pub fn len(&self) -> usize {
+        self.newest.iter().count() + self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // Memory used by the states, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.newest = None;
+        self.deltas.clear();
+        self.size = 0;
+    }
+}
+
+// Deltas start with a tag. A delta between states of different lengths is
+// just the old state. Otherwise it's a series of runs, each a count of bytes
+// that are the same in both states, then a count of bytes that differ and
+// the old values of those bytes. Counts are LEB128.
+const TAG_FULL: u8 = 0;
+const TAG_RUNS: u8 = 1;
+
+// Encodes old as a delta against new.
+fn encode(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    if old.len() != new.len() {
+        delta.push(TAG_FULL);
+        delta.extend_from_slice(old);
+        return delta;
+    }
+
+    delta.push(TAG_RUNS);
+    let mut i = 0;
+    while i < old.len() {
+        let same = old[i..]
+            .iter()
+            .zip(&new[i..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        i += same;
+        let differ = old[i..]
+            .iter()
+            .zip(&new[i..])
+            .take_while(|(a, b)| a != b)
+            .count();
+        write_count(&mut delta, same);
+        write_count(&mut delta, differ);
+        delta.extend_from_slice(&old[i..i + differ]);
+        i += differ;
+    }
+    delta
+}
+
+// Recovers the old state from a delta made by encode().
+fn decode(delta: &[u8], new: &[u8]) -> Vec<u8> {
+    let (&tag, mut delta) = delta.split_first().expect("empty rewind delta");
+    if tag == TAG_FULL {
+        return delta.to_vec();
+    }
+
+    let mut old = Vec::with_capacity(new.len());
+    while !delta.is_empty() {
+        let same = read_count(&mut delta);
+        let differ = read_count(&mut delta);
+        let start = old.len();
+        old.extend_from_slice(&new[start..start + same]);
+        old.extend_from_slice(&delta[..differ]);
+        delta = &delta[differ..];
+    }
+    old
+}
+
+fn write_count(buf: &mut Vec<u8>, mut n: usize) {
+    loop {
+        let b = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(b);
+            return;
+        }
+        buf.push(b | 0x80);
+    }
+}
+
+fn read_count(buf: &mut &[u8]) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let b = buf[0];
+        *buf = &buf[1..];
+        n |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+#[test]
+fn test_delta() {
+    let new: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+    let mut old = new.clone();
+    old[0] ^= 1;
+    old[500..700].iter_mut().for_each(|b| *b = !*b);
+    old[999] = 0;
+
+    let delta = encode(&old, &new);
+    assert!(delta.len() < 220, "{} bytes", delta.len());
+    assert_eq!(decode(&delta, &new), old);
+
+    assert_eq!(decode(&encode(&new, &new), &new), new);
+    assert_eq!(decode(&encode(&[1, 2], &new), &new), vec![1, 2]);
+}
+
+#[test]
+fn test_ring() {
+    let state = |i: u8| {
+        let mut state = vec![0; 100];
+        state[i as usize] = i;
+        state
+    };
+
+    // 200 bytes is enough for the newest state and a few deltas
+    let mut rewind = Rewind::new(1, 200);
+    for i in 1..=50 {
+        rewind.push(state(i));
+        assert!(rewind.size() <= 200);
+    }
+    assert!(rewind.len() > 2 && rewind.len() < 50);
+
+    let len = rewind.len();
+    for i in 0..len {
+        assert_eq!(rewind.pop(), Some(state(50 - i as u8)));
+    }
+    assert_eq!(rewind.pop(), None);
+    assert_eq!(rewind.size(), 0);
+}
+
+#[test]
+fn test_capture_interval() {
+    let mut cpu = Cpu::new_test();
+    let mut rewind = Rewind::new(3, 1 << 20);
+    for frame in 1..=9 {
+        cpu.regs.a = frame;
+        rewind.frame(&cpu);
+    }
+    assert_eq!(rewind.len(), 3);
+
+    for &a in &[9, 6, 3] {
+        savestate::load(&mut cpu, &rewind.pop().unwrap()).unwrap();
+        assert_eq!(cpu.regs.a, a);
+    }
+    assert!(rewind.is_empty());
+}
@@ -0,0 +1,351 @@
+// Save states: snapshots of the whole machine, which can be restored later
+// to resume from exactly the same point.
+//
+// A state is the magic bytes, a version, then each component's fields in a
+// fixed order, little-endian. Byte arrays are prefixed with their length.
+// There are no field names or tags, so any change to what a component saves
+// needs a new VERSION. ROM isn't saved, so a state can only be loaded into
+// a machine running the same cartridge.
+
+use crate::cpu::Cpu;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8] = b"NESSTATE";
+pub const VERSION: u16 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    TrailingData(usize),
+
+    // The state doesn't fit this machine, e.g. because it was saved with a
+    // different cartridge.
+    Mismatch(&'static str),
+
+    // A field has a value that the component can't be in.
+    Invalid(&'static str),
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::InvalidMagic => write!(f, "not a save state"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported save state version: {}", v),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::TrailingData(len) => write!(f, "{} bytes after the end of the save state", len),
+            Self::Mismatch(what) => write!(f, "save state doesn't match this machine: {}", what),
+            Self::Invalid(what) => write!(f, "invalid save state: {}", what),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+// A component of the machine that can be saved and restored. Loading only
+// restores what saving wrote, so configuration, like which devices are
+// plugged in, comes from the machine being loaded into.
+pub trait State {
+    fn save_state(&self, w: &mut Writer);
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error>;
+}
+
+pub fn save(cpu: &Cpu) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.buf.extend_from_slice(MAGIC);
+    w.u16(VERSION);
+    cpu.save_state(&mut w);
+    w.buf
+}
+
+// Restores a state made by save(). If it fails, the machine is left as it
+// was.
+pub fn load(cpu: &mut Cpu, data: &[u8]) -> Result<(), Error> {
+    let backup = save(cpu);
+    let res = load_unchecked(cpu, data);
+    if res.is_err() {
+        load_unchecked(cpu, &backup).expect("restoring the machine's own state");
+    }
+    res
+}
+
+fn load_unchecked(cpu: &mut Cpu, data: &[u8]) -> Result<(), Error> {
+    if !data.starts_with(MAGIC) {
+        return Err(Error::InvalidMagic);
+    }
+    let mut r = Reader::new(&data[MAGIC.len()..]);
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    cpu.load_state(&mut r)?;
+    match r.remaining() {
+        0 => Ok(()),
+        len => Err(Error::TrailingData(len)),
+    }
+}
+
+pub fn save_file(cpu: &Cpu, path: &Path) -> Result<(), Error> {
+    Ok(fs::write(path, save(cpu))?)
+}
+
+pub fn load_file(cpu: &mut Cpu, path: &Path) -> Result<(), Error> {
+    load(cpu, &fs::read(path)?)
+}
+
+#[derive(Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(Error::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::Invalid("boolean")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, Error> {
+        Ok(self.u64()? as usize)
+    }
+
+    // Reads a byte array into a buffer of the same length. what names the
+    // buffer, in case it doesn't match.
+    pub fn bytes_into(&mut self, buf: &mut [u8], what: &'static str) -> Result<(), Error> {
+        let len = self.u32()? as usize;
+        if len != buf.len() {
+            return Err(Error::Mismatch(what));
+        }
+        buf.copy_from_slice(self.take(len)?);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_reader_writer() {
+    let mut w = Writer::new();
+    w.u8(1);
+    w.bool(true);
+    w.u16(0x0203);
+    w.u32(0x0405_0607);
+    w.u64(u64::MAX);
+    w.bytes(&[8, 9]);
+
+    let mut r = Reader::new(&w.buf);
+    assert_eq!(r.u8().unwrap(), 1);
+    assert!(r.bool().unwrap());
+    assert_eq!(r.u16().unwrap(), 0x0203);
+    assert_eq!(r.u32().unwrap(), 0x0405_0607);
+    assert_eq!(r.u64().unwrap(), u64::MAX);
+    let mut buf = [0; 2];
+    r.bytes_into(&mut buf, "buf").unwrap();
+    assert_eq!(buf, [8, 9]);
+    assert_eq!(r.remaining(), 0);
+    assert!(matches!(r.u8(), Err(Error::Truncated)));
+
+    let mut r = Reader::new(&w.buf[16..]);
+    let mut buf = [0; 3];
+    assert!(matches!(
+        r.bytes_into(&mut buf, "buf"),
+        Err(Error::Mismatch("buf"))
+    ));
+}
+
+#[cfg(test)]
+fn test_cpu() -> Cpu {
+    let mut cpu = Cpu::new_test();
+    let code = crate::cpu::assemble::assemble(
+        "
+        loop: inx
+        stx $2006
+        stx $2007
+        stx $4000
+        stx $4016
+        lda $2002
+        lda $4016
+        sta $0300,x
+        jmp loop
+        ",
+        0x0600,
+    )
+    .unwrap();
+    cpu.mem_write_buf(0x0600, code);
+    cpu.regs.pc = 0x0600;
+    cpu.regs.s = 0xFD;
+    cpu.regs.p = 0x24;
+    cpu
+}
+
+// Running on from a restored state ends up where running on from the saved
+// one did.
+#[test]
+fn test_round_trip() {
+    let mut cpu = test_cpu();
+    let run = |cpu: &mut Cpu| {
+        for _ in 0..20_000 {
+            cpu.step().unwrap();
+        }
+    };
+
+    run(&mut cpu);
+    let saved = save(&cpu);
+    run(&mut cpu);
+    let expected = save(&cpu);
+
+    let mut other = test_cpu();
+    load(&mut other, &saved).unwrap();
+    assert_eq!(save(&other), saved);
+    run(&mut other);
+    assert_eq!(save(&other), expected);
+}
+
+// A loaded state shows the picture it was saved with.
+#[test]
+fn test_framebuffer() {
+    let mut cpu = test_cpu();
+    for (i, px) in cpu.bus.ppu.framebuf.iter_mut().enumerate() {
+        *px = i as u8;
+    }
+    let saved = save(&cpu);
+    let framebuf = cpu.bus.ppu.framebuf.clone();
+
+    cpu.bus.ppu.framebuf.iter_mut().for_each(|px| *px = 0);
+    load(&mut cpu, &saved).unwrap();
+    assert!(cpu.bus.ppu.framebuf == framebuf);
+}
+
+#[test]
+fn test_load_errors() {
+    let mut cpu = test_cpu();
+    let saved = save(&cpu);
+    cpu.regs.a = 0x12;
+    let before = save(&cpu);
+
+    assert!(matches!(
+        load(&mut cpu, b"NES\x1A"),
+        Err(Error::InvalidMagic)
+    ));
+    let mut newer = saved.clone();
+    newer[MAGIC.len()] = 99;
+    assert!(matches!(
+        load(&mut cpu, &newer),
+        Err(Error::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        load(&mut cpu, &saved[..saved.len() - 1]),
+        Err(Error::Truncated)
+    ));
+    let mut longer = saved.clone();
+    longer.push(0);
+    assert!(matches!(
+        load(&mut cpu, &longer),
+        Err(Error::TrailingData(1))
+    ));
+
+    // a different cartridge
+    let (prg, chr) = crate::mapper::nrom::new(
+        &[0; crate::mapper::nrom::PRG_SIZE],
+        &[],
+        crate::mapper::Mirroring::Vertical,
+    );
+    let nrom = Cpu::new(Box::new(prg), Box::new(chr));
+    assert!(matches!(
+        load(&mut cpu, &save(&nrom)),
+        Err(Error::Mismatch(_))
+    ));
+
+    // failed loads leave the machine alone
+    assert_eq!(save(&cpu), before);
+}
@@ -0,0 +1,68 @@
+// Runs Klaus Dormann's 6502 functional test, which exercises every official
+// opcode and address mode, including decimal mode, and traps in a loop at a
+// known address when it passes. Any other trap address identifies the
+// failing check in the test's listing.
+//
+// The binary is GPL-licensed and not distributed with this repository, so the
+// test is ignored by default; see tests/fixtures/README.md. CI fetches it and
+// runs the test. Run it with `cargo test --release -- --ignored`, since it
+// executes tens of millions of instructions.
+
+extern crate nes;
+
+use nes::cpu::{Cpu, RamBus, Variant};
+use std::fs;
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/6502_functional_test.bin"
+);
+
+// Addresses for the prebuilt binary from the test's repository.
+const START: u16 = 0x0400;
+const SUCCESS: u16 = 0x3469;
+
+const INSTRUCTION_LIMIT: u64 = 100_000_000;
+
+#[test]
+#[ignore = "needs tests/fixtures/6502_functional_test.bin, which isn't checked in"]
+fn functional_test() {
+    let bin = fs::read(FIXTURE).unwrap_or_else(|err| {
+        panic!(
+            "cannot read {}: {}; see tests/fixtures/README.md",
+            FIXTURE, err
+        )
+    });
+    assert_eq!(bin.len(), 1 << 16, "expected a 64 KiB memory image");
+
+    let mut bus = RamBus::new();
+    bus.mem.copy_from_slice(&bin);
+    let mut cpu = Cpu::with_bus(bus);
+    cpu.variant = Variant::Nmos6502;
+    cpu.regs.pc = START;
+
+    let trap = run_until_trap(&mut cpu);
+    assert_eq!(
+        trap, SUCCESS,
+        "trapped at {:#06X}, regs: {:?}, cycles: {}",
+        trap, cpu.regs, cpu.cycles
+    );
+}
+
+// Steps until an instruction jumps or branches to itself, and returns its
+// address.
+fn run_until_trap(cpu: &mut Cpu<RamBus>) -> u16 {
+    for _ in 0..INSTRUCTION_LIMIT {
+        let pc = cpu.regs.pc;
+        if let Err(err) = cpu.step() {
+            panic!("{}, regs: {:?}", err, cpu.regs);
+        }
+        if cpu.regs.pc == pc {
+            return pc;
+        }
+    }
+    panic!(
+        "no trap after {} instructions, regs: {:?}",
+        INSTRUCTION_LIMIT, cpu.regs
+    );
+}
@@ -0,0 +1,87 @@
+// Runs the nestest ROM in its automated mode, which starts at $C000 and
+// needs no PPU or controller, and compares the execution trace line by line
+// against the reference log. The ROM stores result codes at $02 and $03,
+// which are zero when every check passes.
+//
+// The ROM and log come with no license and are not distributed with this
+// repository, so the test is ignored by default; see tests/fixtures/README.md.
+// CI fetches them and runs the test. Run it with `cargo test -- --ignored`.
+
+extern crate nes;
+
+use nes::cpu::{Cpu, Trace};
+use nes::ines;
+use std::fs;
+use std::io;
+
+const ROM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/nestest.nes");
+const LOG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/nestest.log");
+
+const START: u16 = 0xC000;
+
+// Lines of context to show around a divergence.
+const CONTEXT: usize = 5;
+
+#[test]
+#[ignore = "needs tests/fixtures/nestest.nes and nestest.log, which aren't checked in"]
+fn nestest() {
+    let rom = fs::read(ROM).unwrap_or_else(|err| read_error(ROM, err));
+    let log = fs::read_to_string(LOG).unwrap_or_else(|err| read_error(LOG, err));
+    let expected: Vec<&str> = log.lines().map(|line| line.trim_end()).collect();
+
+    let rom = ines::parse(&rom).unwrap();
+    let (prg, chr) = rom.mapper().unwrap();
+    let mut cpu = Cpu::new(prg, chr);
+
+    // The reset sequence leaves the PPU and cycle count where the log
+    // starts. The log's register values are those of a power-on.
+    cpu.reset();
+    cpu.regs.pc = START;
+    cpu.regs.s = 0xFD;
+    cpu.regs.p = 0x24;
+    cpu.trace = Some(Trace::Buffer(Vec::new()));
+
+    for i in 0..expected.len() {
+        let res = cpu.step();
+        let actual = match &cpu.trace {
+            Some(Trace::Buffer(lines)) => lines,
+            _ => unreachable!(),
+        };
+        if actual.get(i).map(String::as_str) != Some(expected[i]) || res.is_err() {
+            panic!("{}", divergence(&expected, actual, i, res.err()));
+        }
+    }
+
+    assert_eq!(
+        (cpu.mem_read(0x02), cpu.mem_read(0x03)),
+        (0, 0),
+        "result codes at $02 and $03"
+    );
+}
+
+fn read_error(path: &str, err: io::Error) -> ! {
+    panic!(
+        "cannot read {}: {}; see tests/fixtures/README.md",
+        path, err
+    )
+}
+
+fn divergence(
+    expected: &[&str],
+    actual: &[String],
+    i: usize,
+    err: Option<nes::cpu::CpuError>,
+) -> String {
+    let start = i.saturating_sub(CONTEXT);
+    let mut msg = format!("trace diverges at line {}\n", i + 1);
+    for (n, line) in expected.iter().enumerate().take(i + 1).skip(start) {
+        msg += &format!("  expected {:5}: {}\n", n + 1, line);
+    }
+    for (n, line) in actual.iter().enumerate().take(i + 1).skip(start) {
+        msg += &format!("  actual   {:5}: {}\n", n + 1, line);
+    }
+    if let Some(err) = err {
+        msg += &format!("  error: {}\n", err);
+    }
+    msg
+}